@@ -0,0 +1,13 @@
+// Generates the protobuf types from `src/inet/chess.proto` into OUT_DIR,
+// they are included by the `inet::items` module
+fn main()
+{
+    // Use the protoc binary shipped with `protoc-bin-vendored` so that
+    // building does not depend on a system wide protobuf install
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()
+                                    .expect("NO VENDORED PROTOC FOR THIS PLATFORM"));
+
+    println!("cargo:rerun-if-changed=src/inet/chess.proto");
+    prost_build::compile_protos(&["src/inet/chess.proto"], &["src/inet/"])
+        .expect("COULD NOT COMPILE chess.proto");
+}
@@ -0,0 +1,89 @@
+use std::time::{Duration, Instant};
+
+use chess::colors::Colors;
+use chess::piece_types::PieceTypes;
+
+use crate::position::{Squares, square_to_block};
+
+// A move being animated: the moving pieces slide from their old block to the
+// new one while captured pieces fade out. Worked out by comparing the board
+// before and after the move, so moves from clicks, the network and castling
+// (king and rook) are all animated the same way.
+// Blocks are numbered like `ChessView::block_pos`, 0 = a8, 63 = h1
+#[derive(Clone)]
+pub struct Animation
+{
+    started: Instant,
+    duration: Duration,
+    // from block, to block, piece
+    pub movers: Vec<(u8, u8, (PieceTypes, Colors))>,
+    // block, piece
+    pub captured: Vec<(u8, (PieceTypes, Colors))>,
+}
+
+impl Animation
+{
+    // `None` if nothing moved. A normal move changes 2 squares, castling and
+    // en passant 3 or 4, anything more is a new or loaded position and is
+    // shown right away
+    pub fn between(before: &Squares, after: &Squares, duration: Duration) -> Option<Animation>
+    {
+        let changed: Vec<usize> = (0..64).filter(|s| before[*s] != after[*s]).collect();
+        if changed.is_empty() || changed.len() > 4 || duration.is_zero() { return None; }
+
+        // Squares a piece left
+        let mut departures: Vec<usize> = changed.iter().cloned()
+            .filter(|s| before[*s].is_some() && after[*s].is_none())
+            .collect();
+
+        let mut movers: Vec<(u8, u8, (PieceTypes, Colors))> = Vec::new();
+        let mut captured: Vec<(u8, (PieceTypes, Colors))> = Vec::new();
+
+        for to in changed.iter().cloned()
+        {
+            let piece: (PieceTypes, Colors) = match after[to] { Some(piece) => piece, None => continue };
+
+            // The same piece, or a pawn of the same color that got promoted
+            let from: Option<usize> = departures.iter().position(|s| before[*s] == Some(piece))
+                .or_else(|| departures.iter().position(|s| before[*s].map(|p| p.1) == Some(piece.1)));
+
+            if let Some(i) = from
+            {
+                movers.push((square_to_block(departures[i] as u32), square_to_block(to as u32), piece));
+                departures.remove(i);
+            }
+            if let Some(taken) = before[to]
+            {
+                captured.push((square_to_block(to as u32), taken));
+            }
+        }
+
+        // Left without anything arriving, a pawn taken en passant
+        for s in departures
+        {
+            if let Some(taken) = before[s] { captured.push((square_to_block(s as u32), taken)); }
+        }
+
+        if movers.is_empty() { return None; }
+        Some(Animation{started: Instant::now(), duration, movers, captured})
+    }
+
+    // 0.0 at the start, 1.0 once done. Eased out, so pieces slow down
+    // before landing
+    pub fn progress(&self) -> f64
+    {
+        let t: f64 = (self.started.elapsed().as_secs_f64() / self.duration.as_secs_f64()).min(1.0);
+        1.0 - (1.0 - t) * (1.0 - t)
+    }
+
+    pub fn finished(&self) -> bool
+    {
+        self.started.elapsed() >= self.duration
+    }
+
+    // Blocks the board shouldn't draw a piece on, the animation draws it
+    pub fn covers(&self, block: u8) -> bool
+    {
+        self.movers.iter().any(|(_, to, _)| *to == block)
+    }
+}
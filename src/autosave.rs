@@ -0,0 +1,230 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use chess::colors::Colors;
+use json::JsonValue;
+
+use crate::chessview_controller::GameMode;
+use crate::game::Game;
+use crate::inet::discovery::DiscoveredGame;
+use crate::inet::transport::TransportKind;
+
+// The game in progress, written to the user's data directory after every
+// move and on exit, offered as "Continue" on the intro screen. Looks like
+// {"mode": "computer", "computer-color": "black", "flipped": false, "pgn": "..."}
+// or for MP {"mode": "online", "server": "host:port", "transport": "tcp",
+// "game-id": 3, "spectate": false}
+const SAVE_FILE: &str = "autosave.json";
+
+pub struct SavedGame
+{
+    pub mode: GameMode,
+    // The moves and annotations, empty for MP games, the server has them
+    pub game: Game,
+    pub flipped: bool,
+    // The server of an MP game and whether we were spectating
+    pub server: Option<(DiscoveredGame, bool)>,
+}
+
+impl SavedGame
+{
+    fn _to_json(&self) -> JsonValue
+    {
+        let mut saved: JsonValue = json::object!{"flipped": self.flipped};
+        match self.mode
+        {
+            GameMode::Local => saved["mode"] = "local".into(),
+            GameMode::Analysis => saved["mode"] = "analysis".into(),
+            GameMode::Computer(color) => {
+                saved["mode"] = "computer".into();
+                saved["computer-color"] = if color == Colors::White { "white" } else { "black" }.into();
+            },
+            GameMode::Online => saved["mode"] = "online".into(),
+        }
+
+        match &self.server
+        {
+            Some((game, spectate)) => {
+                saved["server"] = game.ip_port.as_str().into();
+                saved["transport"] = game.transport.name().into();
+                saved["game-id"] = game.game_id.into();
+                saved["spectate"] = (*spectate).into();
+            },
+            None => saved["pgn"] = self.game.to_pgn().into(),
+        }
+        saved
+    }
+
+    fn _from_json(saved: &JsonValue) -> Result<SavedGame, String>
+    {
+        let mode: GameMode = match saved["mode"].as_str()
+        {
+            Some("local") => GameMode::Local,
+            Some("analysis") => GameMode::Analysis,
+            Some("computer") => match saved["computer-color"].as_str()
+            {
+                Some("white") => GameMode::Computer(Colors::White),
+                Some("black") => GameMode::Computer(Colors::Black),
+                _ => return Err(String::from("\"computer-color\" must be \"white\" or \"black\"")),
+            },
+            Some("online") => GameMode::Online,
+            _ => return Err(String::from("unknown \"mode\"")),
+        };
+
+        let server: Option<(DiscoveredGame, bool)> = if mode == GameMode::Online
+        {
+            let address: &str = saved["server"].as_str().ok_or("no \"server\"")?;
+            let game: DiscoveredGame = DiscoveredGame{
+                name: address.to_string(),
+                ip_port: address.to_string(),
+                transport: TransportKind::from_config(saved["transport"].as_str().unwrap_or("tcp"))?,
+                game_id: saved["game-id"].as_u64().ok_or("no \"game-id\"")?,
+                players: 0,
+                spectators: 0,
+            };
+            Some((game, saved["spectate"].as_bool().unwrap_or(false)))
+        }
+        else { None };
+
+        let game: Game = match (&server, saved["pgn"].as_str())
+        {
+            (Some(_), _) => Game::new(),
+            (None, Some(pgn)) => Game::from_pgn(pgn)?,
+            (None, None) => return Err(String::from("no \"pgn\"")),
+        };
+
+        Ok(SavedGame{mode, game, flipped: saved["flipped"].as_bool().unwrap_or(false), server})
+    }
+}
+
+// A file of ours in the user's data directory, e.g.
+// ~/.local/share/chess-gui/autosave.json. `None` if the system has none
+pub fn data_file(name: &str) -> Option<PathBuf>
+{
+    dirs::data_dir().map(|dir| dir.join("chess-gui").join(name))
+}
+
+// Same, and creates the directory so the file can be written
+pub fn writable_data_file(name: &str) -> Result<PathBuf, String>
+{
+    let path: PathBuf = data_file(name).ok_or("no data directory to save in")?;
+    if let Some(dir) = path.parent()
+    {
+        fs::create_dir_all(dir).map_err(|err| format!("cannot create {}: {}", dir.display(), err))?;
+    }
+    Ok(path)
+}
+
+pub fn save_path() -> Option<PathBuf>
+{
+    data_file(SAVE_FILE)
+}
+
+pub fn save(saved: &SavedGame) -> Result<(), String>
+{
+    let path: PathBuf = writable_data_file(SAVE_FILE)?;
+    fs::write(&path, saved._to_json().pretty(4))
+        .map_err(|err| format!("cannot write {}: {}", path.display(), err))
+}
+
+// Forget the save, e.g. once its game is over
+pub fn remove()
+{
+    if let Some(path) = save_path()
+    {
+        if let Err(err) = fs::remove_file(&path)
+        {
+            if err.kind() != io::ErrorKind::NotFound { eprintln!("Could not remove {}: {}", path.display(), err); }
+        }
+    }
+}
+
+// The saved game, `None` if there is none
+pub fn load() -> Result<Option<SavedGame>, String>
+{
+    let path: PathBuf = match save_path() { Some(path) => path, None => return Ok(None) };
+    let text: String = match fs::read_to_string(&path)
+    {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(format!("cannot read {}: {}", path.display(), err)),
+    };
+
+    let saved: JsonValue = json::parse(&text)
+        .map_err(|err| format!("{} is not valid json: {}", path.display(), err))?;
+    SavedGame::_from_json(&saved)
+        .map(Some)
+        .map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn _round_trip(saved: &SavedGame) -> SavedGame
+    {
+        SavedGame::_from_json(&json::parse(&saved._to_json().dump()).unwrap()).unwrap()
+    }
+
+    // Why `saved` can't be read, empty if it can
+    fn _error(saved: &str) -> String
+    {
+        SavedGame::_from_json(&json::parse(saved).unwrap()).err().unwrap_or_default()
+    }
+
+    #[test]
+    fn games_round_trip()
+    {
+        let game: Game = Game::from_pgn("1. e4 e5 2. Nf3 {[%csl Re5]} *").unwrap();
+        for mode in [GameMode::Local, GameMode::Analysis, GameMode::Computer(Colors::White), GameMode::Computer(Colors::Black)]
+        {
+            let saved: SavedGame = _round_trip(&SavedGame{mode, game: game.clone(), flipped: true, server: None});
+            assert!(saved.mode == mode);
+            assert!(saved.flipped);
+            assert!(saved.server.is_none());
+            assert_eq!(saved.game.to_pgn(), game.to_pgn());
+        }
+    }
+
+    #[test]
+    fn online_games_keep_the_server()
+    {
+        let server: DiscoveredGame = DiscoveredGame{
+            name: String::from("LAN game"),
+            ip_port: String::from("192.168.1.5:1337"),
+            transport: TransportKind::WebSocket,
+            game_id: 3,
+            players: 2,
+            spectators: 1,
+        };
+        let saved: SavedGame = _round_trip(&SavedGame{
+            mode: GameMode::Online,
+            game: Game::from_pgn("1. d4 *").unwrap(),
+            flipped: false,
+            server: Some((server, true)),
+        });
+
+        assert!(saved.mode == GameMode::Online);
+        // The server has the moves
+        assert!(saved.game.line().is_empty());
+        let (game, spectate) = saved.server.unwrap();
+        assert_eq!((game.ip_port.as_str(), game.transport, game.game_id, spectate),
+                   ("192.168.1.5:1337", TransportKind::WebSocket, 3, true));
+    }
+
+    #[test]
+    fn broken_saves()
+    {
+        assert_eq!(_error(r#"{"mode": "blitz", "pgn": "*"}"#), "unknown \"mode\"");
+        assert_eq!(_error(r#"{"mode": "computer", "pgn": "*"}"#), "\"computer-color\" must be \"white\" or \"black\"");
+        assert_eq!(_error(r#"{"mode": "local"}"#), "no \"pgn\"");
+        assert_eq!(_error(r#"{"mode": "online", "game-id": 1}"#), "no \"server\"");
+        assert_eq!(_error(r#"{"mode": "online", "server": "host:1337"}"#), "no \"game-id\"");
+        assert!(!_error(r#"{"mode": "local", "pgn": "1. e5 *"}"#).is_empty());
+
+        // Only the mode and the game are needed
+        assert_eq!(_error(r#"{"mode": "analysis", "pgn": "1. e4 *"}"#), "");
+    }
+}
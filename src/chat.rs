@@ -0,0 +1,269 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Longest chat line (in chars) that can be sent, longer input is not accepted
+pub const MAX_CHAT_LEN: usize = 200;
+
+// At most `RATE_LIMIT_COUNT` messages can be sent during `RATE_LIMIT_WINDOW`
+const RATE_LIMIT_COUNT: usize = 3;
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(5);
+
+// How many lines are kept in the history before the oldest ones are dropped
+const MAX_HISTORY: usize = 200;
+
+#[derive(Clone)]
+pub struct ChatLine
+{
+    pub sender: String,
+    pub text: String,
+}
+
+// Chat state of an MP game: the received history, the line being typed
+// and the timestamps used for rate limiting the outgoing messages
+pub struct Chat
+{
+    pub history: Vec<ChatLine>,
+    // The history as drawn, wrapped to the width of the panel, see `wrap`
+    pub lines: Vec<String>,
+    // Messages pushed since the last `wrap`
+    unwrapped: usize,
+    // The width and font `lines` were wrapped for, they're only wrapped
+    // again when one of them changes or a message arrives
+    wrapped_for: Option<(f64, String)>,
+    pub input: String,
+    pub open: bool,
+    // Number of wrapped lines scrolled up from the newest one
+    pub scroll: usize,
+    // Shown in place of the input when a message could not be sent
+    pub notice: Option<String>,
+    rate_limit: RateLimit,
+}
+
+impl Chat
+{
+    pub fn new() -> Chat
+    {
+        Chat{history: Vec::new(), lines: Vec::new(), unwrapped: 0, wrapped_for: None, input: String::new(), open: false,
+            scroll: 0, notice: None, rate_limit: RateLimit::new()}
+    }
+
+    // Add a relayed message to the history
+    pub fn push(&mut self, sender: &str, text: &str)
+    {
+        self.history.push(ChatLine{sender: sender.to_string(), text: text.to_string()});
+
+        if self.history.len() > MAX_HISTORY
+        {
+            self.history.remove(0);
+        }
+
+        self.unwrapped = (self.unwrapped + 1).min(self.history.len());
+    }
+
+    // Wrap the history into `lines` no wider than `max_width`, as measured
+    // by `width` with `font`. Called before drawing, the font is needed for
+    // measuring. Does nothing if neither they nor the history changed
+    pub fn wrap(&mut self, max_width: f64, font: &str, mut width: impl FnMut(&str) -> f64)
+    {
+        let wrapped_for: (f64, String) = (max_width, font.to_string());
+        if self.unwrapped == 0 && self.wrapped_for.as_ref() == Some(&wrapped_for) { return; }
+        self.wrapped_for = Some(wrapped_for);
+
+        let mut lines: Vec<String> = Vec::new();
+        let mut new_lines: usize = 0;
+        let first_new: usize = self.history.len() - self.unwrapped;
+        for (i, line) in self.history.iter().enumerate()
+        {
+            let wrapped: Vec<String> = wrap_text(&format!("{}: {}", line.sender, line.text), max_width, &mut width);
+            if i >= first_new { new_lines += wrapped.len(); }
+            lines.extend(wrapped);
+        }
+
+        // Keep the view where it was if the user has scrolled up
+        if self.scroll > 0 { self.scroll += new_lines; }
+        self.unwrapped = 0;
+        self.lines = lines;
+        self.scroll = self.scroll.min(self.lines.len().saturating_sub(1));
+    }
+
+    // Append typed text (from piston text events) to the input line
+    pub fn type_text(&mut self, text: &str)
+    {
+        self.notice = None;
+        for ch in text.chars().filter(|ch| !ch.is_control())
+        {
+            if self.input.chars().count() >= MAX_CHAT_LEN { break; }
+            self.input.push(ch);
+        }
+    }
+
+    pub fn backspace(&mut self)
+    {
+        self.input.pop();
+    }
+
+    // Scroll the history, positive `lines` moves towards older messages
+    pub fn scroll_by(&mut self, lines: i32)
+    {
+        let max_scroll: i64 = self.lines.len().saturating_sub(1) as i64;
+        self.scroll = (self.scroll as i64 + lines as i64).clamp(0, max_scroll) as usize;
+    }
+
+    // Take the typed line out of the input if it is allowed to be sent.
+    // Returns `None` for an empty line or when the rate limit was hit
+    pub fn take_message(&mut self) -> Option<String>
+    {
+        let text: String = self.input.trim().to_string();
+        if text.is_empty() { return None; }
+
+        if !self.rate_limit.allow()
+        {
+            self.notice = Some("Slow down! Too many messages".to_string());
+            return None;
+        }
+
+        self.input.clear();
+        self.scroll = 0;
+        Some(text)
+    }
+}
+
+// At most `RATE_LIMIT_COUNT` messages during `RATE_LIMIT_WINDOW`, for the
+// messages typed here and, on a hosting GUI, for each client's
+pub struct RateLimit
+{
+    sent_at: VecDeque<Instant>,
+}
+
+impl RateLimit
+{
+    pub fn new() -> RateLimit
+    {
+        RateLimit{sent_at: VecDeque::new()}
+    }
+
+    // Whether one more message may go now, counts it if so
+    pub fn allow(&mut self) -> bool
+    {
+        let now: Instant = Instant::now();
+        while let Some(sent) = self.sent_at.front()
+        {
+            if now.duration_since(*sent) < RATE_LIMIT_WINDOW { break; }
+            self.sent_at.pop_front();
+        }
+
+        if self.sent_at.len() >= RATE_LIMIT_COUNT { return false; }
+        self.sent_at.push_back(now);
+        true
+    }
+}
+
+// Split `text` at spaces into lines no wider than `max_width`, a single
+// word wider than that gets a line of its own
+pub fn wrap_text(text: &str, max_width: f64, mut width: impl FnMut(&str) -> f64) -> Vec<String>
+{
+    let mut lines: Vec<String> = Vec::new();
+    let mut current: String = String::new();
+    for word in text.split(' ')
+    {
+        let candidate: String = if current.is_empty() { word.to_string() }
+                                else { format!("{} {}", current, word) };
+
+        if !current.is_empty() && width(&candidate) > max_width
+        {
+            lines.push(current);
+            current = word.to_string();
+        }
+        else
+        {
+            current = candidate;
+        }
+    }
+    lines.push(current);
+    lines
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // Every char is one unit wide
+    fn _chars(text: &str) -> f64
+    {
+        text.chars().count() as f64
+    }
+
+    #[test]
+    fn wrap_text_breaks_at_spaces()
+    {
+        assert_eq!(wrap_text("aaa bbb ccc", 7.0, _chars), vec!["aaa bbb", "ccc"]);
+        assert_eq!(wrap_text("aaaaaaaaaa b", 4.0, _chars), vec!["aaaaaaaaaa", "b"]);
+        assert_eq!(wrap_text("", 4.0, _chars), vec![""]);
+    }
+
+    #[test]
+    fn scroll_counts_wrapped_lines()
+    {
+        let mut chat: Chat = Chat::new();
+        chat.push("W", "one two three four");
+        chat.push("B", "hi");
+        chat.wrap(9.0, "font", _chars);
+        assert_eq!(chat.lines, vec!["W: one", "two three", "four", "B: hi"]);
+
+        chat.scroll_by(100);
+        assert_eq!(chat.scroll, 3);
+        chat.scroll_by(-2);
+        assert_eq!(chat.scroll, 1);
+
+        // A long message arriving keeps the same lines in view
+        chat.push("W", "five six seven");
+        chat.wrap(9.0, "font", _chars);
+        assert_eq!(chat.scroll, 3);
+        assert_eq!(chat.lines[chat.lines.len() - 1 - chat.scroll], "four");
+    }
+
+    #[test]
+    fn newest_line_stays_at_the_bottom()
+    {
+        let mut chat: Chat = Chat::new();
+        chat.push("W", "a long message that wraps over lines");
+        chat.wrap(9.0, "font", _chars);
+        assert_eq!(chat.scroll, 0);
+        assert_eq!(chat.lines.last().map(String::as_str), Some("lines"));
+    }
+
+    // Wrap `chat`, returns how many texts were measured
+    fn _measured(chat: &mut Chat, max_width: f64, font: &str) -> usize
+    {
+        let mut measured: usize = 0;
+        chat.wrap(max_width, font, |text| { measured += 1; _chars(text) });
+        measured
+    }
+
+    #[test]
+    fn wrap_only_when_something_changed()
+    {
+        let mut chat: Chat = Chat::new();
+        chat.push("W", "one two three four");
+        assert!(_measured(&mut chat, 9.0, "font") > 0);
+        assert_eq!(_measured(&mut chat, 9.0, "font"), 0);
+
+        // Another width, another font or a new message
+        assert!(_measured(&mut chat, 12.0, "font") > 0);
+        assert_eq!(chat.lines, vec!["W: one two", "three four"]);
+        assert!(_measured(&mut chat, 12.0, "bigger font") > 0);
+        chat.push("B", "hi");
+        assert!(_measured(&mut chat, 12.0, "bigger font") > 0);
+        assert_eq!(chat.lines.last().map(String::as_str), Some("B: hi"));
+        assert_eq!(_measured(&mut chat, 12.0, "bigger font"), 0);
+    }
+
+    #[test]
+    fn rate_limit()
+    {
+        let mut limit: RateLimit = RateLimit::new();
+        assert!((0..RATE_LIMIT_COUNT).all(|_| limit.allow()));
+        assert!(!limit.allow());
+    }
+}
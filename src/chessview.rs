@@ -3,157 +3,147 @@ use std::collections::HashMap;
 use chess::colors::Colors;
 use chess::piece_types::PieceTypes;
 use graphics::types::Color;
-use opengl_graphics::{Texture, GlyphCache};
-use graphics::{Transformed, Image, Rectangle, Context, Graphics};
+use opengl_graphics::{Texture, GlyphCache, GlGraphics};
+use graphics::{Transformed, Image, Rectangle, Ellipse, Line, Context, Graphics, Text};
+use graphics::ellipse;
+use graphics::character::CharacterCache;
 use graphics::rectangle::square;
 
 use chess::chess_engine::*;
 
-use crate::ChessViewController;
-use crate::chessview_controller::GameState;
+use crate::{ChessViewController, W_WIDTH, W_HEIGHT};
+use crate::chessview_controller::GameMode;
+use crate::position;
+use crate::animation::Animation;
+use crate::game::{Game, Annotations};
+use crate::chat::Chat;
 
+// Around the text of the chat panel
+const CHAT_PADDING: f64 = 6.0;
 
 #[derive(Clone)]
 pub struct ChessView
 {
     pub board: ChessEngine,
     pub settings: ChessViewSettings,
+    pub layout: ViewLayout,
+    // Board seen from black's side, rank 1 at the top
+    pub flipped: bool,
+    // The last move while it's being animated
+    pub animation: Option<Animation>,
+    // Moves played on `board` and the arrows/circles drawn on them
+    pub game: Game,
+    // Shown instead of `board`, e.g. a diagram set up from a FEN
+    pub position: Option<position::Squares>,
 }
 
 impl ChessView
 {
     pub fn new(settings: ChessViewSettings) -> ChessView
     { 
-        ChessView{board: ChessEngine::new(), settings: settings}
+        let layout: ViewLayout = ViewLayout::fit(&settings, [W_WIDTH as f64, W_HEIGHT as f64],
+                                                 [W_WIDTH as f64, W_HEIGHT as f64]);
+        ChessView{board: ChessEngine::new(), settings, layout, flipped: false, animation: None,
+                  game: Game::new(), position: None}
     }
 
-
-    pub fn draw<G: Graphics<Texture = Texture>>(&self, glyph: &mut GlyphCache,
-                            textures: &HashMap<(PieceTypes, Colors), Texture>, 
-                            controller: &ChessViewController, 
-                            c: &Context, g: &mut G)
+    // The pieces drawn on the board
+    pub fn squares(&self) -> position::Squares
     {
+        self.position.unwrap_or_else(|| position::snapshot(&self.board))
+    }
 
-        // Run the right draw function based on the game state
-        match controller.state
-        {
-            GameState::Intro => {
-                self._draw_intro(glyph, c, g);
-            },
-            GameState::SPIngame => {
-                self._draw_sp(textures, controller, c, g);
-            },
-            GameState::MPIngame => {
-                self._draw_mp(glyph, textures, controller, c, g);
-            },
-            _ => ()
-        }
-
-
+    // Top left corner of block `i` (0 = a8, 63 = h1, the order of `get_board`)
+    // in view coordinates
+    pub fn block_pos(&self, i: u8) -> [f64; 2]
+    {
+        let drawn: u8 = if self.flipped { 63 - i } else { i };
+        let block_size: f64 = self.settings._size / 8.0;
+        [
+            self.settings.board_pos()[0] + (drawn%8) as f64 * block_size,
+            self.settings.board_pos()[1] + (drawn/8) as f64 * block_size
+        ]
+    }
 
+    // True while a move is animated, the event loop then has to keep
+    // rendering frames
+    pub fn is_animating(&self) -> bool
+    {
+        self.animation.is_some()
     }
 
-    // Draws the game intro (SP & MP buttons + title)
-    fn _draw_intro<G: Graphics<Texture = Texture>>(&self, 
-        glyph: &mut GlyphCache, 
-        c: &Context, g: &mut G)
+    // Forget the animation once a frame showing its end was drawn
+    pub fn end_finished_animation(&mut self, drawn_finished: bool)
     {
-        let main_bg_rect: [f64; 4] = [
-            0.0,
-            0.0,
-            self.settings._size + 2.0*self.settings._pos[0],
-            self.settings._size + 2.0*self.settings._pos[1]
-        ];
+        if drawn_finished { self.animation = None; }
+    }
 
-        let main_bg_color: [f32; 4] = [0.4,0.4,0.4, 1.0];
+    // Recompute the layout for a new window size. `window_size` is in
+    // logical pixels, `draw_size` in physical ones (larger on HiDPI screens)
+    pub fn fit(&mut self, window_size: [f64; 2], draw_size: [f64; 2])
+    {
+        self.layout = ViewLayout::fit(&self.settings, window_size, draw_size);
+    }
 
-        Rectangle::new(main_bg_color).draw(
-            main_bg_rect,
-            &c.draw_state,
-            c.transform,
-            g
-        );
 
-        let _total_width: f64 = self.settings._size + 2.0*self.settings._pos[0];
-        let _total_height: f64 = self.settings._size + 2.0*self.settings._pos[1];
+    // Draws the screens of the controller's stack, see screen.rs
+    pub fn draw(&self, glyph: &mut GlyphCache,
+                            textures: &HashMap<(PieceTypes, Colors), Texture>, 
+                            controller: &ChessViewController, 
+                            c: &Context, g: &mut GlGraphics)
+    {
+        let c: &Context = &self._view_context(c);
+        controller.screens.draw(self, glyph, textures, controller, c, g);
+    }
 
-        // Graphics context transform to try and center the text
-        let text_transform: [[f64; 3]; 2] = c.transform.trans(
-            self.settings._intro_text_coords[0],
-            self.settings._intro_text_coords[1]);
+    // Only the board and what's around it (captured pieces, coordinates),
+    // whatever the game state. For drawing without a window, see render.rs
+    pub fn draw_diagram<G: Graphics, C: CharacterCache<Texture = G::Texture>>(&self, glyph: &mut C,
+                            textures: &HashMap<(PieceTypes, Colors), G::Texture>,
+                            controller: &ChessViewController,
+                            c: &Context, g: &mut G)
+    {
+        let c: &Context = &self._view_context(c);
+        self._draw_board(glyph, textures, controller, c, g);
+    }
 
-        // Draw the main intro text
-        graphics::text::Text::new_color([1.0; 4], 50)
-            .draw(
-                "Chess 2", 
-                glyph, 
-                &c.draw_state, 
-                text_transform, 
-            g).unwrap();
-
-
-        // Coord and size struct for the SP and MP buttons
-        let button1_data: [f64; 4] = [
-            self.settings._intro_sp_button_pos[0],
-            self.settings._intro_sp_button_pos[1],
-            self.settings._intro_button_size[0],
-            self.settings._intro_button_size[1]
-        ];
+    // Everything is drawn in view coordinates (the layout from the
+    // settings), scaled and centered to fit the window
+    fn _view_context(&self, c: &Context) -> Context
+    {
+        Context{
+            transform: c.transform.trans(self.layout.offset[0], self.layout.offset[1])
+                                  .zoom(self.layout.scale),
+            ..*c
+        }
+    }
 
-        let button2_data: [f64; 4] = [
-            self.settings._intro_mp_button_pos[0],
-            self.settings._intro_mp_button_pos[1],
-            self.settings._intro_button_size[0],
-            self.settings._intro_button_size[1]
-        ];
-        // Draw the buttons
-        Rectangle::new(self.settings._intro_button_col).draw(
-            button1_data,
-            &c.draw_state,
-            c.transform,
-            g  
-        );
-        Rectangle::new(self.settings._intro_button_col).draw(
-            button2_data,
+    // Fills the whole window with the background color, behind the menus
+    pub fn draw_background<G: Graphics>(&self, c: &Context, g: &mut G)
+    {
+        Rectangle::new(self.settings._bg_col).draw(
+            self.layout.visible_rect(),
             &c.draw_state,
             c.transform,
-            g  
+            g
         );
+    }
 
-        // Draw text "SP" and "MP" on the buttons
-        let text_sp_transform: [[f64; 3]; 2] = c.transform.trans(
-            self.settings._intro_sp_button_pos[0]+50.0,
-            self.settings._intro_sp_button_pos[1]+37.0);
-        let text_mp_transform: [[f64; 3]; 2] = c.transform.trans(
-            self.settings._intro_mp_button_pos[0]+45.0,
-            self.settings._intro_mp_button_pos[1]+37.0
-        );
-        graphics::text::Text::new_color([1.0; 4], 30)
-        .draw(
-            "SP", 
-            glyph, 
-            &c.draw_state, 
-            text_sp_transform, 
-        g).unwrap();
-
-        graphics::text::Text::new_color([1.0; 4], 30)
-        .draw(
-            "MP", 
-            glyph, 
-            &c.draw_state, 
-            text_mp_transform, 
-        g).unwrap();
-    }
-
-    fn _draw_sp<G: Graphics<Texture = Texture>>(&self, 
+    pub fn draw_sp<G: Graphics<Texture = Texture>>(&self, 
+        glyph: &mut GlyphCache,
         textures: &HashMap<(PieceTypes, Colors), Texture>, 
         controller: &ChessViewController, 
         c: &Context, g: &mut G)
     {
-        self._draw_board(textures, controller, c, g);
+        self._draw_board(glyph, textures, controller, c, g);
+
+        if controller.move_list_open && controller.mode == GameMode::Analysis
+        {
+            self._draw_move_list(glyph, c, g);
+        }
     }
 
-    fn _draw_mp<G: Graphics<Texture = Texture>>(&self,
+    pub fn draw_mp<G: Graphics<Texture = Texture>>(&self,
         glyph: &mut GlyphCache,
         textures: &HashMap<(PieceTypes, Colors), Texture>, 
         controller: &ChessViewController, 
@@ -161,70 +151,266 @@ impl ChessView
     {
         if !controller.mp_connection
         {
-            let main_bg_rect: [f64; 4] = [
-                0.0,
-                0.0,
-                self.settings._size+2.0*self.settings._pos[0],
-                self.settings._size+2.0*self.settings._pos[1]
-            ];
-
             // Draw the gray bg
-            Rectangle::new([0.4, 0.4, 0.4, 1.0]).draw(
-                main_bg_rect,
+            Rectangle::new(self.settings._bg_col).draw(
+                self.layout.visible_rect(),
                 &c.draw_state,
                 c.transform,
                 g
             );
 
-            // Matrix for positioning the "waiting for connection text"
-            let text_transform: [[f64; 3]; 2] = c.transform.trans(
-                120.0, 250.0
-            );
+            // The status can span several lines (e.g. a refused connection
+            // and its reason), the first one is drawn bigger. Every line is
+            // centered horizontally
+            let mut y: f64 = 250.0;
+            for (i, line) in controller.mp_status.lines().enumerate()
+            {
+                let font_size: u32 = if i == 0 { 30 } else { 18 };
+                let width: f64 = self.text_width(glyph, font_size, line);
 
-            graphics::text::Text::new_color([1.0; 4], 30)
-            .draw(
-                "Waiting for connection..", 
-                glyph, 
-                &c.draw_state, 
-                text_transform, 
-            g).unwrap();
+                self.draw_text(Text::new_color([1.0; 4], font_size), line,
+                    [(self.layout.view_size[0] - width) / 2.0, y], glyph, c, g);
+
+                y += font_size as f64 + 12.0;
+            }
 
             return; 
         }
 
-        self._draw_board(textures, controller, c, g);
+        self._draw_board(glyph, textures, controller, c, g);
+
+        if controller.chat.open
+        {
+            self._draw_chat(glyph, controller, c, g);
+        }
+    }
+
+    // Draws the chat panel on top of the lower part of the board:
+    // the message history (newest at the bottom) and the input line
+    fn _draw_chat<G: Graphics<Texture = Texture>>(&self,
+        glyph: &mut GlyphCache,
+        controller: &ChessViewController,
+        c: &Context, g: &mut G)
+    {
+        let chat_rect: [f64; 4] = self.settings.chat_rect();
+        let font_size: u32 = self.settings._chat_font_size;
+        let line_height: f64 = font_size as f64 + 6.0;
+        let padding: f64 = CHAT_PADDING;
+
+        Rectangle::new(self.settings._chat_bg_col).draw(
+            chat_rect,
+            &c.draw_state,
+            c.transform,
+            g
+        );
+
+        // Input line at the bottom of the panel
+        let input_rect: [f64; 4] = [
+            chat_rect[0],
+            chat_rect[1] + chat_rect[3] - line_height - padding,
+            chat_rect[2],
+            line_height + padding
+        ];
+        Rectangle::new(self.settings._chat_input_col).draw(
+            input_rect,
+            &c.draw_state,
+            c.transform,
+            g
+        );
+
+        let input_text: String = match &controller.chat.notice
+        {
+            Some(notice) => notice.clone(),
+            None => format!("> {}_", controller.chat.input)
+        };
+        self.draw_text(Text::new_color(self.settings._chat_text_col, font_size), input_text.as_str(),
+            [input_rect[0] + padding, input_rect[1] + line_height], glyph, c, g);
+
+        // Draw from the bottom up, skipping the lines scrolled past
+        let mut y: f64 = input_rect[1] - padding;
+        for line in controller.chat.lines.iter().rev().skip(controller.chat.scroll)
+        {
+            if y - line_height < chat_rect[1] { break; }
+
+            self.draw_text(Text::new_color(self.settings._chat_text_col, font_size), line.as_str(),
+                [chat_rect[0] + padding, y], glyph, c, g);
+
+            y -= line_height;
+        }
+    }
+
+    // Draws the moves with their variations where the chat panel goes, the
+    // move of the position shown highlighted and kept in view
+    fn _draw_move_list<G: Graphics<Texture = Texture>>(&self,
+        glyph: &mut GlyphCache,
+        c: &Context, g: &mut G)
+    {
+        let list_rect: [f64; 4] = self.settings.chat_rect();
+        let font_size: u32 = self.settings._chat_font_size;
+        let line_height: f64 = font_size as f64 + 6.0;
+        let padding: f64 = 6.0;
+        let space: f64 = self.text_width(glyph, font_size, " ");
+
+        Rectangle::new(self.settings._chat_bg_col).draw(
+            list_rect,
+            &c.draw_state,
+            c.transform,
+            g
+        );
+
+        self.draw_text(Text::new_color(self.settings._chat_text_col, font_size),
+            "Ctrl+Z/Y back/on  Ctrl+Up/Down other move  Ctrl+P promote  Ctrl+D delete",
+            [list_rect[0] + padding, list_rect[1] + line_height], glyph, c, g);
+
+        // Wrap the words into lines of (word, x, is current)
+        let max_width: f64 = list_rect[2] - 2.0*padding;
+        let mut lines: Vec<Vec<(String, f64, bool)>> = vec![Vec::new()];
+        let mut x: f64 = 0.0;
+        for (word, current) in self.game.movetext()
+        {
+            let width: f64 = self.text_width(glyph, font_size, &word);
+            if x > 0.0 && x + width > max_width
+            {
+                lines.push(Vec::new());
+                x = 0.0;
+            }
+            if let Some(line) = lines.last_mut() { line.push((word, x, current)); }
+            x += width + space;
+        }
+
+        let shown: usize = ((list_rect[3] - 2.0*padding) / line_height) as usize - 1;
+        let current_line: usize = lines.iter().position(|line| line.iter().any(|(_, _, current)| *current))
+            .unwrap_or(0);
+        let mut y: f64 = list_rect[1] + 2.0*line_height;
+        for line in lines.iter().skip((current_line + 1).saturating_sub(shown)).take(shown)
+        {
+            for (word, x, current) in line
+            {
+                let col: [f32; 4] = if *current { self.settings._key_cursor_col } else { self.settings._chat_text_col };
+                self.draw_text(Text::new_color(col, font_size), word.as_str(),
+                    [list_rect[0] + padding + x, y], glyph, c, g);
+            }
+            y += line_height;
+        }
+    }
+
+    // Wrap the chat history to the width of the chat panel, before it's
+    // drawn. Scrolling counts these lines. Only does the work when a message
+    // arrived or the panel or the font changed
+    pub fn wrap_chat<C: CharacterCache>(&self, glyph: &mut C, chat: &mut Chat)
+    {
+        let font_size: u32 = self.settings._chat_font_size;
+        let max_width: f64 = self.settings.chat_rect()[2] - 2.0*CHAT_PADDING;
+        // Text is measured at its size on the screen
+        let font: String = format!("{} {} {}", self.settings._font, font_size, self.layout.pixel_scale());
+        chat.wrap(max_width, &font, |text| self.text_width(glyph, font_size, text));
+    }
+
+    // Draws `text` with its baseline starting at `pos`. The glyphs are
+    // rendered at the size they end up on the screen, so the text stays
+    // sharp when the layout is scaled up or on HiDPI screens
+    pub fn draw_text<G: Graphics, C: CharacterCache<Texture = G::Texture>>(&self, style: Text, text: &str,
+        pos: [f64; 2], glyph: &mut C, c: &Context, g: &mut G)
+    {
+        let pixel_scale: f64 = self.layout.pixel_scale();
+        let pixel_size: u32 = ((style.font_size as f64 * pixel_scale).round() as u32).max(1);
+
+        Text{font_size: pixel_size, ..style}
+            .draw(
+                text,
+                glyph,
+                &c.draw_state,
+                c.transform.trans(pos[0], pos[1]).zoom(1.0 / pixel_scale),
+            g).unwrap_or_else(|_| panic!("Could not draw the text \"{}\"", text));
+    }
+
+    // Draws a piece at `pos` (top left, one block big), `alpha` 0.0 is invisible
+    #[allow(clippy::too_many_arguments)]
+    fn _draw_piece<G: Graphics, C: CharacterCache<Texture = G::Texture>>(&self, piece: (PieceTypes, Colors), pos: [f64; 2],
+        alpha: f32, textures: &HashMap<(PieceTypes, Colors), G::Texture>,
+        glyph: &mut C, c: &Context, g: &mut G)
+    {
+        let block_size: f64 = self.settings._size / 8.0;
+
+        // Get the target texture from the hash map
+        match textures.get(&piece)
+        {
+            Some(target_texture) => {
+                // Draw the chess piece png
+                let visual_piece = Image::new_color([1.0, 1.0, 1.0, alpha]).rect(square(pos[0], pos[1], block_size));
+                visual_piece.draw(target_texture, &c.draw_state, c.transform, g);
+            },
+            // No image for this piece in any set, draw its FEN letter instead
+            None => if alpha > 0.5 { self._draw_piece_letter(piece.0, piece.1, pos, block_size, glyph, c, g) },
+        }
+    }
+
+    // Stand-in for a missing piece image: a disc in the piece's color with
+    // the piece letter on it
+    #[allow(clippy::too_many_arguments)]
+    fn _draw_piece_letter<G: Graphics, C: CharacterCache<Texture = G::Texture>>(&self, piece_type: PieceTypes, color: Colors,
+        pos: [f64; 2], block_size: f64, glyph: &mut C, c: &Context, g: &mut G)
+    {
+        let (fill, ink): (Color, Color) = if color == Colors::White { ([1.0; 4], [0.0, 0.0, 0.0, 1.0]) }
+                                          else { ([0.0, 0.0, 0.0, 1.0], [1.0; 4]) };
+        let margin: f64 = block_size * 0.15;
+
+        Ellipse::new(fill).border(ellipse::Border{color: ink, radius: 1.0}).draw(
+            [pos[0] + margin, pos[1] + margin, block_size - 2.0*margin, block_size - 2.0*margin],
+            &c.draw_state,
+            c.transform,
+            g
+        );
+
+        let letter: String = position::piece_to_char(piece_type, Colors::White).to_string();
+        let font_size: u32 = (block_size * 0.45) as u32;
+        let width: f64 = self.text_width(glyph, font_size, &letter);
+        self.draw_text(Text::new_color(ink, font_size), &letter,
+            [pos[0] + (block_size - width) / 2.0, pos[1] + block_size*0.5 + font_size as f64*0.35], glyph, c, g);
+    }
+
+    // Width of `text` in view coordinates, as drawn by `_draw_text`
+    pub fn text_width<C: CharacterCache>(&self, glyph: &mut C, font_size: u32, text: &str) -> f64
+    {
+        let pixel_scale: f64 = self.layout.pixel_scale();
+        let pixel_size: u32 = ((font_size as f64 * pixel_scale).round() as u32).max(1);
+
+        glyph.width(pixel_size, text).unwrap_or(0.0) / pixel_scale
     }
 
     // Main funcion that draws the whole boards
-    fn _draw_board<G: Graphics<Texture = Texture>>(&self, 
-        textures: &HashMap<(PieceTypes, Colors), Texture>, 
+    fn _draw_board<G: Graphics, C: CharacterCache<Texture = G::Texture>>(&self,
+        glyph: &mut C,
+        textures: &HashMap<(PieceTypes, Colors), G::Texture>,
         controller: &ChessViewController, 
         c: &Context, g: &mut G)
     {
         let board_rect: [f64; 4] = [
-            self.settings._pos[0],
-            self.settings._pos[1],
+            self.settings.board_pos()[0],
+            self.settings.board_pos()[1],
             self.settings._size,
             self.settings._size,
         ];
 
-        let _col_bl: [f32; 4] = [128.0/255.0, 64.0/255.0, 0.0, 1.0];
-        let _col_wh: [f32; 4] = [1.0; 4];
-        let block_size: f64 = self.settings._size as f64 / 8.0;
+        let _col_bl: [f32; 4] = self.settings._dark_block_col;
+        let _col_wh: [f32; 4] = self.settings._light_block_col;
+        let block_size: f64 = self.settings._size / 8.0;
 
+        let squares: position::Squares = self.squares();
+        let last_move: Option<(u8, u8)> = self.game.last_move()
+            .map(|mv| (position::square_to_block(mv.from), position::square_to_block(mv.to)));
         let mut i: u8 = 0;
         // Loop through every element in the board
-        for row in self.board.get_board()
+        for row in squares.chunks(8).rev()
         {
             for piece in row
             {
                 // The actual coordinates inside the GUI canvas
-                let x1: f64 = self.settings._pos[0] + (i%8) as f64 * block_size;
-                let y1: f64 = self.settings._pos[1] + (i/8) as f64 * block_size;
+                let [x1, y1] = self.block_pos(i);
 
                 // Rectangle parameters for drawing, width,height, start x & y
                 let chess_cell: [f64; 4] = [x1,y1,block_size, block_size];
-                let mut chess_cell_col: [f32; 4] = if (i/8+i+1)%2==0 {_col_wh} else {_col_bl};
+                let mut chess_cell_col: [f32; 4] = if (i/8+i+1).is_multiple_of(2) {_col_wh} else {_col_bl};
                 
                 // If looping though the selected block, set the bg color to green to mark it
                 // as the selected one
@@ -241,17 +427,24 @@ impl ChessView
                     g
                 );
 
+                // Tint the blocks the last move went from and to
+                if last_move.is_some_and(|(from, to)| i == from || i == to)
+                {
+                    Rectangle::new(self.settings._last_move_col).draw(
+                        chess_cell,
+                        &c.draw_state,
+                        c.transform,
+                        g
+                    );
+                }
+
                 // If there's actually a piece at this block (not empty)
                 // try to draw it
-                if let Some(piece) = piece
+                // try to draw it. Pieces still sliding in are drawn below
+                let covered: bool = self.animation.as_ref().is_some_and(|anim| anim.covers(i));
+                if let (Some(piece), false) = (piece, covered)
                 {
-                    // Get the target texture from the hash map
-                    let target_texture: &Texture = textures.get(
-                        &(piece.piece_type, piece.color)).unwrap();
-
-                    // Draw the chess piece png
-                    let visual_piece = Image::new().rect(square(x1,y1, block_size));
-                    visual_piece.draw(target_texture, &c.draw_state, c.transform, g);
+                    self._draw_piece(*piece, [x1, y1], 1.0, textures, glyph, c, g);
                 }
 
                 // Holding track of the linear coordinates
@@ -259,6 +452,42 @@ impl ChessView
             }
         }
 
+        if let Some(animation) = &self.animation
+        {
+            let progress: f64 = animation.progress();
+
+            // Captured pieces fade out under the piece taking them
+            for (block, piece) in animation.captured.iter()
+            {
+                self._draw_piece(*piece, self.block_pos(*block), (1.0 - progress) as f32, textures, glyph, c, g);
+            }
+
+            for (from, to, piece) in animation.movers.iter()
+            {
+                let [fx, fy] = self.block_pos(*from);
+                let [tx, ty] = self.block_pos(*to);
+                let pos: [f64; 2] = [fx + (tx - fx) * progress, fy + (ty - fy) * progress];
+                self._draw_piece(*piece, pos, 1.0, textures, glyph, c, g);
+            }
+        }
+
+        if let Some(annotations) = self.game.current_annotations()
+        {
+            self._draw_annotations(annotations, c, g);
+        }
+
+        // Block picked with the arrow keys
+        if let Some(block) = controller.key_cursor
+        {
+            let [x, y] = self.block_pos(block);
+            Rectangle::new_border(self.settings._key_cursor_col, block_size * 0.04).draw(
+                [x, y, block_size, block_size],
+                &c.draw_state,
+                c.transform,
+                g
+            );
+        }
+
         // Draw a border around the chess board
         Rectangle::new_border(self.settings._border_col,
                                 self.settings._border_radius).draw(
@@ -266,30 +495,259 @@ impl ChessView
                                     &c.draw_state,
                                     c.transform,
                                     g
-                                );         
+                                );
+
+        if self.settings._coords
+        {
+            self._draw_coordinates(glyph, c, g);
+        }
+
+        if self.settings._captured_height > 0.0
+        {
+            self._draw_captured(glyph, textures, c, g);
+        }
+
+        if !controller.move_input.is_empty() || controller.move_error.is_some()
+        {
+            self._draw_move_input(glyph, controller, c, g);
+        }
+    }
+
+    // Draws the move being typed in a bar over the bottom of the board, or
+    // why the last one couldn't be played
+    fn _draw_move_input<G: Graphics, C: CharacterCache<Texture = G::Texture>>(&self,
+        glyph: &mut C,
+        controller: &ChessViewController,
+        c: &Context, g: &mut G)
+    {
+        let font_size: u32 = self.settings._chat_font_size;
+        let padding: f64 = 6.0;
+        let height: f64 = font_size as f64 + 6.0 + padding;
+        let board_pos: [f64; 2] = self.settings.board_pos();
+        let bar_rect: [f64; 4] = [
+            board_pos[0],
+            board_pos[1] + self.settings._size - height,
+            self.settings._size,
+            height
+        ];
+
+        Rectangle::new(self.settings._chat_input_col).draw(
+            bar_rect,
+            &c.draw_state,
+            c.transform,
+            g
+        );
+
+        let (text, col): (String, Color) = match &controller.move_error
+        {
+            Some(error) => (error.clone(), self.settings._move_error_col),
+            None => (format!("Move: {}_", controller.move_input), self.settings._chat_text_col)
+        };
+        self.draw_text(Text::new_color(col, font_size), text.as_str(),
+            [bar_rect[0] + padding, bar_rect[1] + height - padding], glyph, c, g);
+    }
+
+    // Draws the pieces each side has taken next to it, the side at the
+    // bottom of the board below it, plus how far ahead in material it is
+    fn _draw_captured<G: Graphics, C: CharacterCache<Texture = G::Texture>>(&self,
+        glyph: &mut C,
+        textures: &HashMap<(PieceTypes, Colors), G::Texture>,
+        c: &Context, g: &mut G)
+    {
+        let squares: position::Squares = self.squares();
+        let balance: i32 = position::material_balance(&squares);
+        let bottom: Colors = if self.flipped { Colors::Black } else { Colors::White };
+
+        for color in [Colors::White, Colors::Black]
+        {
+            let opponent: Colors = if color == Colors::White { Colors::Black } else { Colors::White };
+            let ahead: i32 = if color == Colors::White { balance } else { -balance };
+            let [x, y, _, height] = self.settings.captured_rect(color != bottom);
+
+            // Small pieces overlapping a bit, a gap between kinds
+            let piece_size: f64 = height * 0.9;
+            let mut piece_x: f64 = x;
+            let mut last: Option<PieceTypes> = None;
+            for piece_type in position::missing_pieces(&squares, opponent)
+            {
+                if last.is_some() && last != Some(piece_type) { piece_x += piece_size * 0.4; }
+                last = Some(piece_type);
+
+                if let Some(texture) = textures.get(&(piece_type, opponent))
+                {
+                    Image::new().rect(square(piece_x, y + (height - piece_size) / 2.0, piece_size))
+                        .draw(texture, &c.draw_state, c.transform, g);
+                }
+                piece_x += piece_size * 0.55;
+            }
+
+            if ahead > 0
+            {
+                let font_size: u32 = (height * 0.5) as u32;
+                self.draw_text(Text::new_color(self.settings._captured_text_col, font_size),
+                    &format!("+{}", ahead),
+                    [piece_x + piece_size * 0.6, y + height / 2.0 + font_size as f64 * 0.35], glyph, c, g);
+            }
+        }
+    }
+
+    // Draws the circled squares and the arrows of the current position
+    fn _draw_annotations<G: Graphics>(&self,
+        annotations: &Annotations,
+        c: &Context, g: &mut G)
+    {
+        let block_size: f64 = self.settings._size / 8.0;
+        let center = |square: u32| {
+            let [x, y] = self.block_pos(position::square_to_block(square));
+            [x + block_size / 2.0, y + block_size / 2.0]
+        };
+
+        for (square, mark) in annotations.circles.iter()
+        {
+            let [x, y] = self.block_pos(position::square_to_block(*square));
+            let inset: f64 = block_size * 0.06;
+            Ellipse::new_border(mark.color(), block_size * 0.04).draw(
+                [x + inset, y + inset, block_size - 2.0*inset, block_size - 2.0*inset],
+                &c.draw_state,
+                c.transform,
+                g
+            );
+        }
+
+        for (from, to, mark) in annotations.arrows.iter()
+        {
+            let [x1, y1] = center(*from);
+            let [x2, y2] = center(*to);
+
+            // Stop a bit before the center so the head doesn't cover the
+            // whole target block
+            let length: f64 = ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt();
+            let shorten: f64 = (block_size * 0.25).min(length / 2.0);
+            let (x2, y2) = (x2 - (x2 - x1) / length * shorten, y2 - (y2 - y1) / length * shorten);
+
+            Line::new(mark.color(), block_size * 0.08).draw_arrow(
+                [x1, y1, x2, y2],
+                block_size * 0.3,
+                &c.draw_state,
+                c.transform,
+                g
+            );
+        }
+    }
+
+    // Draws the files (a-h) below the board and the ranks (1-8) left of it,
+    // centered on their blocks in the margin
+    fn _draw_coordinates<G: Graphics, C: CharacterCache<Texture = G::Texture>>(&self,
+        glyph: &mut C,
+        c: &Context, g: &mut G)
+    {
+        let font_size: u32 = self.settings._coords_font_size;
+        let block_size: f64 = self.settings._size / 8.0;
+        let board_pos: [f64; 2] = self.settings.board_pos();
+        let board_bottom: f64 = board_pos[1] + self.settings._size;
+
+        for n in 0..8u8
+        {
+            // Block n of the top row and of the left column, as drawn
+            let file: u8 = if self.flipped { 7 - n } else { n };
+            let rank: u8 = if self.flipped { n + 1 } else { 8 - n };
+
+            let file_label: String = ((b'a' + file) as char).to_string();
+            let file_x: f64 = board_pos[0] + (n as f64 + 0.5) * block_size
+                              - self.text_width(glyph, font_size, &file_label) / 2.0;
+            self.draw_text(Text::new_color(self.settings._coords_col, font_size), &file_label,
+                [file_x, board_bottom + font_size as f64 + 2.0], glyph, c, g);
+
+            let rank_label: String = rank.to_string();
+            let rank_x: f64 = board_pos[0] - self.text_width(glyph, font_size, &rank_label) - 4.0;
+            self.draw_text(Text::new_color(self.settings._coords_col, font_size), &rank_label,
+                [rank_x, board_pos[1] + (n as f64 + 0.5) * block_size + font_size as f64 * 0.35],
+                glyph, c, g);
+        }
     }
 }
 
 
 
 
-// Could be later implemented for this struct to be constructed from a config file
-#[derive(Clone, Copy)]
+// Layout, colors and font of the GUI. `new` has the defaults, a settings file
+// can override any of them (see settings_file.rs)
+#[derive(Clone)]
 pub struct ChessViewSettings
 {
     pub _pos: [f64; 2],
     pub _size: f64,
+    pub _font: String,
+    // Name of the theme from assets/themes, empty if the colors aren't from a theme
+    pub _theme: String,
+    // Directory name in assets/pieces
+    pub _piece_set: String,
+    pub _bg_col: Color,
+    pub _light_block_col: Color,
+    pub _dark_block_col: Color,
     pub _border_col: Color,
     pub _border_radius: f64,
     pub _block_radius: f64,
 
     pub _from_block_col: Color,
 
+    // a-h and 1-8 around the board
+    pub _coords: bool,
+    pub _coords_font_size: u32,
+    pub _coords_col: Color,
+
+    // Strips above and below the board with the captured pieces, 0 = hidden
+    pub _captured_height: f64,
+    pub _captured_text_col: Color,
+
+    // How long a move is animated, 0 = off
+    pub _animation_ms: u32,
+    pub _last_move_col: Color,
+    pub _key_cursor_col: Color,
+    pub _move_error_col: Color,
+    // Menu buttons under the mouse, and held down
+    pub _button_hover_col: Color,
+    pub _button_pressed_col: Color,
+    // Dims the screens below the pause menu
+    pub _overlay_col: Color,
+
     pub _intro_text_coords: [f64; 2],
     pub _intro_button_col: Color,
     pub _intro_button_size: [f64; 2],
     pub _intro_sp_button_pos: [f64; 2],
     pub _intro_mp_button_pos: [f64; 2],
+    pub _intro_host_button_pos: [f64; 2],
+    pub _intro_settings_button_pos: [f64; 2],
+    pub _intro_continue_button_pos: [f64; 2],
+    pub _intro_games_button_pos: [f64; 2],
+    pub _intro_puzzles_button_pos: [f64; 2],
+
+    pub _lobby_title_coords: [f64; 2],
+    pub _lobby_row_pos: [f64; 2],
+    pub _lobby_row_size: [f64; 2],
+    pub _lobby_row_gap: f64,
+    pub _lobby_row_col: Color,
+    pub _lobby_back_button_pos: [f64; 2],
+
+    pub _chat_height: f64,
+    pub _chat_font_size: u32,
+    pub _chat_bg_col: Color,
+    pub _chat_input_col: Color,
+    pub _chat_text_col: Color,
+
+    pub _replay_bar_height: f64,
+    pub _replay_bar_col: Color,
+
+    pub _puzzle_bar_height: f64,
+    pub _puzzle_bar_col: Color,
+}
+
+impl Default for ChessViewSettings
+{
+    fn default() -> ChessViewSettings
+    {
+        ChessViewSettings::new()
+    }
 }
 
 impl ChessViewSettings
@@ -299,12 +757,36 @@ impl ChessViewSettings
         ChessViewSettings{
             _pos: [20.0;2],
             _size: 600.0,
+            _font: String::from("assets/font/Lato-Bold.ttf"),
+            _theme: String::new(),
+            _piece_set: String::from("classic"),
+            // rgba
+            _bg_col: [0.4, 0.4, 0.4, 1.0],
+            _light_block_col: [1.0; 4],
+            _dark_block_col: [128.0/255.0, 64.0/255.0, 0.0, 1.0],
             // rgba
             _border_col: [0.0, 0.0, 0.4, 1.0],
             _border_radius: 2.0,
             _block_radius: 1.0,
             // rgba
             _from_block_col: [0.0, 128.0/255.0, 0.0, 1.0],
+            _coords: true,
+            _coords_font_size: 14,
+            // rgba
+            _coords_col: [0.2, 0.2, 0.2, 1.0],
+            _captured_height: 30.0,
+            // rgba
+            _captured_text_col: [0.2, 0.2, 0.2, 1.0],
+            _animation_ms: 180,
+            // rgba, blended over the blocks, a transparent color hides it
+            _last_move_col: [0.9, 0.85, 0.2, 0.45],
+            // rgba
+            _key_cursor_col: [0.9, 0.68, 0.0, 1.0],
+            _move_error_col: [1.0, 0.4, 0.4, 1.0],
+            // rgba
+            _button_hover_col: [0.0, 0.7, 0.0, 1.0],
+            _button_pressed_col: [0.0, 0.45, 0.0, 1.0],
+            _overlay_col: [0.0, 0.0, 0.0, 0.6],
             // x,y
             _intro_text_coords: [220.0, 100.0],
             // rgba
@@ -313,8 +795,205 @@ impl ChessViewSettings
             _intro_button_size: [150.0, 45.0],
             // x,y
             _intro_sp_button_pos: [245.0, 150.0],
-            _intro_mp_button_pos: [245.0, 210.0]
+            _intro_mp_button_pos: [245.0, 210.0],
+            _intro_host_button_pos: [245.0, 270.0],
+            _intro_settings_button_pos: [245.0, 330.0],
+            _intro_continue_button_pos: [245.0, 390.0],
+            _intro_games_button_pos: [245.0, 450.0],
+            _intro_puzzles_button_pos: [245.0, 510.0],
+
+            // x,y
+            _lobby_title_coords: [200.0, 80.0],
+            // x,y of the first row, the others follow below it
+            _lobby_row_pos: [70.0, 140.0],
+            // width, height
+            _lobby_row_size: [500.0, 40.0],
+            _lobby_row_gap: 10.0,
+            // rgba
+            _lobby_row_col: [0.25, 0.25, 0.25, 1.0],
+            // x,y, same size as the intro buttons
+            _lobby_back_button_pos: [245.0, 560.0],
+
+            // Chat overlay, covers the lower part of the board
+            _chat_height: 220.0,
+            _chat_font_size: 14,
+            // rgba
+            _chat_bg_col: [0.0, 0.0, 0.0, 0.75],
+            _chat_input_col: [0.15, 0.15, 0.15, 0.9],
+            _chat_text_col: [1.0; 4],
 
+            // Replay controls, over the bottom of the board like the chat
+            _replay_bar_height: 100.0,
+            // rgba
+            _replay_bar_col: [0.0, 0.0, 0.0, 0.75],
+
+            // Puzzle status, over the top of the board so the typed move
+            // still shows at the bottom
+            _puzzle_bar_height: 60.0,
+            // rgba
+            _puzzle_bar_col: [0.0, 0.0, 0.0, 0.75]
         }
     }
-}
\ No newline at end of file
+
+    // Top left corner of the board, below the captured pieces strip
+    pub fn board_pos(&self) -> [f64; 2]
+    {
+        [self._pos[0], self._pos[1] + self._captured_height]
+    }
+
+    // x, y, width, height of the captured pieces strip above (`top`) or
+    // below the board. The lower one is below the file labels
+    pub fn captured_rect(&self, top: bool) -> [f64; 4]
+    {
+        let y: f64 = if top { self._pos[1] }
+                     else { self.board_pos()[1] + self._size + self._pos[1] };
+        [self._pos[0], y, self._size, self._captured_height]
+    }
+
+    // x, y, width, height of the i-th lobby row
+    pub fn lobby_row_rect(&self, i: usize) -> [f64; 4]
+    {
+        [
+            self._lobby_row_pos[0],
+            self._lobby_row_pos[1] + i as f64 * (self._lobby_row_size[1] + self._lobby_row_gap),
+            self._lobby_row_size[0],
+            self._lobby_row_size[1]
+        ]
+    }
+
+    // x, y, width, height of a scrolled list of lobby rows, from the i-th
+    // row down to the back button
+    pub fn lobby_list_rect(&self, first_row: usize) -> [f64; 4]
+    {
+        let top: f64 = self.lobby_row_rect(first_row)[1];
+        [
+            self._lobby_row_pos[0],
+            top,
+            self._lobby_row_size[0],
+            (self._lobby_back_button_pos[1] - self._lobby_row_gap - top).max(0.0)
+        ]
+    }
+
+    pub fn lobby_back_button_rect(&self) -> [f64; 4]
+    {
+        [
+            self._lobby_back_button_pos[0],
+            self._lobby_back_button_pos[1],
+            self._intro_button_size[0],
+            self._intro_button_size[1]
+        ]
+    }
+
+    // x, y, width, height of the resume, leave and replay buttons of the
+    // pause menu
+    pub fn pause_button_rects(&self) -> [[f64; 4]; 3]
+    {
+        let [w, h] = self._intro_button_size;
+        [
+            [self._intro_sp_button_pos[0], self._intro_sp_button_pos[1], w, h],
+            [self._intro_mp_button_pos[0], self._intro_mp_button_pos[1], w, h],
+            [self._intro_host_button_pos[0], self._intro_host_button_pos[1], w, h]
+        ]
+    }
+
+    // x, y, width, height of the replay controls
+    pub fn replay_bar_rect(&self) -> [f64; 4]
+    {
+        [
+            self.board_pos()[0],
+            self.board_pos()[1] + self._size - self._replay_bar_height,
+            self._size,
+            self._replay_bar_height
+        ]
+    }
+
+    // x, y, width, height of the puzzle status
+    pub fn puzzle_bar_rect(&self) -> [f64; 4]
+    {
+        [self.board_pos()[0], self.board_pos()[1], self._size, self._puzzle_bar_height]
+    }
+
+    // x, y, width, height of the chat panel
+    pub fn chat_rect(&self) -> [f64; 4]
+    {
+        [
+            self.board_pos()[0],
+            self.board_pos()[1] + self._size - self._chat_height,
+            self._size,
+            self._chat_height
+        ]
+    }
+}
+
+
+// Maps the layout from `ChessViewSettings` (view coordinates) onto the
+// window: scaled uniformly so the whole view fits and centered, the rest of
+// the window is filled with the background. Drawing and the hit-tests of the
+// controller both go through this, so they always agree
+#[derive(Clone, Copy)]
+pub struct ViewLayout
+{
+    // Size of the whole view in view coordinates, the board plus its margins
+    pub view_size: [f64; 2],
+    // Window size in logical pixels
+    pub window_size: [f64; 2],
+    // Physical pixels per logical pixel, > 1 on HiDPI screens
+    pub dpi_scale: f64,
+    // Logical pixels per view unit
+    pub scale: f64,
+    // Top left corner of the view in the window, logical pixels
+    pub offset: [f64; 2],
+}
+
+impl ViewLayout
+{
+    pub fn fit(settings: &ChessViewSettings, window_size: [f64; 2], draw_size: [f64; 2]) -> ViewLayout
+    {
+        let view_size: [f64; 2] = [
+            settings._size + 2.0*settings._pos[0],
+            settings._size + 2.0*settings._pos[1] + 2.0*settings._captured_height
+        ];
+
+        // A minimized window reports 0x0, keep the scale usable
+        let window_size: [f64; 2] = [window_size[0].max(1.0), window_size[1].max(1.0)];
+        let scale: f64 = (window_size[0] / view_size[0]).min(window_size[1] / view_size[1]);
+        let dpi_scale: f64 = if draw_size[0] > 0.0 { draw_size[0] / window_size[0] } else { 1.0 };
+
+        ViewLayout{
+            view_size,
+            window_size,
+            dpi_scale,
+            scale,
+            offset: [
+                (window_size[0] - view_size[0]*scale) / 2.0,
+                (window_size[1] - view_size[1]*scale) / 2.0
+            ],
+        }
+    }
+
+    // Window coordinates (e.g. the mouse cursor) to view coordinates
+    pub fn to_view(&self, window_pos: [f64; 2]) -> [f64; 2]
+    {
+        [
+            (window_pos[0] - self.offset[0]) / self.scale,
+            (window_pos[1] - self.offset[1]) / self.scale
+        ]
+    }
+
+    // The whole window as x, y, width, height in view coordinates
+    pub fn visible_rect(&self) -> [f64; 4]
+    {
+        [
+            -self.offset[0] / self.scale,
+            -self.offset[1] / self.scale,
+            self.window_size[0] / self.scale,
+            self.window_size[1] / self.scale
+        ]
+    }
+
+    // Physical pixels per view unit
+    pub fn pixel_scale(&self) -> f64
+    {
+        self.scale * self.dpi_scale
+    }
+}
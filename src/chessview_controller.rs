@@ -1,35 +1,119 @@
 extern crate json;
 
-use std::net::TcpStream;
 use std::fs;
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use piston::{GenericEvent, MouseButton, Button};
-use crate::ChessView;
+use piston::{Event, GenericEvent, MouseButton, Button, Key};
+use crate::{ChessView, ViewLayout};
+use crate::widgets::UiInput;
+use crate::screen::{ScreenStack, IntroScreen};
+use crate::chat::Chat;
+use crate::inet::{self, items};
+use crate::inet::client::{NetClient, NetEvent};
+use crate::inet::version;
+use crate::inet::transport::TransportKind;
+use crate::inet::discovery::{self, Discovery, DiscoveredGame};
+use crate::inet::host::{HostServer, HostEvent, HostCommand};
+use crate::position;
+use crate::animation::Animation;
+use crate::autosave::{self, SavedGame};
+use crate::game::{self, Game, Annotations, Mark};
+use crate::database::{self, ArchivedGame};
+use crate::computer;
+use crate::settings_file;
 use chess::piece::*;
+use chess::colors::Colors;
+use chess::piece_types::PieceTypes;
+use chess::chess_engine::ChessEngine;
 
-pub mod items {
-    include!("inet/protobuf.rs");
-}
+// Where Ctrl+S saves games
+const PGN_DIR: &str = "pgn";
 
-#[derive(Clone, Copy)]
-#[repr(u8)]
-pub enum GameState
-{
-    Intro = 0,
-    SPIngame = 1,
-    MPIngame = 2,
+// Longest typed move, "exd8=Q+" and some slack
+const MAX_MOVE_INPUT: usize = 10;
 
-    SPPause = 3,
-    MPPause = 4
+// Who plays the moves of the game on the board
+#[derive(Clone, Copy, PartialEq)]
+pub enum GameMode
+{
+    // Both sides on this screen
+    Local,
+    // Against the computer, which plays this color
+    Computer(Colors),
+    // Both sides on this screen, moves can be taken back and played again
+    Analysis,
+    // Over the network, as a player or a spectator
+    Online,
 }
 
-#[derive(Clone)]
 pub struct ChessViewController
 {
     pub mp_connection: bool,
-    pub state: GameState,
+    // Shown on the MP screen while there is no connection
+    pub mp_status: String,
+    pub net: Option<NetClient>,
+    // Set instead of `net` when this GUI is the server of the game
+    pub host: Option<HostServer>,
+    // Our color, `None` while spectating
+    pub mp_is_white: Option<bool>,
+    pub mp_white_to_move: bool,
+    // Half moves played in the current MP game
+    pub mp_plies: u32,
+    // Selected square, MP moves are only played on the engine once accepted
+    mp_selected: Option<String>,
+    // Move sent to the server and waiting for its `S2CMoveAck`
+    mp_pending_move: Option<items::Move>,
+    // Optional protocol features enabled for the current connection
+    pub mp_capabilities: Vec<String>,
+    pub chat: Chat,
+    // Listens for LAN games while the lobby is open
+    pub discovery: Option<Discovery>,
+    // Lobby rows: the server from config/server.json first, then the LAN games
+    pub lobby_games: Vec<DiscoveredGame>,
+    lobby_config_game: Option<DiscoveredGame>,
+    pub lobby_status: String,
+    // Rows of the settings screen, the themes first, then the piece sets
+    pub settings_themes: Vec<String>,
+    pub settings_piece_sets: Vec<String>,
+    pub settings_status: String,
+    // Intro at the bottom, the game and its overlays on top
+    pub screens: ScreenStack,
+    pub mode: GameMode,
+    // The move list panel is shown in analysis mode
+    pub move_list_open: bool,
+    // The squares before the board first changed on this event, see
+    // `_board_will_change`
+    board_before: Option<position::Squares>,
+    // The board was set up rather than moved on this event, e.g. a move taken back
+    board_replaced: bool,
+    // A game worth continuing is on the board, not while hosting
+    autosaving: bool,
+    // The server and spectating of the joined MP game, saved for rejoining
+    mp_game: Option<(DiscoveredGame, bool)>,
     pub selected: u8,
-    cursor_pos: [f64;2]
+    // Move typed on the keyboard, and why the last one wasn't played
+    pub move_input: String,
+    pub move_error: Option<String>,
+    // Block picked with the arrow keys
+    pub key_cursor: Option<u8>,
+    // Square a right-click drag started on, for drawing an arrow
+    arrow_start: Option<u32>,
+    // Held modifier keys, they pick the arrow/circle color
+    shift_held: bool,
+    ctrl_held: bool,
+    alt_held: bool,
+    // Mouse and keyboard state for the menu widgets, the cursor in view
+    // coordinates
+    pub ui: UiInput
+}
+
+impl Default for ChessViewController
+{
+    fn default() -> ChessViewController
+    {
+        ChessViewController::new()
+    }
 }
 
 impl ChessViewController
@@ -38,96 +122,800 @@ impl ChessViewController
     pub fn new() -> ChessViewController
     {
         // 255 = not selected
-        ChessViewController{mp_connection: false, state: GameState::Intro,
-            selected: 255, cursor_pos: [0.0;2]}
+        ChessViewController{mp_connection: false, 
+            mp_status: String::from("Waiting for connection.."), net: None, host: None,
+            mp_is_white: None, mp_white_to_move: true, mp_plies: 0,
+            mp_selected: None, mp_pending_move: None,
+            mp_capabilities: Vec::new(), chat: Chat::new(), discovery: None, lobby_games: Vec::new(),
+            lobby_config_game: None,
+            lobby_status: String::new(),
+            settings_themes: Vec::new(), settings_piece_sets: Vec::new(), settings_status: String::new(),
+            screens: ScreenStack::new(Box::new(IntroScreen::new())), mode: GameMode::Local,
+            move_list_open: false, board_before: None, board_replaced: false, autosaving: false, mp_game: None,
+            selected: 255, move_input: String::new(), move_error: None, key_cursor: None, arrow_start: None, shift_held: false, ctrl_held: false, alt_held: false,
+            ui: UiInput::default()}
+    }
+
+    // True while a network worker or the LAN discovery is running, the event
+    // loop then has to keep polling so that incoming messages are shown.
+    // Also while the computer has to move, or a screen plays something by
+    // itself
+    pub fn needs_polling(&self, chessview: &ChessView) -> bool
+    {
+        self.net.is_some() || self.host.is_some() || self.discovery.is_some()
+            || self._computer_to_move(chessview) || self.screens.needs_polling()
+    }
+
+    // Controlls the events, s.a mouse clicks and so on.. The screen on top
+    // of the stack decides what they do
+    pub fn event(&mut self, chessview: &mut ChessView, e: &Event)
+    {
+        // Kept in view coordinates, the same ones the layout is drawn in
+        let layout: ViewLayout = chessview.layout;
+        self.ui.begin(e, |pos| layout.to_view(pos));
+
+        self._modifier_event(e);
+
+        // Screens get the controller, so the stack is taken out meanwhile
+        let mut screens: ScreenStack = std::mem::take(&mut self.screens);
+        let switched: bool = screens.event(self, chessview, e);
+        self.screens = screens;
+
+        // A game set up or left isn't a move
+        let replaced: bool = std::mem::take(&mut self.board_replaced);
+        let board_before: Option<position::Squares> = self.board_before.take();
+        if switched { return; }
+
+        // Most events don't touch the board
+        let board_before: position::Squares = match board_before
+        {
+            Some(squares) => squares,
+            None => return
+        };
+        let board_after: position::Squares = chessview.squares();
+        let duration: Duration = Duration::from_millis(chessview.settings._animation_ms as u64);
+        if let Some(animation) = Animation::between(&board_before, &board_after, duration)
+        {
+            chessview.animation = Some(animation);
+        }
+
+        // Also when animations are off. A move other than the one taken back
+        // starts a variation
+        if !replaced && board_before != board_after && chessview.game.record(&board_before, &board_after)
+        {
+            self._check_game_over(chessview);
+        }
+
+        // MP games are saved once on joining, the server has their moves
+        if board_before != board_after && self.mode != GameMode::Online
+        {
+            self.autosave(chessview);
+        }
+    }
+
+    // Call before changing the board, `event` then animates the change and
+    // records it as a move. Keeps the squares from before the first change
+    fn _board_will_change(&mut self, chessview: &ChessView)
+    {
+        if self.board_before.is_none() { self.board_before = Some(chessview.squares()); }
+    }
+
+    // Checkmate or stalemate sets the result, and the game goes to the
+    // database instead of the save file. Analysis games are studies and
+    // aren't kept
+    fn _check_game_over(&mut self, chessview: &mut ChessView)
+    {
+        let white_to_move: bool = if self.mode == GameMode::Online { self.mp_white_to_move }
+                                  else { chessview.game.ply().is_multiple_of(2) };
+        let to_move: Colors = if white_to_move { Colors::White } else { Colors::Black };
+        let result: &str = match position::game_result(&chessview.board, to_move)
+        {
+            Some(result) => result,
+            None => return
+        };
+        chessview.game.result = result.to_string();
+        if self.mode == GameMode::Analysis { return; }
+
+        let (color, opponent): (Option<Colors>, String) = match self.mode
+        {
+            GameMode::Computer(Colors::White) => (Some(Colors::Black), String::from("Computer")),
+            GameMode::Computer(Colors::Black) => (Some(Colors::White), String::from("Computer")),
+            GameMode::Online => (
+                self.mp_is_white.map(|white| if white { Colors::White } else { Colors::Black }),
+                self.mp_game.as_ref().map_or(String::from("Guest"), |(game, _)| game.ip_port.clone())
+            ),
+            _ => (None, String::from("Local")),
+        };
+
+        let game: &mut Game = &mut chessview.game;
+        match color
+        {
+            Some(Colors::White) => { game.white = String::from("You"); game.black = opponent.clone(); },
+            Some(Colors::Black) => { game.white = opponent.clone(); game.black = String::from("You"); },
+            None => (),
+        }
+
+        let archived: ArchivedGame = ArchivedGame{
+            date: game::today(),
+            white: game.white.clone(),
+            black: game.black.clone(),
+            result: game.result.clone(),
+            opening: game.opening().to_string(),
+            color,
+            opponent,
+            pgn: game.to_pgn(),
+        };
+        if let Err(err) = database::add(&archived)
+        {
+            eprintln!("Could not archive the game: {}", err);
+        }
+
+        self.autosaving = false;
+        autosave::remove();
+    }
+
+    // Write the game on the board to the save file, so "Continue" on the
+    // intro screen picks it up again. Diagrams can't be played from, so
+    // they aren't saved
+    pub fn autosave(&self, chessview: &ChessView)
+    {
+        if !self.autosaving || chessview.position.is_some() { return; }
+
+        let saved: SavedGame = SavedGame{
+            mode: self.mode,
+            game: if self.mp_game.is_some() { Game::new() } else { chessview.game.clone() },
+            flipped: chessview.flipped,
+            server: self.mp_game.clone(),
+        };
+        if let Err(err) = autosave::save(&saved)
+        {
+            eprintln!("Could not save the game: {}", err);
+        }
     }
 
-    // Controlls the events, s.a mouse clicks and so on..
-    pub fn event<E: GenericEvent>(&mut self, chessview: &mut ChessView, e: &E)
+    // Input while the board is shown
+    pub fn game_event<E: GenericEvent>(&mut self, chessview: &mut ChessView, e: &E)
     {
-        let pos: [f64; 2] = chessview.settings._pos;
+        let pos: [f64; 2] = chessview.settings.board_pos();
+        let mp: bool = self.mode == GameMode::Online;
+        // Diagrams set up from a FEN can't be played on, MP games following
+        // the server's position can
+        let can_move: bool = (chessview.position.is_none() || mp) && !self._computer_to_move(chessview);
+
+        if mp { self._chat_event(chessview, e); }
+        self._annotation_event(pos, chessview, e);
+        if can_move { self._keyboard_event(chessview, e, mp); }
+
+        // Ctrl+Z takes a move back in analysis mode, Ctrl+Y plays it again.
+        // Ctrl+Up/Down pick which of the moves played from here Ctrl+Y plays,
+        // Ctrl+P promotes the variation shown and Ctrl+D deletes it
+        if let (GameMode::Analysis, true, true, Some(Button::Keyboard(key))) =
+            (self.mode, self.ctrl_held, can_move, e.press_args())
+        {
+            match key
+            {
+                Key::Z => self._undo(chessview),
+                Key::Y => self._redo(chessview),
+                Key::Up => { chessview.game.switch_variation(-1); },
+                Key::Down => { chessview.game.switch_variation(1); },
+                Key::P => { chessview.game.promote_variation(); },
+                Key::D if chessview.game.delete_variation() => self._show_current_ply(chessview),
+                _ => ()
+            }
+        }
+
+        // Tab shows the move list with the variations in analysis mode
+        if let (GameMode::Analysis, Some(Button::Keyboard(Key::Tab))) = (self.mode, e.press_args())
+        {
+            self.move_list_open = !self.move_list_open;
+        }
 
-        if let Some(cursor_pos) = e.mouse_cursor_args()
+        // Ctrl+S saves the game so far
+        if let (true, Some(Button::Keyboard(Key::S))) = (self.ctrl_held, e.press_args())
         {
-            self.cursor_pos = cursor_pos;
+            match _export_pgn(&chessview.game)
+            {
+                Ok(path) => println!("Saved the game to {}", path),
+                Err(err) => eprintln!("Could not save the game: {}", err),
+            }
         }
 
         // If a mouse click happened, try to register it a selection or a move
         if let Some(Button::Mouse(MouseButton::Left)) = e.press_args()
         {
+            // Any left click on the board clears the arrows and circles
+            if self._clicked_block(pos, chessview.settings._size, chessview.flipped).is_some()
+            {
+                chessview.game.clear_annotations();
+            }
+
+            if !can_move { return; }
+            if mp { self._click_mp_ingame_handler(pos, chessview); }
+            else { self._click_sp_ingame_handler(pos, chessview); }
+        }
+    }
+
+    // Let the computer play when it's its turn, once the last move is
+    // done animating
+    pub fn computer_move(&mut self, chessview: &mut ChessView)
+    {
+        if !self._computer_to_move(chessview) || chessview.is_animating() { return; }
 
-            // Run the right click event handler based on the game state
-            match self.state
+        let color: Colors = if chessview.game.ply().is_multiple_of(2) { Colors::White } else { Colors::Black };
+        if let Some((from, to)) = computer::choose_move(&chessview.board, color)
+        {
+            self._board_will_change(chessview);
+            position::play_move(&mut chessview.board, &position::square_to_notation(from),
+                                &position::square_to_notation(to), None);
+            self.selected = 255;
+        }
+    }
+
+    fn _computer_to_move(&self, chessview: &ChessView) -> bool
+    {
+        let white_to_move: bool = chessview.game.ply().is_multiple_of(2);
+        match self.mode
+        {
+            GameMode::Computer(color) => (color == Colors::White) == white_to_move && chessview.position.is_none(),
+            _ => false
+        }
+    }
+
+    // Taking a move back keeps it in the game, playing another one from
+    // there makes that a variation
+    fn _undo(&mut self, chessview: &mut ChessView)
+    {
+        if !chessview.game.back() { return; }
+        chessview.game.result = String::from("*");
+        self._show_current_ply(chessview);
+    }
+
+    fn _redo(&mut self, chessview: &mut ChessView)
+    {
+        if chessview.game.forward() { self._show_current_ply(chessview); }
+    }
+
+    fn _show_current_ply(&mut self, chessview: &mut ChessView)
+    {
+        self._board_will_change(chessview);
+        chessview.board = chessview.game.board_at(chessview.game.ply());
+        self.selected = 255;
+        self.board_replaced = true;
+    }
+
+    // Handle what the network worker or the hosted server received
+    pub fn poll_mp(&mut self, chessview: &mut ChessView)
+    {
+        self._poll_network(chessview);
+        self._poll_host(chessview);
+    }
+
+    fn _modifier_event<E: GenericEvent>(&mut self, e: &E)
+    {
+        let (key, held) = match (e.press_args(), e.release_args())
+        {
+            (Some(Button::Keyboard(key)), _) => (key, true),
+            (_, Some(Button::Keyboard(key))) => (key, false),
+            _ => return
+        };
+
+        match key
+        {
+            Key::LShift | Key::RShift => self.shift_held = held,
+            Key::LCtrl | Key::RCtrl => self.ctrl_held = held,
+            Key::LAlt | Key::RAlt => self.alt_held = held,
+            _ => ()
+        }
+    }
+
+    // Right-click marks a square with a circle, right-click dragging draws an
+    // arrow. Green by default, red with Shift, blue with Ctrl, yellow with Alt.
+    // Stored with the current position, so they come back with it
+    fn _annotation_event<E: GenericEvent>(&mut self, pos: [f64; 2], chessview: &mut ChessView, e: &E)
+    {
+        let square_under_cursor = |controller: &ChessViewController| controller
+            ._clicked_block(pos, chessview.settings._size, chessview.flipped)
+            .map(|(block, _)| position::block_to_square(block));
+
+        if let Some(Button::Mouse(MouseButton::Right)) = e.press_args()
+        {
+            self.arrow_start = square_under_cursor(self);
+        }
+
+        if let Some(Button::Mouse(MouseButton::Right)) = e.release_args()
+        {
+            let (from, to) = match (self.arrow_start.take(), square_under_cursor(self))
             {
-                GameState::Intro => self._click_intro_handler(chessview),
-                GameState::SPIngame => self._click_sp_ingame_handler(pos, chessview),
-                GameState::MPIngame => self._click_mp_ingame_handler(pos, chessview),
-                _ => ()
+                (Some(from), Some(to)) => (from, to),
+                _ => return
+            };
+
+            let mark: Mark = if self.shift_held { Mark::Red }
+                             else if self.ctrl_held { Mark::Blue }
+                             else if self.alt_held { Mark::Yellow }
+                             else { Mark::Green };
+
+            let annotations: &mut Annotations = chessview.game.annotations_mut();
+            if from == to { annotations.toggle_circle(to, mark); }
+            else { annotations.toggle_arrow(from, to, mark); }
+        }
+    }
+
+    // Start a game on this screen, going on from the moves of `game`
+    pub fn start_game(&mut self, chessview: &mut ChessView, mode: GameMode, game: Game)
+    {
+        self._reset_board(chessview);
+        chessview.board = game.board_at(game.ply());
+        chessview.game = game;
+        // The human plays from the bottom of the screen
+        chessview.flipped = mode == GameMode::Computer(Colors::White);
+        self.mode = mode;
+        self.autosaving = true;
+        self.mp_game = None;
+    }
+
+    // Back to the intro: disconnect and forget the game, the save file
+    // keeps it for "Continue"
+    pub fn leave_game(&mut self, chessview: &mut ChessView)
+    {
+        self.autosave(chessview);
+        self.autosaving = false;
+        self.mp_game = None;
+        self.net = None;
+        self.host = None;
+        self.mp_connection = false;
+        self._reset_board(chessview);
+        self.mode = GameMode::Local;
+    }
+
+    // Show a game to step through, see `ReplayScreen`
+    pub fn start_replay(&mut self, chessview: &mut ChessView, flipped: bool)
+    {
+        self.leave_game(chessview);
+        chessview.flipped = flipped;
+    }
+
+    // Show `game` after its first `ply` moves, with the arrows and circles
+    // drawn on that position. Not a move, so it isn't recorded
+    pub fn show_ply(&mut self, chessview: &mut ChessView, game: &Game, ply: usize)
+    {
+        self._board_will_change(chessview);
+        chessview.board = game.board_at(ply);
+        // After an underpromotion the engine has a queen instead
+        let squares: position::Squares = game.positions()[ply];
+        chessview.position = if squares != position::snapshot(&chessview.board) { Some(squares) } else { None };
+        chessview.game = game.clone();
+        chessview.game.go_to(ply);
+        self.board_replaced = true;
+    }
+
+    // A new board isn't a move, nothing gets animated
+    fn _reset_board(&mut self, chessview: &mut ChessView)
+    {
+        self.board_before = None;
+        chessview.board = ChessEngine::new();
+        chessview.position = None;
+        chessview.game = Game::new();
+        chessview.flipped = false;
+        self.selected = 255;
+        self.move_input.clear();
+        self.move_error = None;
+        self.key_cursor = None;
+        self.move_list_open = false;
+    }
+
+    // Show the settings screen with the themes and piece sets found in assets
+    pub fn open_settings(&mut self)
+    {
+        self.settings_themes = settings_file::list_themes();
+        self.settings_piece_sets = settings_file::list_piece_sets();
+        self.settings_status = String::new();
+    }
+
+    // Start a fresh MP game on the board
+    fn _reset_mp(&mut self, chessview: &mut ChessView)
+    {
+        self._reset_board(chessview);
+        self.mode = GameMode::Online;
+        self.autosaving = false;
+        self.mp_game = None;
+        self.mp_connection = false;
+        self.mp_capabilities = Vec::new();
+        self.mp_is_white = None;
+        self.mp_white_to_move = true;
+        self.mp_plies = 0;
+        self.mp_selected = None;
+        self.mp_pending_move = None;
+        self.chat = Chat::new();
+    }
+
+    // Act as the server: listen on "host-port" from config/server.json and
+    // play as "host-color" against the first client that joins
+    pub fn start_hosting(&mut self, chessview: &mut ChessView)
+    {
+        self._reset_mp(chessview);
+
+        let config = _read_config().unwrap_or(json::JsonValue::Null);
+        let port: u16 = config["host-port"].as_u16().unwrap_or(1337);
+        let host_is_white: bool = config["host-color"].as_str() != Some("black");
+
+        match HostServer::start(port, config["game-id"].as_u64().unwrap_or(0), host_is_white,
+                                String::from(position::START_FEN))
+        {
+            Ok(host) => {
+                self.mp_status = format!("Hosting on port {}\nWaiting for an opponent..", host.port);
+                self.mp_is_white = Some(host_is_white);
+                chessview.flipped = !host_is_white;
+                // The host implements everything itself
+                self.mp_capabilities = version::CAPABILITIES.iter().map(|cap| cap.to_string()).collect();
+                self.host = Some(host);
+            },
+            Err(err) => self.mp_status = format!("Cannot host a game\n{}", err)
+        }
+    }
+
+    // Show the lobby and start listening for games announced on the LAN
+    pub fn open_lobby(&mut self)
+    {
+        self.lobby_status = String::new();
+
+        // The configured server is always listed so it can be joined
+        // even when it doesn't announce itself
+        match _configured_game()
+        {
+            Ok(game) => self.lobby_config_game = Some(game),
+            Err(reason) => {
+                self.lobby_config_game = None;
+                self.lobby_status = reason;
             }
+        }
 
+        match Discovery::listen()
+        {
+            Ok(discovery) => self.discovery = Some(discovery),
+            Err(err) => {
+                self.discovery = None;
+                // Shown next to a config error, only one program can
+                // listen on the discovery port
+                let reason: String = if err.kind() == io::ErrorKind::AddrInUse
+                {
+                    format!("LAN discovery unavailable: port {} is used by another program", discovery::DISCOVERY_PORT)
+                }
+                else
+                {
+                    format!("LAN discovery unavailable: {}", err)
+                };
+                if !self.lobby_status.is_empty() { self.lobby_status.push_str(" - "); }
+                self.lobby_status.push_str(&reason);
+            }
+        }
 
+        self.poll_lobby();
+    }
+
+    pub fn poll_lobby(&mut self)
+    {
+        let mut games: Vec<DiscoveredGame> = self.lobby_config_game.iter().cloned().collect();
+
+        if let Some(discovery) = &mut self.discovery
+        {
+            games.extend(discovery.poll());
         }
+
+        self.lobby_games = games;
     }
 
-    fn _click_intro_handler(&mut self, chessview: &ChessView)
+    // Connect to a game picked in the lobby. Full games are joined as spectator
+    pub fn join_game(&mut self, game: &DiscoveredGame, chessview: &mut ChessView)
     {
-        let x: f64 = self.cursor_pos[0];
-        let y: f64 = self.cursor_pos[1];
-        let b_sp_xy: [f64; 2] = chessview.settings._intro_sp_button_pos;
-        let b_mp_xy: [f64; 2] = chessview.settings._intro_mp_button_pos;
-        let b_wh: [f64; 2] = chessview.settings._intro_button_size;
+        let spectate: bool = game.players >= 2 || _read_config()
+            .map(|config| config["spectate"].as_bool().unwrap_or(false))
+            .unwrap_or(false);
+        self.connect(game, spectate, chessview);
+    }
+
+    // Connect to a game as a player, or as a spectator
+    pub fn connect(&mut self, game: &DiscoveredGame, spectate: bool, chessview: &mut ChessView)
+    {
+        self._reset_mp(chessview);
+        self.discovery = None;
+        self.mp_status = String::from("Waiting for connection..");
 
-        // Calculate if click pos inside the bounds of the SP button
-        let clicked_sp: bool = 0.0 <= x - b_sp_xy[0] && x - b_sp_xy[0] <= b_wh[0]
-                                && 0.0 <= y - b_sp_xy[1] && y - b_sp_xy[1] <= b_wh[1];
+        let request = version::connect_request(game.game_id, spectate);
 
+        // Connect to server & initialize the connection as detailed in `src/inet/chess.proto`
+        self.net = Some(NetClient::connect(game.transport, game.ip_port.clone(), request));
 
-        // Calculate if click pos inside the bounds of the MP button
-        let clicked_mp: bool = 0.0 <= x - b_mp_xy[0] && x - b_mp_xy[0] <= b_wh[0]
-                                && 0.0 <= y - b_mp_xy[1] && y - b_mp_xy[1] <= b_wh[1];
+        self.mp_game = Some((game.clone(), spectate));
+        self.autosaving = true;
+        self.autosave(chessview);
+    }
+
+    // Connect to a server typed in the lobby as "host:port", with the game
+    // id and transport of config/server.json
+    pub fn join_address(&mut self, address: &str, chessview: &mut ChessView) -> Result<(), String>
+    {
+        let valid: bool = address.rsplit_once(':')
+            .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok());
+        if !valid { return Err(format!("\"{}\" is not a host:port address", address)); }
 
+        let config = _read_config().unwrap_or(json::JsonValue::Null);
+        let game: DiscoveredGame = DiscoveredGame{
+            name: address.to_string(),
+            ip_port: address.to_string(),
+            transport: TransportKind::from_config(config["transport"].as_str().unwrap_or("tcp"))?,
+            game_id: config["game-id"].as_u64().unwrap_or(0),
+            players: 0,
+            spectators: 0,
+        };
+        self.join_game(&game, chessview);
+        Ok(())
+    }
+
+    // Handle everything the network worker received since the last event
+    fn _poll_network(&mut self, chessview: &mut ChessView)
+    {
+        let mut disconnected: Option<String> = None;
+
+        let mut events: Vec<NetEvent> = Vec::new();
+        if let Some(net) = &self.net
+        {
+            while let Some(event) = net.poll() { events.push(event); }
+        }
 
-        // Change the game state to either mp or sp based on the button clicked
-        if clicked_sp { self.state = GameState::SPIngame; }
-        if clicked_mp { 
-            self.state = GameState::MPIngame; 
-            
-            let _config_str = fs::read_to_string("config/server.json")
-                                                    .expect("CANNOT OPEN MAIN CONFIG FILE");
+        for event in events
+        {
+            match event
+            {
+                NetEvent::Received(items::s2c_message::Msg::ConnectAck(ack)) => {
+                    // Refuse servers we can't understand instead of
+                    // misinterpreting their messages
+                    match version::check_ack(&ack)
+                    {
+                        Ok(capabilities) => {
+                            self.mp_capabilities = capabilities;
+                            self.mp_is_white = ack.client_is_white;
+                            self.mp_connection = true;
+                            // Black plays from the bottom of the screen
+                            chessview.flipped = ack.client_is_white == Some(false);
+                            // The game may be under way already
+                            if let Some(board) = &ack.starting_position
+                            {
+                                self._follow_server(chessview, board);
+                            }
+                        },
+                        Err(reason) => disconnected = Some(reason)
+                    }
+                },
+                // The opponent's move (or any move while spectating)
+                NetEvent::Received(items::s2c_message::Msg::Move(mv)) => {
+                    self._apply_mp_move(chessview, &mv);
+                },
+                // The server's verdict on our own move, and the position after it
+                NetEvent::Received(items::s2c_message::Msg::MoveAck(ack)) => {
+                    if let Some(mv) = self.mp_pending_move.take()
+                    {
+                        if ack.legal { self._apply_mp_move(chessview, &mv); }
+                    }
+                    if let Some(board) = &ack.board_result
+                    {
+                        self._follow_server(chessview, board);
+                    }
+                },
+                NetEvent::Received(items::s2c_message::Msg::Chat(chat)) => {
+                    self.chat.push(chat.sender.as_deref().unwrap_or("?"), &chat.text);
+                },
+                NetEvent::Disconnected(reason) => {
+                    // Keep the more specific reason if there already is one
+                    if disconnected.is_none()
+                    {
+                        disconnected = Some(format!("Disconnected\n{}", reason));
+                    }
+                },
+            }
+        }
 
-            let config = json::parse(_config_str.as_str()).unwrap();
-            // Connect to server & initialize the connection as detailed in `src/inet/chess.proto`
-            _connect2server_and_init(config["ip-port"].as_str().unwrap());
+        if let Some(reason) = disconnected
+        {
+            self.net = None;
+            self.mp_connection = false;
+            self.mp_status = reason;
         }
     }
 
-    fn _click_sp_ingame_handler(&mut self, pos: [f64; 2], chessview: &mut ChessView)
+    // Handle everything the hosted server thread reported since the last event
+    fn _poll_host(&mut self, chessview: &mut ChessView)
     {
-        // Total board size
-        let size: f64 = chessview.settings._size;
+        let mut events: Vec<HostEvent> = Vec::new();
+        if let Some(host) = &self.host
+        {
+            while let Some(event) = host.poll() { events.push(event); }
+        }
 
-        // x and y coordinates relative to the actual chess board
-        let x: f64 = self.cursor_pos[0] - pos[0];
-        let y: f64 = self.cursor_pos[1] - pos[1];
+        for event in events
+        {
+            match event
+            {
+                HostEvent::OpponentJoined => self.mp_connection = true,
+                HostEvent::OpponentLeft => {
+                    self.mp_connection = false;
+                    self.mp_status = String::from("The opponent left\nWaiting for an opponent..");
+                },
+                HostEvent::Move(mv) => {
+                    // Validate the opponent's move with our own engine: it has
+                    // to be their turn, their piece and a move the engine accepts
+                    let opponent_white: bool = self.mp_is_white != Some(true);
 
-        // If not inside - just leave
-        if !(x>=0.0 && x < size && y >= 0.0 && y < size) { return; }
+                    let legal: bool = mv.from_square < 64 && mv.to_square < 64
+                        && self.mp_white_to_move == opponent_white
+                        && self._is_own_piece(chessview, mv.from_square, opponent_white)
+                        && self._apply_mp_move(chessview, &mv);
 
-        // Compute the actual block in the chess board
-        let block_x: u8 = (x / size * 8.0) as u8;
-        let block_y: u8 = (y / size * 8.0) as u8;
+                    let fen: String = self._mp_fen(chessview);
+                    if let Some(host) = &self.host
+                    {
+                        host.send(HostCommand::MoveResult{mv, legal, fen});
+                    }
+                },
+                HostEvent::Chat(chat) => {
+                    self.chat.push(chat.sender.as_deref().unwrap_or("?"), &chat.text);
+                }
+            }
+        }
+    }
 
-        let u8_x_to_str: [&str; 8] = ["a","b","c","d","e","f","g","h"];
-        let u8_y_to_str: [&str; 8] = ["1","2","3","4","5","6","7","8"];
+    // Play a move accepted by the server (or by us as host) on the board.
+    // The engine picks the promotion, see `position::play_move`
+    fn _apply_mp_move(&mut self, chessview: &mut ChessView, mv: &items::Move) -> bool
+    {
+        let (from, to) = (mv.from_square, mv.to_square);
+        if from >= 64 || to >= 64 { return false; }
 
-        let block_x_str: &str = u8_x_to_str[block_x as usize];
-        let block_y_str: &str = u8_y_to_str[7-block_y as usize];
-        let block_coords_str: String = format!("{}{}", block_x_str, block_y_str);
+        let before: position::Squares = chessview.squares();
+        let promotion: Option<PieceTypes> = if !position::is_promotion(&before, from, to) { None }
+            // Servers that leave it out mean a queen, like a click
+            else { inet::promotion_from_proto(mv.promotion).or(Some(PieceTypes::Queen)) };
+
+        match chessview.position
+        {
+            // Following the server's position, the server checked the move
+            Some(_) => {
+                if before[from as usize].is_none() { return false; }
+                let after: position::Squares = position::apply_move(&before, from, to, promotion);
+                chessview.position = Some(after);
+
+                let duration: Duration = Duration::from_millis(chessview.settings._animation_ms as u64);
+                chessview.animation = Animation::between(&before, &after, duration);
+
+                let to_move: Colors = if self.mp_white_to_move { Colors::Black } else { Colors::White };
+                if let Some(result) = position::position_result(&after, to_move, Some((from, to)))
+                {
+                    chessview.game.result = result.to_string();
+                }
+            },
+            None => {
+                self._board_will_change(chessview);
+                if !position::play_move(&mut chessview.board, &position::square_to_notation(from),
+                                        &position::square_to_notation(to), promotion)
+                {
+                    return false;
+                }
+            }
+        }
+
+        self.mp_white_to_move = !self.mp_white_to_move;
+        self.mp_plies += 1;
+        true
+    }
+
+    // The server has the real position. When ours differs, e.g. on joining a
+    // game under way, the board shows the server's squares from then on
+    // since the engine can't be set up from them. Such games aren't recorded
+    fn _follow_server(&mut self, chessview: &mut ChessView, board: &items::BoardState)
+    {
+        let (squares, white_to_move) = match position::from_fen(&board.fen_string)
+        {
+            Ok(parsed) => parsed,
+            Err(err) => { eprintln!("The server sent an invalid position: {}", err); return; }
+        };
+
+        self.mp_white_to_move = white_to_move;
+        if squares != chessview.squares()
+        {
+            chessview.position = Some(squares);
+            chessview.animation = None;
+        }
+    }
+
+    fn _mp_fen(&self, chessview: &ChessView) -> String
+    {
+        position::to_fen(&position::snapshot(&chessview.board), self.mp_white_to_move,
+                         self.mp_plies/2 + 1)
+    }
+
+    fn _is_own_piece(&self, chessview: &ChessView, square: u32, white: bool) -> bool
+    {
+        match chessview.squares().get(square as usize)
+        {
+            Some(Some((_, color))) => (*color == Colors::White) == white,
+            _ => false
+        }
+    }
+
+    // Chat panel input: Tab toggles the panel, typed text goes to the input
+    // line while it is open, Enter sends it and the wheel scrolls the history
+    fn _chat_event<E: GenericEvent>(&mut self, chessview: &ChessView, e: &E)
+    {
+        if !self.mp_connection { return; }
+
+        // The server (or this connection) might not support chat
+        if !self.mp_capabilities.iter().any(|cap| cap == version::CAP_CHAT) { return; }
+
+        match e.press_args()
+        {
+            Some(Button::Keyboard(Key::Tab)) => {
+                self.chat.open = !self.chat.open;
+                return;
+            },
+            Some(Button::Keyboard(Key::Backspace)) if self.chat.open => {
+                self.chat.backspace();
+            },
+            Some(Button::Keyboard(Key::Return)) if self.chat.open => {
+                if let Some(text) = self.chat.take_message()
+                {
+                    if let Some(net) = &self.net
+                    {
+                        // Not echoed locally, the server relays it back to us
+                        net.send(items::c2s_message::Msg::Chat(
+                            items::ChatMessage{text, sender: None}));
+                    }
+                    else if let Some(host) = &self.host
+                    {
+                        let sender: &str = if self.mp_is_white == Some(true) { "White" } else { "Black" };
+                        self.chat.push(sender, &text);
+                        host.send(HostCommand::Chat(text));
+                    }
+                }
+            },
+            _ => ()
+        }
+
+        if !self.chat.open { return; }
+
+        if let Some(text) = e.text_args()
+        {
+            self.chat.type_text(&text);
+        }
+
+        if let Some(scroll) = e.mouse_scroll_args()
+        {
+            let [x, y, w, h] = chessview.settings.chat_rect();
+            let [cx, cy] = self.ui.cursor;
+            if cx >= x && cx < x+w && cy >= y && cy < y+h
+            {
+                self.chat.scroll_by(scroll[1] as i32);
+            }
+        }
+    }
+
+    fn _click_sp_ingame_handler(&mut self, pos: [f64; 2], chessview: &mut ChessView)
+    {
+        // If not inside - just leave
+        if let Some((block, block_coords_str)) = self._clicked_block(pos, chessview.settings._size, chessview.flipped)
+        {
+            self._sp_block_pressed(chessview, block, block_coords_str);
+        }
+    }
+
+    // A block was clicked (or picked with the keyboard cursor) in SP
+    fn _sp_block_pressed(&mut self, chessview: &mut ChessView, block: u8, block_coords_str: String)
+    {
 
         match chessview.board.get_selected()
         {
             // If there's a piece selected make the move if possible
             Some(_from) => {
+                self._board_will_change(chessview);
                 chessview.board.play_selected_piece_with_notation(
                                     block_coords_str.as_str());
                 
@@ -140,10 +928,16 @@ impl ChessViewController
                                                         block_coords_str.as_str());
                 // If the target block selected is not empty, select it and register 
                 // the coordinates
-                if let Some(_item) = item
+                // The computer's pieces aren't ours to move
+                let computers: bool = match (item, self.mode)
+                {
+                    (Some(piece), GameMode::Computer(color)) => piece.color == color,
+                    _ => false
+                };
+                if let (Some(_item), false) = (item, computers)
                 {
                     // Set the coordinate of the selected block
-                    self.selected = block_x+8*block_y;
+                    self.selected = block;
                     chessview.board.select_piece_notation(block_coords_str.as_str());
                 }
             }
@@ -154,14 +948,364 @@ impl ChessViewController
     {
         // Don't allow any moves when connection to server is not established
         if !self.mp_connection { return; }
+
+        // Spectators can't move, and players only on their turn
+        let is_white: bool = match self._mp_can_move() { Ok(is_white) => is_white, Err(_) => return };
+
+        if let Some((block, notation)) = self._clicked_block(pos, chessview.settings._size, chessview.flipped)
+        {
+            self._mp_block_pressed(chessview, block, notation, is_white);
+        }
+    }
+
+    // A block was clicked (or picked with the keyboard cursor) in MP, while
+    // it's our turn
+    fn _mp_block_pressed(&mut self, chessview: &mut ChessView, block: u8, notation: String, is_white: bool)
+    {
+        // Clicking one of our pieces (re)selects it
+        if position::notation_to_square(&notation).is_some_and(|square| self._is_own_piece(chessview, square, is_white))
+        {
+            self.mp_selected = Some(notation);
+            self.selected = block;
+            return;
+        }
+
+        let from: String = match self.mp_selected.take() { Some(from) => from, None => return };
+        self.selected = 255;
+        self._mp_play(chessview, from, notation, None);
+    }
+
+    // Play our move, through our own engine when hosting, otherwise by
+    // asking the server
+    fn _mp_play(&mut self, chessview: &mut ChessView, from: String, notation: String, promotion: Option<PieceTypes>)
+    {
+        let from: u32 = position::notation_to_square(&from).unwrap();
+        let to: u32 = position::notation_to_square(&notation).unwrap();
+        // A click promotes to a queen, the server is told which piece
+        let promotion: Option<PieceTypes> = if position::is_promotion(&chessview.squares(), from, to)
+        {
+            promotion.or(Some(PieceTypes::Queen))
+        }
+        else { None };
+        let mv = items::Move{from_square: from, to_square: to, promotion: inet::promotion_to_proto(promotion)};
+
+        if self.host.is_some()
+        {
+            // We are the server, our own engine decides
+            if self._apply_mp_move(chessview, &mv)
+            {
+                let fen: String = self._mp_fen(chessview);
+                if let Some(host) = &self.host
+                {
+                    host.send(HostCommand::HostMove{mv, fen});
+                }
+            }
+        }
+        else if let Some(net) = &self.net
+        {
+            // Only played once the server accepts it
+            net.send(items::c2s_message::Msg::Move(mv.clone()));
+            self.mp_pending_move = Some(mv);
+        }
+    }
+
+    // Our color if we may move now, otherwise why not
+    fn _mp_can_move(&self) -> Result<bool, String>
+    {
+        if !self.mp_connection { return Err(String::from("Not connected")); }
+
+        let is_white: bool = self.mp_is_white.ok_or(String::from("Spectators can't move"))?;
+        if is_white != self.mp_white_to_move { return Err(String::from("Not your turn")); }
+        if self.mp_pending_move.is_some() { return Err(String::from("Waiting for the server")); }
+        Ok(is_white)
+    }
+
+    // Typing a move, and moving a cursor over the blocks with the arrow
+    // keys, Enter or Space then presses the block under it. Used while the
+    // chat is closed
+    fn _keyboard_event<E: GenericEvent>(&mut self, chessview: &mut ChessView, e: &E, mp: bool)
+    {
+        if self.chat.open { return; }
+
+        // Ctrl+S and the like aren't typing
+        if let (Some(text), false) = (e.text_args(), self.ctrl_held || self.alt_held)
+        {
+            self.type_move(&text);
+        }
+
+        let key: Key = match e.press_args() { Some(Button::Keyboard(key)) => key, _ => return };
+        match key
+        {
+            Key::Backspace => {
+                self.move_input.pop();
+                self.move_error = None;
+            },
+            Key::Escape => {
+                self.move_input.clear();
+                self.move_error = None;
+                self.key_cursor = None;
+            },
+            Key::Return if !self.move_input.is_empty() => self._submit_move_input(chessview, mp),
+            Key::Return | Key::Space => {
+                if let Some(block) = self.key_cursor
+                {
+                    let notation: String = position::square_to_notation(position::block_to_square(block));
+                    if !mp { self._sp_block_pressed(chessview, block, notation); }
+                    else
+                    {
+                        match self._mp_can_move()
+                        {
+                            Ok(is_white) => self._mp_block_pressed(chessview, block, notation, is_white),
+                            Err(reason) => self.move_error = Some(reason),
+                        }
+                    }
+                }
+            },
+            // With Ctrl they're for the variations
+            Key::Up | Key::Down | Key::Left | Key::Right if !self.ctrl_held => {
+                // Moved on screen, so from black's side up is towards rank 1
+                let drawn: u8 = match self.key_cursor
+                {
+                    Some(block) => if chessview.flipped { 63 - block } else { block },
+                    // Start on the e-file in front of the player
+                    None => 52,
+                };
+                let (mut x, mut y) = ((drawn % 8) as i32, (drawn / 8) as i32);
+                match key
+                {
+                    Key::Up => y -= 1,
+                    Key::Down => y += 1,
+                    Key::Left => x -= 1,
+                    _ => x += 1,
+                }
+                let drawn: u8 = (x.clamp(0, 7) + 8 * y.clamp(0, 7)) as u8;
+                self.key_cursor = Some(if chessview.flipped { 63 - drawn } else { drawn });
+            },
+            _ => ()
+        }
+    }
+
+    // Add what was typed to the move, leaving out what can't be part of one
+    pub fn type_move(&mut self, text: &str)
+    {
+        for ch in text.chars().filter(|ch| ch.is_ascii_alphanumeric() || "-=+#x".contains(*ch))
+        {
+            if self.move_input.len() < MAX_MOVE_INPUT { self.move_input.push(ch); }
+        }
+        self.move_error = None;
     }
+
+    // Play the typed move the same way a click would, or say what's wrong
+    // with it below the board
+    fn _submit_move_input(&mut self, chessview: &mut ChessView, mp: bool)
+    {
+        let white_to_move: bool = if mp
+        {
+            match self._mp_can_move()
+            {
+                Ok(is_white) => is_white,
+                Err(reason) => { self.move_error = Some(reason); return; }
+            }
+        }
+        // Every SP move is recorded in the game
+        else { chessview.game.ply().is_multiple_of(2) };
+
+        let squares: position::Squares = chessview.squares();
+        let candidates: Vec<(u32, u32, Option<PieceTypes>)> = match position::parse_move(&squares, white_to_move, &self.move_input)
+        {
+            Ok(candidates) => candidates,
+            Err(reason) => { self.move_error = Some(reason); return; }
+        };
+
+        // Without the engine when following the server's position
+        let followed: Option<Vec<(u32, u32)>> = chessview.position
+            .map(|squares| position::legal_moves(&squares, if white_to_move { Colors::White } else { Colors::Black }, None));
+        let legal: Vec<(u32, u32, Option<PieceTypes>)> = candidates.into_iter()
+            .filter(|(from, to, _)| match &followed
+            {
+                Some(moves) => moves.contains(&(*from, *to)),
+                None => position::is_legal(&chessview.board, *from, *to),
+            })
+            .collect();
+
+        let (from, to, promotion) = match legal.as_slice()
+        {
+            [mv] => *mv,
+            [] => { self.move_error = Some(format!("{} is not a legal move", self.move_input)); return; },
+            _ => {
+                self.move_error = Some(format!("{} is ambiguous, add the file or rank of the piece", self.move_input));
+                return;
+            }
+        };
+
+        // Our engine always promotes to a queen, like a click does. The
+        // server of a joined game takes any piece
+        let engine_plays: bool = !mp || self.host.is_some();
+        if engine_plays && promotion.is_some_and(|piece_type| piece_type != PieceTypes::Queen)
+        {
+            self.move_error = Some(String::from("Pawns can only promote to a queen here"));
+            return;
+        }
+
+        let (from, to) = (position::square_to_notation(from), position::square_to_notation(to));
+        self.selected = 255;
+        if mp
+        {
+            self.mp_selected = None;
+            self._mp_play(chessview, from, to, promotion);
+        }
+        else
+        {
+            self._board_will_change(chessview);
+            position::play_move(&mut chessview.board, &from, &to, promotion);
+        }
+
+        self.move_input.clear();
+        self.move_error = None;
+    }
+
+    // The block of the board under the cursor, see `_clicked_block`
+    pub fn block_under_cursor(&self, chessview: &ChessView) -> Option<u8>
+    {
+        self._clicked_block(chessview.settings.board_pos(), chessview.settings._size, chessview.flipped)
+            .map(|(block, _)| block)
+    }
+
+    // The block under the cursor as its index (0 = a8, 63 = h1, the order of
+    // `get_board`) and its notation, `None` if the cursor is outside of the
+    // board. `flipped` is the board orientation, see `ChessView::flipped`
+    fn _clicked_block(&self, pos: [f64; 2], size: f64, flipped: bool) -> Option<(u8, String)>
+    {
+        // x and y coordinates relative to the actual chess board
+        let x: f64 = self.ui.cursor[0] - pos[0];
+        let y: f64 = self.ui.cursor[1] - pos[1];
+
+        if !(x>=0.0 && x < size && y >= 0.0 && y < size) { return None; }
+
+        // Compute the actual block in the chess board
+        let mut block_x: u8 = (x / size * 8.0) as u8;
+        let mut block_y: u8 = (y / size * 8.0) as u8;
+
+        // Seen from black's side, the top left block is h1
+        if flipped
+        {
+            block_x = 7 - block_x;
+            block_y = 7 - block_y;
+        }
+
+        let u8_x_to_str: [&str; 8] = ["a","b","c","d","e","f","g","h"];
+        let u8_y_to_str: [&str; 8] = ["1","2","3","4","5","6","7","8"];
+
+        let block_x_str: &str = u8_x_to_str[block_x as usize];
+        let block_y_str: &str = u8_y_to_str[7-block_y as usize];
+        Some((block_x+8*block_y, format!("{}{}", block_x_str, block_y_str)))
+    }
+}
+
+// Write the game to pgn/game-<unix time>.pgn, returns the path
+fn _export_pgn(game: &Game) -> Result<String, String>
+{
+    fs::create_dir_all(PGN_DIR).map_err(|err| err.to_string())?;
+
+    let time: u64 = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let path: String = format!("{}/game-{}.pgn", PGN_DIR, time);
+    fs::write(&path, game.to_pgn()).map_err(|err| err.to_string())?;
+    Ok(path)
 }
 
-fn _connect2server_and_init(ip_port: &str)
+fn _read_config() -> Result<json::JsonValue, String>
+{
+    let _config_str = fs::read_to_string("config/server.json")
+                            .map_err(|err| format!("Cannot open config/server.json: {}", err))?;
+
+    json::parse(_config_str.as_str())
+        .map_err(|err| format!("Bad config/server.json: {}", err))
+}
+
+// The game set up in config/server.json, shown first in the lobby
+fn _configured_game() -> Result<DiscoveredGame, String>
+{
+    let config = _read_config()?;
+
+    // "tcp" (the default) or "ws" for a WebSocket connection
+    let transport: TransportKind = TransportKind::from_config(
+        config["transport"].as_str().unwrap_or("tcp"))?;
+
+    Ok(DiscoveredGame{
+        name: String::from("config/server.json"),
+        ip_port: config["ip-port"].as_str().ok_or("No \"ip-port\" in config/server.json")?.to_string(),
+        transport,
+        game_id: config["game-id"].as_u64().unwrap_or(0),
+        // Unknown until connected
+        players: 0,
+        spectators: 0,
+    })
+}
+#[cfg(test)]
+mod tests
 {
-    let mut stream = TcpStream::connect(ip_port)
-                                    .expect("CANNOT CONNECT TO SERVER!");
-    //....
-    // follow the `chess.proto` to init a successfull connection
-       
-}
\ No newline at end of file
+    use super::*;
+    use std::net::TcpListener;
+    use std::time::Instant;
+    use crate::ChessViewSettings;
+    use crate::inet::transport::{Transport, TcpTransport};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn _recv(transport: &mut dyn Transport) -> items::c2s_message::Msg
+    {
+        let start: Instant = Instant::now();
+        while start.elapsed() < TIMEOUT
+        {
+            if let Some(body) = transport.recv().unwrap()
+            {
+                return inet::decode_c2s(body.as_slice()).unwrap().msg.unwrap();
+            }
+        }
+        panic!("nothing received in {:?}", TIMEOUT);
+    }
+
+    #[test]
+    fn typed_underpromotion_is_sent_to_the_server()
+    {
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address: String = listener.local_addr().unwrap().to_string();
+
+        let mut controller: ChessViewController = ChessViewController::new();
+        let mut chessview: ChessView = ChessView::new(ChessViewSettings::new());
+        controller.mode = GameMode::Online;
+        controller.net = Some(NetClient::connect(TransportKind::Tcp, address, version::connect_request(0, false)));
+
+        // A game under way, white to move can promote on e8
+        let (stream, _) = listener.accept().unwrap();
+        let mut server: TcpTransport = TcpTransport::from_stream(stream).unwrap();
+        assert!(matches!(_recv(&mut server), items::c2s_message::Msg::ConnectRequest(_)));
+        let ack = items::S2cConnectAck{
+            success: true,
+            game_id: Some(0),
+            starting_position: Some(items::BoardState{fen_string: String::from("8/4P3/8/8/8/8/k7/4K3 w - - 0 40")}),
+            client_is_white: Some(true),
+            protocol_version: version::PROTOCOL_VERSION,
+            ..items::S2cConnectAck::default()
+        };
+        server.send(&inet::encode_s2c(items::s2c_message::Msg::ConnectAck(ack))).unwrap();
+
+        let start: Instant = Instant::now();
+        while !controller.mp_connection && start.elapsed() < TIMEOUT { controller.poll_mp(&mut chessview); }
+        assert!(chessview.position.is_some());
+
+        controller.type_move("e8=N");
+        controller._submit_move_input(&mut chessview, true);
+        assert_eq!(controller.move_error, None);
+
+        match _recv(&mut server)
+        {
+            items::c2s_message::Msg::Move(mv) => {
+                assert_eq!((mv.from_square, mv.to_square), (position::notation_to_square("e7").unwrap(),
+                                                            position::notation_to_square("e8").unwrap()));
+                assert!(inet::promotion_from_proto(mv.promotion) == Some(PieceTypes::Knight));
+            },
+            _ => panic!("expected a move"),
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use std::fs;
+use std::thread;
+
+use chess::colors::Colors;
+
+use crate::{ChessView, ChessViewSettings, ChessViewController, VIEW_CONFIG};
+use crate::chessview_controller::GameMode;
+use crate::game::Game;
+use crate::inet::discovery::DiscoveredGame;
+use crate::inet::transport::{self, TransportKind};
+use crate::position;
+use crate::render;
+use crate::screen::{ScreenStack, IntroScreen, GameScreen, ReplayScreen, PuzzleScreen};
+use crate::settings_file::PREFERENCES;
+
+// A command run instead of the GUI, gets the arguments after its name
+pub type Command = fn(&[String]) -> Result<(), String>;
+
+pub const USAGE: &str = "\
+Usage: chess-gui [options]
+       chess-gui COMMAND [options]
+
+Starts on the intro screen, unless one of these is given:
+  --sp                  A game with both sides played on this screen
+  --computer COLOR      A game against the computer, which plays COLOR (white or black)
+  --analysis            Both sides on this screen, Ctrl+Z/Ctrl+Y take moves back and forth
+  --replay              Step through the --pgn game, nothing can be played
+  --puzzles FILE        Solve the puzzles of FILE, a CSV like the Lichess puzzle database
+                        (the Puzzles button uses assets/puzzles/puzzles.csv)
+  --server HOST:PORT    Join the MP game on this server
+    --game-id N         Game to join on the server (default: 0)
+    --transport KIND    tcp (the default) or ws
+    --spectate          Watch the game instead of playing
+
+Options:
+  --pgn FILE            Go on from the end of the first game in FILE (--analysis by default)
+  --fen FEN             Show this position, only with --analysis: the engine can't
+                        play from it, but arrows and circles can be drawn
+  --config FILE         Settings file (default: config/view.json)
+  --theme NAME          Board theme from assets/themes
+  --flip                Board seen from black's side
+  --help                Show this
+
+Commands, each with its own --help:
+  render-png            Draw a position to a PNG
+  render-gif            Animate a PGN game as a GIF
+  relay                 Let WebSocket clients play on a TCP server";
+
+const RELAY_USAGE: &str = "\
+Usage: chess-gui relay --listen ADDRESS --upstream HOST:PORT
+  --listen ADDRESS      Where WebSocket clients connect, e.g. 0.0.0.0:8080
+  --upstream HOST:PORT  The TCP server they play on";
+
+const COMMANDS: [(&str, Command, &str); 3] = [
+    ("render-png", render::render_png_command, render::RENDER_PNG_USAGE),
+    ("render-gif", render::render_gif_command, render::RENDER_GIF_USAGE),
+    ("relay", _relay_command, RELAY_USAGE),
+];
+
+// What the command line asks for
+pub enum Cli
+{
+    Gui(StartOptions),
+    Command(Command, Vec<String>),
+    // Text to print, the usage of the GUI or of a command
+    Help(&'static str),
+}
+
+pub enum StartMode
+{
+    Intro,
+    Game(GameMode),
+    // The server's game and whether to spectate it
+    Join(DiscoveredGame, bool),
+    Replay,
+    // The puzzle file
+    Puzzles(String),
+}
+
+pub struct StartOptions
+{
+    pub mode: StartMode,
+    pub pgn: Option<String>,
+    pub fen: Option<String>,
+    pub config: String,
+    pub theme: Option<String>,
+    pub flipped: bool,
+}
+
+// The arguments after the program name
+pub fn parse(args: &[String]) -> Result<Cli, String>
+{
+    if let Some((_, command, usage)) = COMMANDS.iter().find(|(name, _, _)| args.first() == Some(&name.to_string()))
+    {
+        if args.iter().any(|arg| arg == "--help") { return Ok(Cli::Help(usage)); }
+        return Ok(Cli::Command(*command, args[1..].to_vec()));
+    }
+
+    let mut options: StartOptions = StartOptions{
+        mode: StartMode::Intro,
+        pgn: None,
+        fen: None,
+        config: String::from(VIEW_CONFIG),
+        theme: None,
+        flipped: false,
+    };
+    let mut modes: Vec<&str> = Vec::new();
+    let mut server: Option<String> = None;
+    let mut game_id: Option<u64> = None;
+    let mut transport: Option<TransportKind> = None;
+    let mut spectate: bool = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next()
+    {
+        let mut value = || args.next().cloned().ok_or(format!("{} needs a value\n{}", arg, USAGE));
+        match arg.as_str()
+        {
+            "--help" => return Ok(Cli::Help(USAGE)),
+            "--sp" => {
+                modes.push("--sp");
+                options.mode = StartMode::Game(GameMode::Local);
+            },
+            "--computer" => {
+                modes.push("--computer");
+                let color: Colors = match value()?.as_str()
+                {
+                    "white" => Colors::White,
+                    "black" => Colors::Black,
+                    other => return Err(format!("--computer plays white or black, not \"{}\"", other)),
+                };
+                options.mode = StartMode::Game(GameMode::Computer(color));
+            },
+            "--analysis" => {
+                modes.push("--analysis");
+                options.mode = StartMode::Game(GameMode::Analysis);
+            },
+            "--replay" => {
+                modes.push("--replay");
+                options.mode = StartMode::Replay;
+            },
+            "--puzzles" => {
+                modes.push("--puzzles");
+                options.mode = StartMode::Puzzles(value()?);
+            },
+            "--server" => {
+                modes.push("--server");
+                server = Some(value()?);
+            },
+            "--game-id" => game_id = Some(value()?.parse::<u64>()
+                .map_err(|_| String::from("--game-id must be a whole number"))?),
+            "--transport" => transport = Some(TransportKind::from_config(&value()?)?),
+            "--spectate" => spectate = true,
+            "--pgn" => options.pgn = Some(value()?),
+            "--fen" => options.fen = Some(value()?),
+            "--config" => options.config = value()?,
+            "--theme" => options.theme = Some(value()?),
+            "--flip" => options.flipped = true,
+            _ => return Err(format!("Unknown option \"{}\"\n{}", arg, USAGE)),
+        }
+    }
+
+    if modes.len() > 1
+    {
+        return Err(format!("Choose one of {}", modes.join(", ")));
+    }
+    if server.is_none() && (game_id.is_some() || transport.is_some() || spectate)
+    {
+        return Err(String::from("--game-id, --transport and --spectate need --server"));
+    }
+    if options.pgn.is_some() && options.fen.is_some()
+    {
+        return Err(String::from("Use either --pgn or --fen"));
+    }
+
+    if let Some(address) = server
+    {
+        if options.pgn.is_some() || options.fen.is_some()
+        {
+            return Err(String::from("--pgn and --fen don't work with --server"));
+        }
+        let game: DiscoveredGame = DiscoveredGame{
+            name: address.clone(),
+            ip_port: address,
+            transport: transport.unwrap_or(TransportKind::Tcp),
+            game_id: game_id.unwrap_or(0),
+            players: 0,
+            spectators: 0,
+        };
+        options.mode = StartMode::Join(game, spectate);
+    }
+
+    // A game or a position to look at
+    if let (StartMode::Intro, true) = (&options.mode, options.pgn.is_some() || options.fen.is_some())
+    {
+        options.mode = StartMode::Game(GameMode::Analysis);
+    }
+    if matches!(options.mode, StartMode::Replay) && options.pgn.is_none()
+    {
+        return Err(String::from("--replay needs a game from --pgn"));
+    }
+    if matches!(options.mode, StartMode::Puzzles(_)) && (options.pgn.is_some() || options.fen.is_some())
+    {
+        return Err(String::from("--pgn and --fen don't work with --puzzles"));
+    }
+    if options.fen.is_some() && !matches!(options.mode, StartMode::Game(GameMode::Analysis))
+    {
+        return Err(String::from("--fen only works with --analysis, games are played from the starting position"));
+    }
+
+    Ok(Cli::Gui(options))
+}
+
+// The settings file (--config) with the preferences and the theme
+// (--theme) of the command line. Also used by the render commands
+pub fn load_settings(config: &str, theme: Option<&str>) -> Result<ChessViewSettings, String>
+{
+    let mut settings: ChessViewSettings = ChessViewSettings::load(config, PREFERENCES)?;
+    if let Some(theme) = theme { settings.apply_theme(theme)?; }
+    Ok(settings)
+}
+
+// Set up the screens for the mode of the command line, the intro stays
+// below so leaving the game goes back to it
+pub fn start(options: &StartOptions, controller: &mut ChessViewController, chessview: &mut ChessView)
+    -> Result<(), String>
+{
+    let game: Game = match &options.pgn
+    {
+        Some(path) => Game::from_pgn(&fs::read_to_string(path)
+            .map_err(|err| format!("Could not read {}: {}", path, err))?)?,
+        None => Game::new(),
+    };
+
+    controller.screens = ScreenStack::new(Box::new(IntroScreen::new()));
+    match &options.mode
+    {
+        StartMode::Intro => return Ok(()),
+        StartMode::Replay => {
+            controller.start_replay(chessview, options.flipped);
+            controller.screens.push(Box::new(ReplayScreen::new(game)));
+            return Ok(());
+        },
+        StartMode::Puzzles(file) => {
+            let screen: PuzzleScreen = PuzzleScreen::new(file, controller, chessview);
+            controller.screens.push(Box::new(screen));
+            return Ok(());
+        },
+        StartMode::Game(mode) => {
+            controller.start_game(chessview, *mode, game);
+
+            if let Some(fen) = &options.fen
+            {
+                chessview.position = Some(position::from_fen(fen)?.0);
+            }
+            if options.flipped { chessview.flipped = true; }
+        },
+        StartMode::Join(game, spectate) => controller.connect(game, *spectate, chessview),
+    }
+
+    controller.screens.push(Box::new(GameScreen));
+    Ok(())
+}
+
+// `chess-gui relay ...`, runs until killed
+fn _relay_command(args: &[String]) -> Result<(), String>
+{
+    let mut listen: Option<String> = None;
+    let mut upstream: Option<String> = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next()
+    {
+        let mut value = || args.next().cloned().ok_or(format!("{} needs a value\n{}", arg, RELAY_USAGE));
+        match arg.as_str()
+        {
+            "--listen" => listen = Some(value()?),
+            "--upstream" => upstream = Some(value()?),
+            _ => return Err(format!("Unknown option \"{}\"\n{}", arg, RELAY_USAGE)),
+        }
+    }
+
+    let listen: String = listen.ok_or(format!("--listen is missing\n{}", RELAY_USAGE))?;
+    let upstream: String = upstream.ok_or(format!("--upstream is missing\n{}", RELAY_USAGE))?;
+    let addr = transport::spawn_ws_relay(&listen, &upstream)
+        .map_err(|err| format!("Could not listen on {}: {}", listen, err))?;
+    println!("Relaying WebSocket clients on {} to {}", addr, upstream);
+
+    // The relay runs on its own threads
+    loop { thread::park(); }
+}
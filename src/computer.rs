@@ -0,0 +1,71 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chess::chess_engine::ChessEngine;
+use chess::colors::Colors;
+
+use crate::position::{self, Squares};
+
+// The computer opponent. Looks one move ahead: every legal move is scored
+// by the material it wins minus the most valuable of its own pieces the
+// opponent can take afterwards. Equal moves are picked at random so games
+// differ. Returns `None` when `color` has no legal move
+pub fn choose_move(board: &ChessEngine, color: Colors) -> Option<(u32, u32)>
+{
+    let squares: Squares = position::snapshot(board);
+    let mut best: Vec<(u32, u32)> = Vec::new();
+    let mut best_score: i32 = i32::MIN;
+
+    for from in (0..64).filter(|s| matches!(squares[*s as usize], Some((_, c)) if c == color))
+    {
+        for to in (0..64).filter(|s| !matches!(squares[*s as usize], Some((_, c)) if c == color))
+        {
+            let mut copy: ChessEngine = board.clone();
+            if !position::play_move(&mut copy, &position::square_to_notation(from), &position::square_to_notation(to), None)
+            {
+                continue;
+            }
+
+            let score: i32 = _score(&squares, &position::snapshot(&copy), color);
+            if score > best_score
+            {
+                best_score = score;
+                best.clear();
+            }
+            if score == best_score { best.push((from, to)); }
+        }
+    }
+
+    if best.is_empty() { return None; }
+    Some(best[_random(best.len())])
+}
+
+fn _score(before: &Squares, after: &Squares, color: Colors) -> i32
+{
+    let sign: i32 = if color == Colors::White { 1 } else { -1 };
+    let gained: i32 = sign * (position::material_balance(after) - position::material_balance(before));
+
+    // Leaving the king in check loses outright
+    if position::in_check(after, color) { return i32::MIN + 1; }
+
+    let opponent_takes = |square: u32| (0..64)
+        .any(|s| matches!(after[s as usize], Some((_, c)) if c != color) && position::attacks(after, s, square));
+    let hanging: i32 = (0..64)
+        .filter_map(|s| match after[s as usize] { Some((piece_type, c)) if c == color => Some((s, piece_type)), _ => None })
+        .filter(|(s, _)| opponent_takes(*s))
+        .map(|(_, piece_type)| position::piece_value(piece_type))
+        .max()
+        .unwrap_or(0);
+
+    gained - hanging
+}
+
+// Good enough to pick between equal moves
+fn _random(n: usize) -> usize
+{
+    let nanos: u32 = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+    let mut x: u32 = nanos | 1;
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    x as usize % n
+}
@@ -0,0 +1,212 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use chess::colors::Colors;
+use json::JsonValue;
+
+use crate::autosave;
+
+// Every finished game, one JSON object per line so a new game is just
+// appended. Next to the autosave in the user's data directory, e.g.
+// {"date": "2026.10.19", "white": "You", "black": "Computer", "result": "1-0",
+//  "opening": "Italian Game", "color": "white", "opponent": "Computer", "pgn": "..."}
+const DATABASE_FILE: &str = "games.jsonl";
+
+pub const RESULTS: [&str; 3] = ["1-0", "0-1", "1/2-1/2"];
+
+#[derive(Clone)]
+pub struct ArchivedGame
+{
+    // YYYY.MM.DD, like the PGN Date tag, so they sort as text
+    pub date: String,
+    pub white: String,
+    pub black: String,
+    pub result: String,
+    pub opening: String,
+    // The side played on this screen, `None` when it was both or we watched
+    pub color: Option<Colors>,
+    pub opponent: String,
+    pub pgn: String,
+}
+
+impl ArchivedGame
+{
+    fn _to_json(&self) -> JsonValue
+    {
+        let mut archived: JsonValue = json::object!{
+            "date": self.date.as_str(),
+            "white": self.white.as_str(),
+            "black": self.black.as_str(),
+            "result": self.result.as_str(),
+            "opening": self.opening.as_str(),
+            "opponent": self.opponent.as_str(),
+            "pgn": self.pgn.as_str(),
+        };
+        if let Some(color) = self.color
+        {
+            archived["color"] = if color == Colors::White { "white" } else { "black" }.into();
+        }
+        archived
+    }
+
+    fn _from_json(archived: &JsonValue) -> Result<ArchivedGame, String>
+    {
+        let text = |key: &str| archived[key].as_str().map(str::to_string).ok_or(format!("no \"{}\"", key));
+        let color: Option<Colors> = match archived["color"].as_str()
+        {
+            Some("white") => Some(Colors::White),
+            Some("black") => Some(Colors::Black),
+            _ => None,
+        };
+
+        Ok(ArchivedGame{
+            date: text("date")?,
+            white: text("white")?,
+            black: text("black")?,
+            result: text("result")?,
+            opening: text("opening").unwrap_or_else(|_| String::from("?")),
+            color,
+            opponent: text("opponent").unwrap_or_else(|_| String::from("?")),
+            pgn: text("pgn")?,
+        })
+    }
+}
+
+// What the games screen shows, everything left empty matches any game
+#[derive(Default)]
+pub struct Filter
+{
+    pub result: Option<&'static str>,
+    pub color: Option<Colors>,
+    // Part of the opponent's name, any case
+    pub opponent: String,
+    // Inclusive, as YYYY.MM.DD or the start of one, "2026.10" is all of October
+    pub from: String,
+    pub to: String,
+}
+
+impl Filter
+{
+    pub fn matches(&self, game: &ArchivedGame) -> bool
+    {
+        let to: &str = self.to.trim();
+        let date_to: &str = game.date.get(..to.len()).unwrap_or(&game.date);
+        self.result.is_none_or(|result| game.result == result)
+            && self.color.is_none_or(|color| game.color == Some(color))
+            && game.opponent.to_lowercase().contains(&self.opponent.trim().to_lowercase())
+            && game.date.as_str() >= self.from.trim()
+            && (to.is_empty() || date_to <= to)
+    }
+}
+
+// Append a finished game
+pub fn add(game: &ArchivedGame) -> Result<(), String>
+{
+    let path: PathBuf = autosave::writable_data_file(DATABASE_FILE)?;
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)
+        .map_err(|err| format!("cannot open {}: {}", path.display(), err))?;
+    writeln!(file, "{}", game._to_json().dump())
+        .map_err(|err| format!("cannot write {}: {}", path.display(), err))
+}
+
+// All archived games, the newest first. Lines that can't be read are
+// reported and skipped, so one broken line doesn't hide the rest
+pub fn load() -> Result<Vec<ArchivedGame>, String>
+{
+    let path: PathBuf = match autosave::data_file(DATABASE_FILE) { Some(path) => path, None => return Ok(Vec::new()) };
+    let text: String = match fs::read_to_string(&path)
+    {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(format!("cannot read {}: {}", path.display(), err)),
+    };
+
+    let mut games: Vec<ArchivedGame> = Vec::new();
+    for (i, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty())
+    {
+        match json::parse(line).map_err(|err| err.to_string()).and_then(|archived| ArchivedGame::_from_json(&archived))
+        {
+            Ok(game) => games.push(game),
+            Err(err) => eprintln!("{}:{}: {}", path.display(), i + 1, err),
+        }
+    }
+    games.reverse();
+    Ok(games)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn _game(date: &str, result: &str, color: Option<Colors>, opponent: &str) -> ArchivedGame
+    {
+        ArchivedGame{
+            date: date.to_string(),
+            white: String::from("You"),
+            black: opponent.to_string(),
+            result: result.to_string(),
+            opening: String::from("Italian Game"),
+            color,
+            opponent: opponent.to_string(),
+            pgn: String::from("1. e4 e5 2. Nf3 Nc6 3. Bc4 *"),
+        }
+    }
+
+    fn _round_trip(game: &ArchivedGame) -> ArchivedGame
+    {
+        ArchivedGame::_from_json(&json::parse(&game._to_json().dump()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn games_round_trip()
+    {
+        for color in [Some(Colors::White), Some(Colors::Black), None]
+        {
+            let game: ArchivedGame = _game("2026.10.19", "1-0", color, "Computer");
+            let again: ArchivedGame = _round_trip(&game);
+            assert_eq!((again.date, again.white, again.black, again.result, again.opening, again.opponent, again.pgn),
+                       (game.date, game.white, game.black, game.result, game.opening, game.opponent, game.pgn));
+            assert!(again.color == color);
+        }
+
+        // Older lines without the opening and the opponent
+        let old: ArchivedGame = ArchivedGame::_from_json(&json::parse(
+            r#"{"date": "2026.01.02", "white": "A", "black": "B", "result": "0-1", "pgn": "*"}"#).unwrap()).unwrap();
+        assert_eq!((old.opening.as_str(), old.opponent.as_str()), ("?", "?"));
+        assert!(old.color.is_none());
+
+        let broken: Result<ArchivedGame, String> = ArchivedGame::_from_json(&json::parse(
+            r#"{"date": "2026.01.02", "white": "A", "black": "B", "result": "0-1"}"#).unwrap());
+        assert_eq!(broken.err(), Some(String::from("no \"pgn\"")));
+    }
+
+    #[test]
+    fn filter_games()
+    {
+        let game: ArchivedGame = _game("2026.10.19", "1-0", Some(Colors::White), "Computer");
+        assert!(Filter::default().matches(&game));
+
+        assert!(Filter{result: Some("1-0"), ..Filter::default()}.matches(&game));
+        assert!(!Filter{result: Some("1/2-1/2"), ..Filter::default()}.matches(&game));
+        assert!(Filter{color: Some(Colors::White), ..Filter::default()}.matches(&game));
+        assert!(!Filter{color: Some(Colors::Black), ..Filter::default()}.matches(&game));
+        assert!(!Filter{color: Some(Colors::White), ..Filter::default()}.matches(&_game("2026.10.19", "1-0", None, "Bob")));
+
+        // Part of the name, any case
+        assert!(Filter{opponent: String::from(" comp "), ..Filter::default()}.matches(&game));
+        assert!(!Filter{opponent: String::from("Bob"), ..Filter::default()}.matches(&game));
+
+        // Dates are inclusive, a month or a year covers all of it
+        let dates = |from: &str, to: &str| Filter{from: from.to_string(), to: to.to_string(), ..Filter::default()}.matches(&game);
+        assert!(dates("2026.10.19", "2026.10.19"));
+        assert!(dates("2026.10", "2026.10"));
+        assert!(dates(" 2026.10 ", " 2026.10 "));
+        assert!(dates("2026", ""));
+        assert!(dates("", "2026"));
+        assert!(!dates("2026.10.20", ""));
+        assert!(!dates("", "2026.10.18"));
+        assert!(!dates("", "2026.09"));
+    }
+}
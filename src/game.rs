@@ -0,0 +1,905 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chess::chess_engine::ChessEngine;
+use chess::colors::Colors;
+use chess::piece_types::PieceTypes;
+
+use crate::position::{self, Squares};
+
+// Openings by their first moves, the longest match names a game
+const OPENINGS: [(&str, &str); 38] = [
+    ("e4", "King's Pawn Opening"),
+    ("e4 e5", "Open Game"),
+    ("e4 e5 Nf3 Nc6 Bb5", "Ruy Lopez"),
+    ("e4 e5 Nf3 Nc6 Bc4", "Italian Game"),
+    ("e4 e5 Nf3 Nc6 Bc4 Bc5", "Giuoco Piano"),
+    ("e4 e5 Nf3 Nc6 Bc4 Nf6", "Two Knights Defense"),
+    ("e4 e5 Nf3 Nc6 d4", "Scotch Game"),
+    ("e4 e5 Nf3 Nf6", "Petrov's Defense"),
+    ("e4 e5 Nf3 d6", "Philidor Defense"),
+    ("e4 e5 f4", "King's Gambit"),
+    ("e4 e5 Nc3", "Vienna Game"),
+    ("e4 c5", "Sicilian Defense"),
+    ("e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 a6", "Sicilian Defense, Najdorf"),
+    ("e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 g6", "Sicilian Defense, Dragon"),
+    ("e4 e6", "French Defense"),
+    ("e4 c6", "Caro-Kann Defense"),
+    ("e4 d5", "Scandinavian Defense"),
+    ("e4 d6", "Pirc Defense"),
+    ("e4 Nf6", "Alekhine's Defense"),
+    ("e4 g6", "Modern Defense"),
+    ("d4", "Queen's Pawn Opening"),
+    ("d4 d5 c4", "Queen's Gambit"),
+    ("d4 d5 c4 dxc4", "Queen's Gambit Accepted"),
+    ("d4 d5 c4 e6", "Queen's Gambit Declined"),
+    ("d4 d5 c4 c6", "Slav Defense"),
+    ("d4 d5 Bf4", "London System"),
+    ("d4 Nf6 Bf4", "London System"),
+    ("d4 Nf6 c4 e6 Nc3 Bb4", "Nimzo-Indian Defense"),
+    ("d4 Nf6 c4 e6 Nf3 b6", "Queen's Indian Defense"),
+    ("d4 Nf6 c4 g6 Nc3 Bg7", "King's Indian Defense"),
+    ("d4 Nf6 c4 g6 Nc3 d5", "Grunfeld Defense"),
+    ("d4 Nf6 c4 c5", "Benoni Defense"),
+    ("d4 f5", "Dutch Defense"),
+    ("c4", "English Opening"),
+    ("Nf3", "Reti Opening"),
+    ("f4", "Bird's Opening"),
+    ("b3", "Nimzo-Larsen Attack"),
+    ("g3", "King's Fianchetto Opening"),
+];
+
+// One move of the game, squares numbered a1 = 0 like `Squares`
+#[derive(Clone, PartialEq, Debug)]
+pub struct PlayedMove
+{
+    pub from: u32,
+    pub to: u32,
+    pub promotion: Option<PieceTypes>,
+    pub san: String,
+}
+
+// Colors of arrows and marked squares. The letters are the ones used by
+// the [%cal]/[%csl] PGN comments
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mark
+{
+    Green,
+    Red,
+    Blue,
+    Yellow,
+}
+
+impl Mark
+{
+    pub fn letter(self) -> char
+    {
+        match self
+        {
+            Mark::Green => 'G',
+            Mark::Red => 'R',
+            Mark::Blue => 'B',
+            Mark::Yellow => 'Y',
+        }
+    }
+
+    pub fn from_letter(letter: char) -> Option<Mark>
+    {
+        match letter
+        {
+            'G' => Some(Mark::Green),
+            'R' => Some(Mark::Red),
+            'B' => Some(Mark::Blue),
+            'Y' => Some(Mark::Yellow),
+            _ => None
+        }
+    }
+
+    // rgba, a bit transparent so the pieces stay visible
+    pub fn color(self) -> [f32; 4]
+    {
+        match self
+        {
+            Mark::Green => [0.08, 0.47, 0.11, 0.8],
+            Mark::Red => [0.53, 0.0, 0.0, 0.8],
+            Mark::Blue => [0.0, 0.19, 0.53, 0.8],
+            Mark::Yellow => [0.9, 0.68, 0.0, 0.8],
+        }
+    }
+}
+
+// Arrows and circled squares drawn on one position
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Annotations
+{
+    // from, to, color
+    pub arrows: Vec<(u32, u32, Mark)>,
+    pub circles: Vec<(u32, Mark)>,
+}
+
+impl Annotations
+{
+    // Drawing the same arrow again removes it, with another color recolors it
+    pub fn toggle_arrow(&mut self, from: u32, to: u32, mark: Mark)
+    {
+        match self.arrows.iter().position(|(f, t, _)| *f == from && *t == to)
+        {
+            Some(i) if self.arrows[i].2 == mark => { self.arrows.remove(i); },
+            Some(i) => self.arrows[i].2 = mark,
+            None => self.arrows.push((from, to, mark)),
+        }
+    }
+
+    pub fn toggle_circle(&mut self, square: u32, mark: Mark)
+    {
+        match self.circles.iter().position(|(s, _)| *s == square)
+        {
+            Some(i) if self.circles[i].1 == mark => { self.circles.remove(i); },
+            Some(i) => self.circles[i].1 = mark,
+            None => self.circles.push((square, mark)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.arrows.is_empty() && self.circles.is_empty()
+    }
+
+    // Add the squares of a [%csl] list such as "Gd4,Re5"
+    pub fn add_circles(&mut self, list: &str) -> Result<(), String>
+    {
+        for item in list.split(',').map(str::trim).filter(|item| !item.is_empty())
+        {
+            let (mark, squares) = _split_mark(item);
+            match position::notation_to_square(squares)
+            {
+                Some(square) => self.circles.push((square, mark)),
+                None => return Err(format!("Bad square \"{}\"", item)),
+            }
+        }
+        Ok(())
+    }
+
+    // Add the arrows of a [%cal] list such as "Ge2e4,Rd1h5"
+    pub fn add_arrows(&mut self, list: &str) -> Result<(), String>
+    {
+        for item in list.split(',').map(str::trim).filter(|item| !item.is_empty())
+        {
+            let (mark, squares) = _split_mark(item);
+            let from: Option<u32> = squares.get(..2).and_then(position::notation_to_square);
+            let to: Option<u32> = squares.get(2..).and_then(position::notation_to_square);
+            match (from, to)
+            {
+                (Some(from), Some(to)) => self.arrows.push((from, to, mark)),
+                _ => return Err(format!("Bad arrow \"{}\"", item)),
+            }
+        }
+        Ok(())
+    }
+
+    // Add the [%csl]/[%cal] commands of a PGN comment, anything else in it
+    // is ignored
+    pub fn add_pgn_commands(&mut self, comment: &str) -> Result<(), String>
+    {
+        let mut rest: &str = comment;
+        while let Some(start) = rest.find("[%")
+        {
+            let end: usize = rest[start..].find(']').map(|end| start + end)
+                .ok_or(format!("Unclosed command in the comment \"{}\"", comment))?;
+            let mut command = rest[start+2..end].splitn(2, char::is_whitespace);
+            match (command.next(), command.next())
+            {
+                (Some("csl"), Some(list)) => self.add_circles(list)?,
+                (Some("cal"), Some(list)) => self.add_arrows(list)?,
+                _ => ()
+            }
+            rest = &rest[end+1..];
+        }
+        Ok(())
+    }
+
+    // The PGN comment commands, e.g. "[%csl Gd4] [%cal Ge2e4,Rd1h5]"
+    pub fn to_pgn_commands(&self) -> String
+    {
+        let mut commands: Vec<String> = Vec::new();
+        if !self.circles.is_empty()
+        {
+            let squares: Vec<String> = self.circles.iter()
+                .map(|(s, mark)| format!("{}{}", mark.letter(), position::square_to_notation(*s)))
+                .collect();
+            commands.push(format!("[%csl {}]", squares.join(",")));
+        }
+        if !self.arrows.is_empty()
+        {
+            let arrows: Vec<String> = self.arrows.iter()
+                .map(|(from, to, mark)| format!("{}{}{}", mark.letter(),
+                    position::square_to_notation(*from), position::square_to_notation(*to)))
+                .collect();
+            commands.push(format!("[%cal {}]", arrows.join(",")));
+        }
+        commands.join(" ")
+    }
+}
+
+// A position of the game: the move that reached it (`None` at the start),
+// the arrows and circles drawn on it and the moves played from it. The
+// first of those continues the line, the others are its variations
+#[derive(Clone, Default)]
+pub struct Node
+{
+    pub mv: Option<PlayedMove>,
+    pub annotations: Annotations,
+    pub children: Vec<Node>,
+}
+
+// The moves played, as a tree: going back and playing something else keeps
+// what was played before as a variation. One line of the tree is selected,
+// the board shows its position after `ply` moves. Games always start from
+// the initial position, the engine can't be set up from a FEN
+#[derive(Clone, Default)]
+pub struct Game
+{
+    pub root: Node,
+    // The child taken at each position of the selected line, to its end
+    path: Vec<usize>,
+    ply: usize,
+    pub white: String,
+    pub black: String,
+    // "1-0", "0-1", "1/2-1/2" or "*" while it's going on
+    pub result: String,
+}
+
+impl Game
+{
+    pub fn new() -> Game
+    {
+        Game{
+            white: String::from("?"),
+            black: String::from("?"),
+            result: String::from("*"),
+            ..Game::default()
+        }
+    }
+
+    // Number of moves played to reach the position shown
+    pub fn ply(&self) -> usize
+    {
+        self.ply
+    }
+
+    // The moves of the selected line, also the ones after the position shown
+    pub fn line(&self) -> Vec<&PlayedMove>
+    {
+        let mut node: &Node = &self.root;
+        let mut line: Vec<&PlayedMove> = Vec::new();
+        for i in self.path.iter()
+        {
+            node = &node.children[*i];
+            line.extend(node.mv.as_ref());
+        }
+        line
+    }
+
+    // The move that led to the position shown
+    pub fn last_move(&self) -> Option<&PlayedMove>
+    {
+        self._node().mv.as_ref()
+    }
+
+    // Add the move that turned `before` into `after`, returns whether the
+    // change was a move
+    pub fn record(&mut self, before: &Squares, after: &Squares) -> bool
+    {
+        let (from, to, promotion) = match position::diff_move(before, after)
+        {
+            Some(mv) => mv,
+            None => return false,
+        };
+
+        let san: String = position::san(before, after, from, to, promotion);
+        self.play(PlayedMove{from, to, promotion, san});
+        true
+    }
+
+    // Play `mv` from the position shown. If it was played there before, that
+    // line is followed, otherwise it starts a new variation
+    pub fn play(&mut self, mv: PlayedMove)
+    {
+        let ply: usize = self.ply;
+        let node: &mut Node = _walk_mut(&mut self.root, &self.path[..ply]);
+        let i: usize = match node.children.iter().position(|child| child.mv.as_ref() == Some(&mv))
+        {
+            Some(i) => i,
+            None => {
+                node.children.push(Node{mv: Some(mv), ..Node::default()});
+                node.children.len() - 1
+            }
+        };
+
+        if self.path.get(ply) != Some(&i)
+        {
+            self.path.truncate(ply);
+            self.path.push(i);
+            self._extend_path();
+        }
+        self.ply += 1;
+    }
+
+    // One move back or forward on the selected line, false at its start or end
+    pub fn back(&mut self) -> bool
+    {
+        if self.ply == 0 { return false; }
+        self.ply -= 1;
+        true
+    }
+
+    pub fn forward(&mut self) -> bool
+    {
+        if self.ply >= self.path.len() { return false; }
+        self.ply += 1;
+        true
+    }
+
+    // Show the position after `ply` moves of the selected line
+    pub fn go_to(&mut self, ply: usize)
+    {
+        self.ply = ply.min(self.path.len());
+    }
+
+    // Select the next move played from the position shown, or with a negative
+    // `step` a previous one, `forward` then plays it. False if there's only
+    // the one
+    pub fn switch_variation(&mut self, step: isize) -> bool
+    {
+        let count: usize = self._node().children.len();
+        if count < 2 { return false; }
+
+        let i: usize = (self.path[self.ply] as isize + step).rem_euclid(count as isize) as usize;
+        self.path.truncate(self.ply);
+        self.path.push(i);
+        self._extend_path();
+        true
+    }
+
+    // Move the variation the position shown is in one place up, the first
+    // variation swaps with the line it branches from. False on the main line
+    pub fn promote_variation(&mut self) -> bool
+    {
+        let start: usize = match self._variation_start() { Some(start) => start, None => return false };
+        let i: usize = self.path[start];
+        _walk_mut(&mut self.root, &self.path[..start]).children.swap(i - 1, i);
+        self.path[start] = i - 1;
+        true
+    }
+
+    // Remove the variation the position shown is in, and show the position
+    // it branched off at. False on the main line
+    pub fn delete_variation(&mut self) -> bool
+    {
+        let start: usize = match self._variation_start() { Some(start) => start, None => return false };
+        let i: usize = self.path[start];
+        _walk_mut(&mut self.root, &self.path[..start]).children.remove(i);
+        self.path.truncate(start);
+        self.path.push(i - 1);
+        self._extend_path();
+        self.ply = start;
+        true
+    }
+
+    // Ply the innermost variation of the position shown branches off at,
+    // `None` on the main line
+    fn _variation_start(&self) -> Option<usize>
+    {
+        (0..self.ply).rev().find(|ply| self.path[*ply] != 0)
+    }
+
+    // Follow the first moves on from the end of the selected line
+    fn _extend_path(&mut self)
+    {
+        let mut node: &Node = _walk(&self.root, &self.path);
+        while !node.children.is_empty()
+        {
+            self.path.push(0);
+            node = &node.children[0];
+        }
+    }
+
+    fn _node(&self) -> &Node
+    {
+        _walk(&self.root, &self.path[..self.ply])
+    }
+
+    pub fn annotations_mut(&mut self) -> &mut Annotations
+    {
+        let ply: usize = self.ply;
+        &mut _walk_mut(&mut self.root, &self.path[..ply]).annotations
+    }
+
+    pub fn clear_annotations(&mut self)
+    {
+        *self.annotations_mut() = Annotations::default();
+    }
+
+    pub fn current_annotations(&self) -> Option<&Annotations>
+    {
+        Some(&self._node().annotations).filter(|annotations| !annotations.is_empty())
+    }
+
+    // Name of the opening played, "?" if it isn't one of `OPENINGS`
+    pub fn opening(&self) -> &'static str
+    {
+        let played: Vec<&str> = self.line().iter().map(|mv| mv.san.trim_end_matches(['+', '#'])).collect();
+        OPENINGS.iter()
+            .filter(|(moves, _)| {
+                let moves: Vec<&str> = moves.split(' ').collect();
+                played.starts_with(&moves)
+            })
+            .max_by_key(|(moves, _)| moves.len())
+            .map_or("?", |(_, name)| name)
+    }
+
+    // Read the first game of a PGN file: the players, the result, the moves
+    // with their variations and the arrows and circles of the [%cal]/[%csl]
+    // comments. The moves are checked with `position::legal_moves`, which
+    // unlike the engine can underpromote. Shows the end of the main line
+    pub fn from_pgn(pgn: &str) -> Result<Game, String>
+    {
+        let mut game: Game = Game::new();
+        let mut movetext: String = String::new();
+        for line in pgn.lines().map(str::trim)
+        {
+            if line.starts_with('[') && line.ends_with(']')
+            {
+                // The tags of the next game
+                if !movetext.trim().is_empty() { break; }
+
+                if let Some((tag, value)) = _parse_tag(line)
+                {
+                    match tag
+                    {
+                        "White" => game.white = value,
+                        "Black" => game.black = value,
+                        "Result" => game.result = value,
+                        _ => ()
+                    }
+                }
+            }
+            else if !line.starts_with('%')
+            {
+                movetext.push_str(line);
+                movetext.push('\n');
+            }
+        }
+
+        let mut squares: Squares = position::snapshot(&ChessEngine::new());
+        // Where the line around each open variation goes on
+        let mut outer: Vec<(Vec<usize>, usize)> = Vec::new();
+        for token in _movetext_tokens(&movetext)?
+        {
+            match token
+            {
+                PgnToken::Comment(comment) => game.annotations_mut().add_pgn_commands(&comment)?,
+                PgnToken::Result(result) => game.result = result,
+                PgnToken::VariationStart => {
+                    outer.push((game.path.clone(), game.ply));
+                    // Played instead of the last move
+                    if !game.back() { return Err(String::from("A variation before the first move")); }
+                    squares = game.positions()[game.ply];
+                },
+                PgnToken::VariationEnd => {
+                    let (path, ply) = outer.pop().ok_or("Unexpected ')'")?;
+                    game.path = path;
+                    game.ply = ply;
+                    squares = game.positions()[ply];
+                },
+                PgnToken::Move(san) => {
+                    let ply: usize = game.ply();
+                    let white_to_move: bool = ply.is_multiple_of(2);
+                    let label: String = format!("{}{} {}", ply/2 + 1, if white_to_move { "." } else { "..." }, san);
+
+                    let before: Squares = squares;
+                    let candidates: Vec<(u32, u32, Option<PieceTypes>)> = position::parse_move(&before, white_to_move, &san)
+                        .map_err(|err| format!("{}: {}", label, err))?;
+                    let color: Colors = if white_to_move { Colors::White } else { Colors::Black };
+                    let legal_moves: Vec<(u32, u32)> = position::legal_moves(&before, color,
+                        game.last_move().map(|mv| (mv.from, mv.to)));
+                    let legal: Vec<(u32, u32, Option<PieceTypes>)> = candidates.into_iter()
+                        .filter(|(from, to, _)| legal_moves.contains(&(*from, *to)))
+                        .collect();
+                    let (from, to, promotion) = match legal.as_slice()
+                    {
+                        [mv] => *mv,
+                        [] => return Err(format!("{} is not a legal move", label)),
+                        _ => return Err(format!("{} is ambiguous", label)),
+                    };
+
+                    // Without "=Q" a pawn reaching the last rank becomes a queen
+                    let promotion: Option<PieceTypes> = if position::is_promotion(&before, from, to)
+                    {
+                        promotion.or(Some(PieceTypes::Queen))
+                    }
+                    else { None };
+                    squares = position::apply_move(&before, from, to, promotion);
+                    game.record(&before, &squares);
+                }
+            }
+        }
+        if !outer.is_empty() { return Err(String::from("Unclosed variation")); }
+
+        Ok(game)
+    }
+
+    // The engine after the first `ply` moves of the selected line, e.g. to
+    // go on with a loaded game. The engine picks what pawns promote to, so
+    // underpromotions are queens there, see `positions` for the real squares
+    pub fn board_at(&self, ply: usize) -> ChessEngine
+    {
+        let mut board: ChessEngine = ChessEngine::new();
+        for mv in self.line().into_iter().take(ply)
+        {
+            position::play_move(&mut board, &position::square_to_notation(mv.from),
+                                &position::square_to_notation(mv.to), None);
+        }
+        board
+    }
+
+    // The position at the start and after every move of the selected line,
+    // the index is the ply
+    pub fn positions(&self) -> Vec<Squares>
+    {
+        let mut positions: Vec<Squares> = vec![position::snapshot(&ChessEngine::new())];
+        for mv in self.line()
+        {
+            let before: Squares = positions[positions.len() - 1];
+            positions.push(position::apply_move(&before, mv.from, mv.to, mv.promotion));
+        }
+        positions
+    }
+
+    // The whole game as PGN, variations in parentheses after the move they
+    // replace. Annotations become [%cal]/[%csl] comments after the move
+    // leading to the annotated position
+    pub fn to_pgn(&self) -> String
+    {
+        let mut pgn: String = String::new();
+        for (tag, value) in [
+            ("Event", "Casual game"),
+            ("Site", "chess-gui"),
+            ("Date", today().as_str()),
+            ("Round", "-"),
+            ("White", self.white.as_str()),
+            ("Black", self.black.as_str()),
+            ("Result", self.result.as_str()),
+        ]
+        {
+            pgn.push_str(&format!("[{} \"{}\"]\n", tag, value.replace('\\', "\\\\").replace('"', "\\\"")));
+        }
+        pgn.push('\n');
+
+        let mut words: Vec<String> = self.movetext().into_iter().map(|(word, _)| word).collect();
+        words.push(self.result.clone());
+
+        // Movetext lines of at most 80 characters
+        let mut line: String = String::new();
+        for word in words
+        {
+            if !line.is_empty() && line.len() + 1 + word.len() > 80
+            {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() { line.push(' '); }
+            line.push_str(&word);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+        pgn
+    }
+
+    // The moves of the whole tree as PGN words, without the result. True
+    // for the move that led to the position shown, for the move list
+    pub fn movetext(&self) -> Vec<(String, bool)>
+    {
+        let mut tokens: Vec<(String, bool)> = Vec::new();
+        if !self.root.annotations.is_empty()
+        {
+            tokens.push((format!("{{{}}}", self.root.annotations.to_pgn_commands()), false));
+        }
+        _write_children(&self.root, 0, false, self._node(), &mut tokens);
+
+        // "(" sticks to the word after it and ")" to the one before
+        let mut words: Vec<(String, bool)> = Vec::new();
+        let mut open: String = String::new();
+        for (token, current) in tokens
+        {
+            match (token.as_str(), words.last_mut())
+            {
+                ("(", _) => open.push('('),
+                (")", Some(last)) => last.0.push(')'),
+                _ => words.push((format!("{}{}", std::mem::take(&mut open), token), current)),
+            }
+        }
+        words
+    }
+}
+
+// The node `path` leads to from `node`
+fn _walk<'a>(node: &'a Node, path: &[usize]) -> &'a Node
+{
+    path.iter().fold(node, |node, i| &node.children[*i])
+}
+
+fn _walk_mut<'a>(node: &'a mut Node, path: &[usize]) -> &'a mut Node
+{
+    path.iter().fold(node, |node, i| &mut node.children[*i])
+}
+
+// The moves after `node`: the first one, its variations in parentheses and
+// then the rest of its line. `ply` is the number of moves before them,
+// `numbered` forces a move number on a black move, e.g. after a variation
+fn _write_children(node: &Node, ply: usize, numbered: bool, current: &Node, tokens: &mut Vec<(String, bool)>)
+{
+    let first: &Node = match node.children.first() { Some(first) => first, None => return };
+    _write_move(first, ply, numbered, current, tokens);
+
+    for variation in node.children.iter().skip(1)
+    {
+        tokens.push((String::from("("), false));
+        _write_move(variation, ply, true, current, tokens);
+        _write_children(variation, ply + 1, false, current, tokens);
+        tokens.push((String::from(")"), false));
+    }
+
+    _write_children(first, ply + 1, node.children.len() > 1, current, tokens);
+}
+
+// "12. e4" for white, "12... e5" for black when numbered, then the comment
+fn _write_move(node: &Node, ply: usize, numbered: bool, current: &Node, tokens: &mut Vec<(String, bool)>)
+{
+    let mv: &PlayedMove = match &node.mv { Some(mv) => mv, None => return };
+    if ply.is_multiple_of(2) { tokens.push((format!("{}.", ply/2 + 1), false)); }
+    else if numbered { tokens.push((format!("{}...", ply/2 + 1), false)); }
+
+    tokens.push((mv.san.clone(), std::ptr::eq(node, current)));
+    if !node.annotations.is_empty()
+    {
+        tokens.push((format!("{{{}}}", node.annotations.to_pgn_commands()), false));
+    }
+}
+
+enum PgnToken
+{
+    Move(String),
+    Comment(String),
+    Result(String),
+    VariationStart,
+    VariationEnd,
+}
+
+// Split PGN movetext into moves (without move numbers and !? suffixes),
+// comments, the parentheses around variations and the result. NAGs ($1)
+// are dropped
+fn _movetext_tokens(movetext: &str) -> Result<Vec<PgnToken>, String>
+{
+    let mut tokens: Vec<PgnToken> = Vec::new();
+    let mut chars = movetext.chars().peekable();
+    while let Some(ch) = chars.next()
+    {
+        match ch
+        {
+            '{' => {
+                let comment: String = chars.by_ref().take_while(|ch| *ch != '}').collect();
+                tokens.push(PgnToken::Comment(comment));
+            },
+            ';' => { chars.by_ref().find(|ch| *ch == '\n'); },
+            '(' => tokens.push(PgnToken::VariationStart),
+            ')' => tokens.push(PgnToken::VariationEnd),
+            ch if ch.is_whitespace() => (),
+            ch => {
+                let mut word: String = ch.to_string();
+                while let Some(next) = chars.peek()
+                {
+                    if next.is_whitespace() || "{}();".contains(*next) { break; }
+                    word.push(*next);
+                    chars.next();
+                }
+
+                if ["1-0", "0-1", "1/2-1/2", "*"].contains(&word.as_str())
+                {
+                    tokens.push(PgnToken::Result(word));
+                    continue;
+                }
+                if word.starts_with('$') { continue; }
+
+                // "12." and "12...Nf6" carry the move number, "0-0" is castling
+                let san: &str = match word.rfind('.')
+                {
+                    Some(dot) => &word[dot+1..],
+                    None => &word,
+                };
+                let san: &str = san.trim_end_matches(['!', '?']);
+                if !san.is_empty() { tokens.push(PgnToken::Move(san.to_string())); }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+// `[White "Magnus"]` -> ("White", "Magnus")
+fn _parse_tag(line: &str) -> Option<(&str, String)>
+{
+    let inner: &str = line.strip_prefix('[')?.strip_suffix(']')?.trim();
+    let (tag, value) = inner.split_once(char::is_whitespace)?;
+    let value: &str = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some((tag, value.replace("\\\"", "\"").replace("\\\\", "\\")))
+}
+
+// "Ge2e4" -> (Green, "e2e4"). The color letter is optional, green if left out
+fn _split_mark(item: &str) -> (Mark, &str)
+{
+    match item.chars().next().and_then(Mark::from_letter)
+    {
+        Some(mark) => (mark, &item[1..]),
+        None => (Mark::Green, item),
+    }
+}
+
+// Today's date as YYYY.MM.DD (UTC), the PGN Date format
+pub fn today() -> String
+{
+    let days: i64 = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64 / 86400).unwrap_or(0);
+
+    // Days since 1970-01-01 to a civil date, Howard Hinnant's algorithm
+    let z: i64 = days + 719468;
+    let era: i64 = z.div_euclid(146097);
+    let doe: i64 = z - era * 146097;
+    let yoe: i64 = (doe - doe/1460 + doe/36524 - doe/146096) / 365;
+    let doy: i64 = doe - (365*yoe + yoe/4 - yoe/100);
+    let mp: i64 = (5*doy + 2) / 153;
+    let day: i64 = doy - (153*mp + 2)/5 + 1;
+    let month: i64 = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year: i64 = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn _sans(game: &Game) -> Vec<String>
+    {
+        game.line().iter().map(|mv| mv.san.clone()).collect()
+    }
+
+    #[test]
+    fn underpromotion_round_trips()
+    {
+        let game: Game = Game::from_pgn("1. a4 b5 2. axb5 a6 3. bxa6 Bb7 4. axb7 Nc6 5. bxa8=N *").unwrap();
+        let last: &PlayedMove = game.last_move().unwrap();
+        assert_eq!(last.promotion, Some(PieceTypes::Knight));
+        assert_eq!(last.san, "bxa8=N");
+
+        // Shown as a knight, in replays and GIFs
+        let a8: u32 = position::notation_to_square("a8").unwrap();
+        assert_eq!(game.positions()[9][a8 as usize], Some((PieceTypes::Knight, Colors::White)));
+
+        let pgn: String = game.to_pgn();
+        assert!(pgn.contains("5. bxa8=N *"));
+        let again: Game = Game::from_pgn(&pgn).unwrap();
+        assert_eq!(_sans(&again), _sans(&game));
+        assert_eq!(again.last_move().unwrap().promotion, Some(PieceTypes::Knight));
+    }
+
+    #[test]
+    fn promotion_without_piece_is_a_queen()
+    {
+        let game: Game = Game::from_pgn("1. a4 b5 2. axb5 a6 3. bxa6 Bb7 4. axb7 Nc6 5. bxa8 *").unwrap();
+        assert_eq!(game.last_move().unwrap().san, "bxa8=Q");
+        assert_eq!(game.last_move().unwrap().promotion, Some(PieceTypes::Queen));
+    }
+
+    #[test]
+    fn pgn_tags_and_moves()
+    {
+        let pgn: &str = "[Event \"Club\"]\n[White \"Anna \\\"The Rook\\\" Berg\"]\n[Black \"Carl\"]\n[Result \"1-0\"]\n\n\
+                         1. e4 e5 2. Nf3!? $1 Nc6 ; a comment to the end of the line\n3. Bb5 a6?! 1-0\n\n\
+                         [Event \"Next game\"]\n\n1. d4 *\n";
+        let game: Game = Game::from_pgn(pgn).unwrap();
+        assert_eq!(game.white, "Anna \"The Rook\" Berg");
+        assert_eq!(game.black, "Carl");
+        assert_eq!(game.result, "1-0");
+        assert_eq!(_sans(&game), vec!["e4", "e5", "Nf3", "Nc6", "Bb5", "a6"]);
+        assert_eq!(game.ply(), 6);
+
+        // Tags are escaped again
+        let again: Game = Game::from_pgn(&game.to_pgn()).unwrap();
+        assert_eq!(again.white, game.white);
+        assert_eq!(_sans(&again), _sans(&game));
+    }
+
+    #[test]
+    fn pgn_annotations_round_trip()
+    {
+        let pgn: &str = "{[%csl Re4]} 1. e4 {Center [%cal Gd1h5,Bf1c4] and [%csl Yf7]} e5 *";
+        let mut game: Game = Game::from_pgn(pgn).unwrap();
+        let square = |notation: &str| position::notation_to_square(notation).unwrap();
+        assert_eq!(game.current_annotations(), None);
+
+        game.go_to(1);
+        let annotations: Annotations = game.current_annotations().unwrap().clone();
+        assert_eq!(annotations.arrows, vec![(square("d1"), square("h5"), Mark::Green),
+                                            (square("f1"), square("c4"), Mark::Blue)]);
+        assert_eq!(annotations.circles, vec![(square("f7"), Mark::Yellow)]);
+
+        let again: Game = Game::from_pgn(&game.to_pgn()).unwrap();
+        assert_eq!(again.root.annotations, game.root.annotations);
+        assert_eq!(again.root.children[0].annotations, annotations);
+        assert!(game.to_pgn().contains("1. e4 {[%csl Yf7] [%cal Gd1h5,Bf1c4]} e5 *"));
+    }
+
+    // Why `pgn` can't be read, empty if it can
+    fn _error(pgn: &str) -> String
+    {
+        Game::from_pgn(pgn).err().unwrap_or_default()
+    }
+
+    #[test]
+    fn pgn_errors()
+    {
+        // Leaves the king in check
+        assert_eq!(_error("1. e4 e5 2. d4 Bb4+ 3. Nf3 *"), "3. Nf3 is not a legal move");
+        assert!(_error("1. e4 e5 2. Qh4 *").starts_with("2. Qh4: "));
+        assert!(_error("1. e4 Xx9 *").starts_with("1... Xx9: "));
+        assert_eq!(_error("1. e4 {[%cal Ge2e9]} *"), "Bad arrow \"Ge2e9\"");
+        assert!(_error("1. e4 {[%csl Gd4} *").starts_with("Unclosed command"));
+    }
+
+    #[test]
+    fn variations_round_trip()
+    {
+        let movetext: &str = "1. e4 e5 (1... c5 2. Nf3 (2. Nc3) 2... d6) 2. Nf3 *";
+        let game: Game = Game::from_pgn(movetext).unwrap();
+        assert_eq!(_sans(&game), vec!["e4", "e5", "Nf3"]);
+        assert_eq!(game.root.children[0].children.len(), 2);
+        assert!(game.to_pgn().ends_with(&format!("\n{}\n", movetext)));
+
+        assert_eq!(_error("(1. d4) 1. e4 *"), "A variation before the first move");
+        assert_eq!(_error("1. e4 ) *"), "Unexpected ')'");
+        assert_eq!(_error("1. e4 (1. d4 *"), "Unclosed variation");
+        // Played from the position before the move it replaces, d5 isn't there
+        assert!(_error("1. e4 d5 (1... e5 2. exd5) *").starts_with("2. exd5"));
+    }
+
+    #[test]
+    fn edit_variations()
+    {
+        let mut game: Game = Game::from_pgn("1. e4 e5 2. Nf3 *").unwrap();
+        let c5: PlayedMove = Game::from_pgn("1. e4 c5 *").unwrap().last_move().unwrap().clone();
+
+        // Another move starts a variation, the same one follows it
+        game.go_to(1);
+        game.play(c5.clone());
+        assert_eq!(_sans(&game), vec!["e4", "c5"]);
+        game.back();
+        game.play(c5);
+        assert_eq!(game.root.children[0].children.len(), 2);
+        assert!(game.promote_variation());
+        assert_eq!(game.root.children[0].children[0].mv.as_ref().unwrap().san, "c5");
+        assert_eq!(_sans(&game), vec!["e4", "c5"]);
+        assert!(!game.promote_variation());
+
+        game.go_to(1);
+        assert!(game.switch_variation(1));
+        assert_eq!(_sans(&game), vec!["e4", "e5", "Nf3"]);
+        assert_eq!(game.ply(), 1);
+
+        game.go_to(3);
+        assert!(game.delete_variation());
+        assert_eq!(game.ply(), 1);
+        assert_eq!(_sans(&game), vec!["e4", "c5"]);
+        assert_eq!(game.root.children[0].children.len(), 1);
+        assert!(!game.delete_variation());
+    }
+}
@@ -0,0 +1,87 @@
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+
+use crate::inet::{encode_c2s, decode_s2c};
+use crate::inet::codec::FrameError;
+use crate::inet::transport::{self, Transport, TransportKind};
+use crate::inet::items::{C2sConnectRequest, c2s_message, s2c_message};
+
+// Everything the network worker reports back to the GUI thread
+pub enum NetEvent
+{
+    Received(s2c_message::Msg),
+    Disconnected(String),
+}
+
+// Handle to the background network worker. The worker owns the transport
+// (TCP or WebSocket) so that the GUI thread never blocks on the network, messages are passed
+// in both directions through channels
+pub struct NetClient
+{
+    outgoing: Sender<c2s_message::Msg>,
+    incoming: Receiver<NetEvent>,
+}
+
+impl NetClient
+{
+    // Spawn the worker, which connects to `ip_port` and sends `request` as
+    // the first message as detailed in `src/inet/chess.proto`
+    pub fn connect(kind: TransportKind, ip_port: String, request: C2sConnectRequest) -> NetClient
+    {
+        let (out_tx, out_rx) = mpsc::channel::<c2s_message::Msg>();
+        let (in_tx, in_rx) = mpsc::channel::<NetEvent>();
+
+        thread::spawn(move || {
+            if let Err(err) = _run_worker(kind, &ip_port, request, out_rx, &in_tx)
+            {
+                let _ = in_tx.send(NetEvent::Disconnected(err.to_string()));
+            }
+        });
+
+        NetClient{outgoing: out_tx, incoming: in_rx}
+    }
+
+    pub fn send(&self, msg: c2s_message::Msg)
+    {
+        // If the worker is gone the disconnect is reported through `poll`
+        let _ = self.outgoing.send(msg);
+    }
+
+    // Non-blocking, returns the next event from the worker if there is one
+    pub fn poll(&self) -> Option<NetEvent>
+    {
+        self.incoming.try_recv().ok()
+    }
+}
+
+fn _run_worker(kind: TransportKind, ip_port: &str, request: C2sConnectRequest,
+    outgoing: Receiver<c2s_message::Msg>, incoming: &Sender<NetEvent>) -> Result<(), FrameError>
+{
+    let mut transport: Box<dyn Transport> = transport::connect(kind, ip_port)?;
+
+    transport.send(&encode_c2s(c2s_message::Msg::ConnectRequest(request)))?;
+
+    // Alternate between sending what the GUI queued and waiting a short
+    // while for incoming messages, until the connection is closed
+    loop
+    {
+        loop
+        {
+            match outgoing.try_recv()
+            {
+                Ok(msg) => transport.send(&encode_c2s(msg))?,
+                Err(TryRecvError::Empty) => break,
+                // The GUI dropped the client, nothing left to do
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        }
+
+        if let Some(body) = transport.recv()?
+        {
+            if let Some(msg) = decode_s2c(body.as_slice())?.msg
+            {
+                if incoming.send(NetEvent::Received(msg)).is_err() { return Ok(()); }
+            }
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use prost::DecodeError;
+
+// Largest frame body accepted by default, anything bigger is treated as
+// a protocol error instead of allocating whatever the peer asks for
+pub const DEFAULT_MAX_FRAME: usize = 64 * 1024;
+
+// A varint length prefix is at most 10 bytes long
+const MAX_PREFIX_LEN: usize = 10;
+
+// Everything that can go wrong while reading or writing a frame
+#[derive(Debug)]
+pub enum FrameError
+{
+    // The underlying stream failed. `WouldBlock`/`TimedOut` are not fatal,
+    // the partially read frame is kept and the read can be retried
+    Io(io::Error),
+    // The peer closed the stream between two frames
+    Closed,
+    // The peer closed the stream in the middle of a frame
+    Truncated,
+    // The length prefix is not a valid varint
+    BadLength,
+    // The frame is bigger than the configured cap
+    TooLarge(usize),
+    // The frame body is not a valid message
+    Decode(DecodeError),
+}
+
+impl fmt::Display for FrameError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            FrameError::Io(err) => write!(f, "{}", err),
+            FrameError::Closed => write!(f, "connection closed"),
+            FrameError::Truncated => write!(f, "connection closed in the middle of a message"),
+            FrameError::BadLength => write!(f, "invalid length prefix"),
+            FrameError::TooLarge(len) => write!(f, "message of {} bytes is too large", len),
+            FrameError::Decode(err) => write!(f, "invalid message: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+impl From<io::Error> for FrameError
+{
+    fn from(err: io::Error) -> FrameError
+    {
+        FrameError::Io(err)
+    }
+}
+
+impl From<DecodeError> for FrameError
+{
+    fn from(err: DecodeError) -> FrameError
+    {
+        FrameError::Decode(err)
+    }
+}
+
+// Reads varint length delimited messages (as written by prost's
+// `encode_length_delimited`) from a stream such as a `TcpStream`.
+// Bytes are buffered between calls, so short reads never lose data
+pub struct FramedReader<R>
+{
+    inner: R,
+    buf: Vec<u8>,
+    max_frame: usize,
+}
+
+impl<R: Read> FramedReader<R>
+{
+    pub fn new(inner: R) -> FramedReader<R>
+    {
+        FramedReader::with_max_frame(inner, DEFAULT_MAX_FRAME)
+    }
+
+    pub fn with_max_frame(inner: R, max_frame: usize) -> FramedReader<R>
+    {
+        FramedReader{inner, buf: Vec::new(), max_frame}
+    }
+
+    // Read until one whole frame is buffered and return its body
+    pub fn read_frame(&mut self) -> Result<Vec<u8>, FrameError>
+    {
+        loop
+        {
+            if let Some(frame) = self._take_frame()?
+            {
+                return Ok(frame);
+            }
+
+            let mut chunk: [u8; 4096] = [0; 4096];
+            let n: usize = match self.inner.read(&mut chunk)
+            {
+                Ok(n) => n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(FrameError::Io(err)),
+            };
+
+            if n == 0
+            {
+                return Err(if self.buf.is_empty() { FrameError::Closed } else { FrameError::Truncated });
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    // Split the first complete frame off the buffer, if there is one
+    fn _take_frame(&mut self) -> Result<Option<Vec<u8>>, FrameError>
+    {
+        let (len, prefix_len) = match _parse_prefix(&self.buf)?
+        {
+            Some(parsed) => parsed,
+            None => return Ok(None),
+        };
+
+        if len > self.max_frame as u64
+        {
+            return Err(FrameError::TooLarge(len as usize));
+        }
+
+        let end: usize = prefix_len + len as usize;
+        if self.buf.len() < end { return Ok(None); }
+
+        let frame: Vec<u8> = self.buf[prefix_len..end].to_vec();
+        self.buf.drain(..end);
+        Ok(Some(frame))
+    }
+}
+
+// Writes varint length delimited messages to a stream
+pub struct FramedWriter<W>
+{
+    inner: W,
+    max_frame: usize,
+}
+
+impl<W: Write> FramedWriter<W>
+{
+    pub fn new(inner: W) -> FramedWriter<W>
+    {
+        FramedWriter::with_max_frame(inner, DEFAULT_MAX_FRAME)
+    }
+
+    pub fn with_max_frame(inner: W, max_frame: usize) -> FramedWriter<W>
+    {
+        FramedWriter{inner, max_frame}
+    }
+
+    pub fn get_ref(&self) -> &W
+    {
+        &self.inner
+    }
+
+    // Write an already encoded message body, prefixed with its length
+    pub fn write_frame(&mut self, body: &[u8]) -> Result<(), FrameError>
+    {
+        if body.len() > self.max_frame
+        {
+            return Err(FrameError::TooLarge(body.len()));
+        }
+
+        let mut frame: Vec<u8> = Vec::with_capacity(body.len() + MAX_PREFIX_LEN);
+        prost::encoding::encode_varint(body.len() as u64, &mut frame);
+        frame.extend_from_slice(body);
+
+        self.inner.write_all(&frame)?;
+        self.inner.flush()?;
+        Ok(())
+    }
+}
+
+// Parse the varint at the start of `buf`. Returns the value and the prefix
+// length, or `None` if more bytes are needed
+fn _parse_prefix(buf: &[u8]) -> Result<Option<(u64, usize)>, FrameError>
+{
+    let mut value: u64 = 0;
+    for (i, byte) in buf.iter().enumerate()
+    {
+        if i >= MAX_PREFIX_LEN { return Err(FrameError::BadLength); }
+
+        value |= ((byte & 0x7f) as u64) << (7*i);
+        if byte & 0x80 == 0 { return Ok(Some((value, i+1))); }
+    }
+
+    if buf.len() >= MAX_PREFIX_LEN { return Err(FrameError::BadLength); }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::inet::{encode_c2s, decode_c2s};
+    use crate::inet::items::{c2s_message, ChatMessage};
+
+    // Hands out one byte per read, the shortest reads a stream can do
+    struct OneByte
+    {
+        bytes: Vec<u8>,
+        pos: usize,
+    }
+
+    impl Read for OneByte
+    {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+        {
+            if self.pos >= self.bytes.len() || buf.is_empty() { return Ok(0); }
+            buf[0] = self.bytes[self.pos];
+            self.pos += 1;
+            Ok(1)
+        }
+    }
+
+    fn _reader(bytes: Vec<u8>) -> FramedReader<OneByte>
+    {
+        FramedReader::new(OneByte{bytes, pos: 0})
+    }
+
+    fn _framed(bodies: &[&[u8]]) -> Vec<u8>
+    {
+        let mut writer: FramedWriter<Vec<u8>> = FramedWriter::new(Vec::new());
+        for body in bodies
+        {
+            writer.write_frame(body).unwrap();
+        }
+        writer.inner
+    }
+
+    #[test]
+    fn frames_survive_one_byte_reads()
+    {
+        // 300 bytes needs a two byte prefix
+        let long: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        let bytes: Vec<u8> = _framed(&[b"hello", &long, b""]);
+        assert_eq!(&bytes[6..8], &[0xac, 0x02]);
+
+        let mut reader: FramedReader<OneByte> = _reader(bytes);
+        assert_eq!(reader.read_frame().unwrap(), b"hello");
+        assert_eq!(reader.read_frame().unwrap(), long);
+        assert_eq!(reader.read_frame().unwrap(), b"");
+        assert!(matches!(reader.read_frame(), Err(FrameError::Closed)));
+    }
+
+    #[test]
+    fn messages_round_trip()
+    {
+        let body: Vec<u8> = encode_c2s(c2s_message::Msg::Chat(ChatMessage{text: String::from("gg"), sender: None}));
+        let mut reader: FramedReader<OneByte> = _reader(_framed(&[&body]));
+        let frame: Vec<u8> = reader.read_frame().unwrap();
+        assert_eq!(decode_c2s(&frame).unwrap().msg, Some(c2s_message::Msg::Chat(ChatMessage{text: String::from("gg"), sender: None})));
+    }
+
+    #[test]
+    fn eof_between_and_inside_frames()
+    {
+        assert!(matches!(_reader(Vec::new()).read_frame(), Err(FrameError::Closed)));
+
+        // In the middle of the prefix
+        assert!(matches!(_reader(vec![0xac]).read_frame(), Err(FrameError::Truncated)));
+
+        // After the prefix, before the whole body
+        let mut bytes: Vec<u8> = _framed(&[b"hello"]);
+        bytes.truncate(3);
+        assert!(matches!(_reader(bytes).read_frame(), Err(FrameError::Truncated)));
+    }
+
+    #[test]
+    fn long_prefix_is_bad_length()
+    {
+        let mut bytes: Vec<u8> = vec![0x80; MAX_PREFIX_LEN];
+        bytes.push(0x01);
+        assert!(matches!(_reader(bytes).read_frame(), Err(FrameError::BadLength)));
+    }
+
+    #[test]
+    fn oversize_frames_are_refused()
+    {
+        let body: Vec<u8> = vec![0; 100];
+        let mut bytes: Vec<u8> = _framed(&[&body]);
+        assert!(matches!(FramedReader::with_max_frame(OneByte{bytes: bytes.clone(), pos: 0}, 99).read_frame(),
+            Err(FrameError::TooLarge(100))));
+
+        // Refused before the body arrives
+        bytes.truncate(1);
+        assert!(matches!(FramedReader::with_max_frame(OneByte{bytes, pos: 0}, 99).read_frame(),
+            Err(FrameError::TooLarge(100))));
+
+        let mut writer: FramedWriter<Vec<u8>> = FramedWriter::with_max_frame(Vec::new(), 99);
+        assert!(matches!(writer.write_frame(&body), Err(FrameError::TooLarge(100))));
+        assert!(writer.inner.is_empty());
+    }
+
+    #[test]
+    fn undecodable_body()
+    {
+        // Field 1 claims 5 bytes but there are none
+        let mut reader: FramedReader<OneByte> = _reader(_framed(&[&[0x0a, 0x05]]));
+        let frame: Vec<u8> = reader.read_frame().unwrap();
+        let result: Result<_, FrameError> = decode_c2s(&frame).map_err(FrameError::from);
+        assert!(matches!(result, Err(FrameError::Decode(_))));
+    }
+}
@@ -0,0 +1,154 @@
+use std::io;
+use std::net::{UdpSocket, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::inet::transport::TransportKind;
+use crate::inet::version::PROTOCOL_VERSION;
+
+// UDP port the announcements are broadcast to
+pub const DISCOVERY_PORT: u16 = 1338;
+
+// Identifies our announcements among other broadcast traffic
+const SERVICE_NAME: &str = "chess-gui";
+
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+
+// A game that hasn't been announced for this long is dropped from the list
+const EXPIRE_AFTER: Duration = Duration::from_secs(5);
+
+// One joinable game, as announced by a server or a hosting client
+#[derive(Clone, PartialEq, Debug)]
+pub struct DiscoveredGame
+{
+    pub name: String,
+    pub ip_port: String,
+    pub transport: TransportKind,
+    pub game_id: u64,
+    pub players: u32,
+    pub spectators: u32,
+}
+
+// The games a server currently hosts, (game id, players, spectators).
+// Shared with the announcer thread so the counts stay up to date, the
+// announcer stops once it holds the last reference
+pub type AnnouncedGames = Arc<Mutex<Vec<(u64, u32, u32)>>>;
+
+// Broadcast the games behind `port` on the LAN every `ANNOUNCE_INTERVAL`,
+// as a small json object:
+// {"service": "chess-gui", "version": 1, "name": "..", "port": 1337,
+//  "transport": "tcp", "games": [{"game-id": 0, "players": 1, "spectators": 0}]}
+pub fn spawn_announcer(name: String, port: u16, transport: TransportKind,
+    games: AnnouncedGames) -> io::Result<()>
+{
+    let socket: UdpSocket = UdpSocket::bind(("0.0.0.0", 0))?;
+    socket.set_broadcast(true)?;
+
+    thread::spawn(move || {
+        while Arc::strong_count(&games) > 1
+        {
+            let mut announcement = json::object!{
+                "service": SERVICE_NAME,
+                "version": PROTOCOL_VERSION,
+                "name": name.as_str(),
+                "port": port,
+                "transport": transport.name(),
+                "games": json::JsonValue::new_array()
+            };
+
+            for (game_id, players, spectators) in games.lock().unwrap().iter()
+            {
+                let _ = announcement["games"].push(json::object!{
+                    "game-id": *game_id,
+                    "players": *players,
+                    "spectators": *spectators
+                });
+            }
+
+            // Nobody listening is not an error, just try again later
+            let _ = socket.send_to(announcement.dump().as_bytes(), ("255.255.255.255", DISCOVERY_PORT));
+            thread::sleep(ANNOUNCE_INTERVAL);
+        }
+    });
+
+    Ok(())
+}
+
+// Listens for announcements and keeps the list of currently visible games
+pub struct Discovery
+{
+    socket: UdpSocket,
+    found: Vec<(DiscoveredGame, Instant)>,
+}
+
+impl Discovery
+{
+    pub fn listen() -> io::Result<Discovery>
+    {
+        let socket: UdpSocket = UdpSocket::bind(("0.0.0.0", DISCOVERY_PORT))?;
+        socket.set_nonblocking(true)?;
+        Ok(Discovery{socket, found: Vec::new()})
+    }
+
+    // Non-blocking, handles all received announcements and returns the games
+    // seen recently, sorted by name and game id
+    pub fn poll(&mut self) -> Vec<DiscoveredGame>
+    {
+        let mut buf: [u8; 2048] = [0; 2048];
+        while let Ok((len, from)) = self.socket.recv_from(&mut buf)
+        {
+            let now: Instant = Instant::now();
+            for game in _parse_announcement(&buf[..len], from)
+            {
+                self.found.retain(|(known, _)| !(known.ip_port == game.ip_port
+                                                && known.game_id == game.game_id));
+                self.found.push((game, now));
+            }
+        }
+
+        self.found.retain(|(_, seen)| seen.elapsed() < EXPIRE_AFTER);
+
+        let mut games: Vec<DiscoveredGame> = self.found.iter().map(|(game, _)| game.clone()).collect();
+        games.sort_by(|a, b| (&a.name, a.game_id).cmp(&(&b.name, b.game_id)));
+        games
+    }
+}
+
+// Turn one datagram into the games it announces, anything malformed
+// or from another protocol version is ignored
+fn _parse_announcement(data: &[u8], from: SocketAddr) -> Vec<DiscoveredGame>
+{
+    let parsed = match std::str::from_utf8(data).ok().and_then(|text| json::parse(text).ok())
+    {
+        Some(parsed) => parsed,
+        None => return Vec::new(),
+    };
+
+    if parsed["service"].as_str() != Some(SERVICE_NAME)
+        || parsed["version"].as_u32() != Some(PROTOCOL_VERSION)
+    {
+        return Vec::new();
+    }
+
+    let port: u16 = match parsed["port"].as_u16() { Some(port) => port, None => return Vec::new() };
+    let transport: TransportKind = match TransportKind::from_config(
+        parsed["transport"].as_str().unwrap_or("tcp"))
+    {
+        Ok(transport) => transport,
+        Err(_) => return Vec::new(),
+    };
+    let ip_port: String = SocketAddr::new(from.ip(), port).to_string();
+    let name: String = parsed["name"].as_str().unwrap_or("Unnamed").to_string();
+
+    parsed["games"].members()
+        .filter_map(|game| Some(DiscoveredGame{
+            name: name.clone(),
+            ip_port: ip_port.clone(),
+            transport,
+            game_id: game["game-id"].as_u64()?,
+            players: game["players"].as_u32().unwrap_or(0),
+            spectators: game["spectators"].as_u32().unwrap_or(0),
+        }))
+        .collect()
+}
@@ -0,0 +1,376 @@
+use std::io;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::chat::RateLimit;
+use crate::inet::{encode_s2c, decode_c2s};
+use crate::inet::discovery::{self, AnnouncedGames};
+use crate::inet::transport::{Transport, TcpTransport, TransportKind};
+use crate::inet::version::{self, Negotiated};
+use crate::inet::items::{Move, ChatMessage, BoardState, C2sConnectRequest, S2cConnectAck,
+    S2cMoveAck, c2s_message, s2c_message};
+
+// How long the server thread sleeps when nothing happened
+const IDLE_SLEEP: Duration = Duration::from_millis(5);
+
+// Capabilities offered by the hosting GUI
+const HOST_CAPABILITIES: &[&str] = &[version::CAP_CHAT];
+
+// A connection has this long to send its connect request, and only so many
+// can be waiting for theirs. Beyond that the oldest one is closed, someone
+// who just connected is more likely to be about to send it
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_PENDING: usize = 8;
+
+// What the server thread reports to the hosting GUI
+pub enum HostEvent
+{
+    OpponentJoined,
+    OpponentLeft,
+    // The opponent wants to play this move, answer with `HostCommand::MoveResult`
+    Move(Move),
+    // Chat from one of the clients, `sender` is filled in
+    Chat(ChatMessage),
+}
+
+// What the hosting GUI asks the server thread to do
+pub enum HostCommand
+{
+    // Verdict on the last `HostEvent::Move`, `fen` is the position after it
+    MoveResult{mv: Move, legal: bool, fen: String},
+    // The host played a move, `fen` is the position after it
+    HostMove{mv: Move, fen: String},
+    // The host wrote in the chat
+    Chat(String),
+}
+
+// A connected client that finished the handshake
+struct Peer
+{
+    transport: Box<dyn Transport>,
+    negotiated: Negotiated,
+    // Chat from this client beyond the limit is dropped, not relayed
+    chat_limit: RateLimit,
+}
+
+// Lets the GUI act as the server of one game: accepts a single opponent and
+// any number of spectators speaking chess.proto. The server thread only
+// does the networking, moves are validated by the GUI's own `ChessEngine`
+pub struct HostServer
+{
+    pub port: u16,
+    commands: Sender<HostCommand>,
+    events: Receiver<HostEvent>,
+}
+
+impl HostServer
+{
+    pub fn start(port: u16, game_id: u64, host_is_white: bool, fen: String) -> io::Result<HostServer>
+    {
+        let listener: TcpListener = TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        let port: u16 = listener.local_addr()?.port();
+
+        // Announce the game on the LAN so it shows up in other lobbies
+        let announced: AnnouncedGames = Arc::new(Mutex::new(vec![(game_id, 1, 0)]));
+        discovery::spawn_announcer(String::from("Hosted game"), port, TransportKind::Tcp,
+                                   announced.clone())?;
+
+        let (cmd_tx, cmd_rx) = mpsc::channel::<HostCommand>();
+        let (event_tx, event_rx) = mpsc::channel::<HostEvent>();
+
+        let mut server = HostThread{
+            listener,
+            game_id,
+            host_is_white,
+            fen,
+            pending: Vec::new(),
+            opponent: None,
+            spectators: Vec::new(),
+            announced,
+            events: event_tx,
+        };
+        thread::spawn(move || server.run(cmd_rx));
+
+        Ok(HostServer{port, commands: cmd_tx, events: event_rx})
+    }
+
+    pub fn send(&self, command: HostCommand)
+    {
+        let _ = self.commands.send(command);
+    }
+
+    // Non-blocking, returns the next event from the server thread if there is one
+    pub fn poll(&self) -> Option<HostEvent>
+    {
+        self.events.try_recv().ok()
+    }
+}
+
+struct HostThread
+{
+    listener: TcpListener,
+    game_id: u64,
+    host_is_white: bool,
+    // Current position, sent to everyone who joins
+    fen: String,
+    // Connections that haven't sent their connect request yet, with the
+    // time they have to. Non-blocking, so they don't hold up the game
+    pending: Vec<(TcpTransport, Instant)>,
+    opponent: Option<Peer>,
+    spectators: Vec<Peer>,
+    announced: AnnouncedGames,
+    events: Sender<HostEvent>,
+}
+
+impl HostThread
+{
+    // Runs until the GUI drops its `HostServer`
+    fn run(&mut self, commands: Receiver<HostCommand>)
+    {
+        loop
+        {
+            loop
+            {
+                match commands.try_recv()
+                {
+                    Ok(command) => self._handle_command(command),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            }
+
+            self._accept();
+            self._handle_pending();
+            self._handle_peers();
+
+            *self.announced.lock().unwrap() = vec![(self.game_id,
+                1 + self.opponent.is_some() as u32, self.spectators.len() as u32)];
+
+            thread::sleep(IDLE_SLEEP);
+        }
+    }
+
+    fn _handle_command(&mut self, command: HostCommand)
+    {
+        match command
+        {
+            HostCommand::MoveResult{mv, legal, fen} => {
+                if legal { self.fen = fen.clone(); }
+
+                let ack = s2c_message::Msg::MoveAck(S2cMoveAck{
+                    legal,
+                    board_result: Some(BoardState{fen_string: fen})
+                });
+                if let Some(opponent) = &mut self.opponent
+                {
+                    if opponent.transport.send(&encode_s2c(ack)).is_err() { self._drop_opponent(); }
+                }
+
+                if legal { self._send_spectators(s2c_message::Msg::Move(mv)); }
+            },
+            HostCommand::HostMove{mv, fen} => {
+                self.fen = fen;
+                if let Some(opponent) = &mut self.opponent
+                {
+                    if opponent.transport.send(&encode_s2c(s2c_message::Msg::Move(mv.clone()))).is_err()
+                    {
+                        self._drop_opponent();
+                    }
+                }
+                self._send_spectators(s2c_message::Msg::Move(mv));
+            },
+            HostCommand::Chat(text) => {
+                let sender: &str = if self.host_is_white { "White" } else { "Black" };
+                self._relay_chat(ChatMessage{text, sender: Some(sender.to_string())}, true);
+            }
+        }
+    }
+
+    fn _accept(&mut self)
+    {
+        while let Ok((stream, _)) = self.listener.accept()
+        {
+            if self.pending.len() >= MAX_PENDING { self.pending.remove(0); }
+
+            // Made blocking once it's seated, see `_handle_pending`
+            let _ = stream.set_nonblocking(true);
+            if let Ok(transport) = TcpTransport::from_stream(stream)
+            {
+                self.pending.push((transport, Instant::now() + HANDSHAKE_TIMEOUT));
+            }
+        }
+    }
+
+    // Check for the connect request of new connections and seat them. The
+    // ones out of time are dropped
+    fn _handle_pending(&mut self)
+    {
+        let mut still_pending: Vec<(TcpTransport, Instant)> = Vec::new();
+
+        for (mut transport, deadline) in std::mem::take(&mut self.pending)
+        {
+            let request: C2sConnectRequest = match transport.recv()
+            {
+                Ok(None) if Instant::now() < deadline => { still_pending.push((transport, deadline)); continue; },
+                Ok(None) => continue,
+                Ok(Some(body)) => match decode_c2s(body.as_slice()).ok().and_then(|msg| msg.msg)
+                {
+                    Some(c2s_message::Msg::ConnectRequest(request)) => request,
+                    // Anything else before the handshake is a protocol error
+                    _ => continue,
+                },
+                Err(_) => continue,
+            };
+            // Seated peers are read with the usual timeout
+            if transport.set_nonblocking(false).is_err() { continue; }
+            let mut transport: Box<dyn Transport> = Box::new(transport);
+
+            let mut ack: S2cConnectAck = S2cConnectAck{
+                success: false,
+                game_id: Some(self.game_id),
+                starting_position: None,
+                client_is_white: None,
+                protocol_version: version::PROTOCOL_VERSION,
+                capabilities: Vec::new(),
+                error: None,
+            };
+
+            let negotiated: Negotiated = match version::negotiate(&request, HOST_CAPABILITIES)
+            {
+                Ok(negotiated) => negotiated,
+                Err(reason) => {
+                    ack.error = Some(reason);
+                    let _ = transport.send(&encode_s2c(s2c_message::Msg::ConnectAck(ack)));
+                    continue;
+                }
+            };
+
+            if request.game_id != self.game_id
+            {
+                ack.error = Some(format!("No game {} here, this host runs game {}", request.game_id, self.game_id));
+            }
+            else if !request.spectate && self.opponent.is_some()
+            {
+                ack.error = Some(String::from("The game is full, join as spectator"));
+            }
+            else
+            {
+                ack.success = true;
+                ack.starting_position = Some(BoardState{fen_string: self.fen.clone()});
+                ack.client_is_white = if request.spectate { None } else { Some(!self.host_is_white) };
+            }
+
+            ack.protocol_version = negotiated.protocol_version;
+            ack.capabilities = negotiated.capabilities.clone();
+            let success: bool = ack.success;
+
+            if transport.send(&encode_s2c(s2c_message::Msg::ConnectAck(ack))).is_err() || !success
+            {
+                continue;
+            }
+
+            let peer: Peer = Peer{transport, negotiated, chat_limit: RateLimit::new()};
+            if request.spectate
+            {
+                self.spectators.push(peer);
+            }
+            else
+            {
+                self.opponent = Some(peer);
+                let _ = self.events.send(HostEvent::OpponentJoined);
+            }
+        }
+
+        self.pending = still_pending;
+    }
+
+    // Read everything the opponent and the spectators sent
+    fn _handle_peers(&mut self)
+    {
+        let mut chats: Vec<ChatMessage> = Vec::new();
+
+        if let Some(opponent) = &mut self.opponent
+        {
+            match opponent.transport.recv()
+            {
+                Ok(Some(body)) => match decode_c2s(body.as_slice()).ok().and_then(|msg| msg.msg)
+                {
+                    Some(c2s_message::Msg::Move(mv)) => { let _ = self.events.send(HostEvent::Move(mv)); },
+                    Some(c2s_message::Msg::Chat(mut chat)) if opponent.chat_limit.allow() => {
+                        chat.sender = Some(String::from(if self.host_is_white { "Black" } else { "White" }));
+                        chats.push(chat);
+                    },
+                    _ => ()
+                },
+                Ok(None) => (),
+                Err(_) => self._drop_opponent(),
+            }
+        }
+
+        let mut i: usize = 0;
+        while i < self.spectators.len()
+        {
+            match self.spectators[i].transport.recv()
+            {
+                Ok(Some(body)) => {
+                    // Spectators can only chat
+                    if let Some(c2s_message::Msg::Chat(mut chat)) =
+                        decode_c2s(body.as_slice()).ok().and_then(|msg| msg.msg)
+                    {
+                        if !self.spectators[i].chat_limit.allow() { i += 1; continue; }
+                        chat.sender = Some(String::from("Spectator"));
+                        chats.push(chat);
+                    }
+                    i += 1;
+                },
+                Ok(None) => i += 1,
+                Err(_) => { self.spectators.remove(i); },
+            }
+        }
+
+        for chat in chats
+        {
+            self._relay_chat(chat, false);
+        }
+    }
+
+    // Send a chat line to every client that supports chat, and to the host
+    // unless it wrote the line itself
+    fn _relay_chat(&mut self, mut chat: ChatMessage, from_host: bool)
+    {
+        chat.text = chat.text.chars().take(crate::chat::MAX_CHAT_LEN).collect();
+
+        let msg = s2c_message::Msg::Chat(chat.clone());
+        if let Some(opponent) = &mut self.opponent
+        {
+            if opponent.negotiated.has(version::CAP_CHAT) && opponent.transport.send(&encode_s2c(msg.clone())).is_err()
+            {
+                self._drop_opponent();
+            }
+        }
+        self.spectators.retain_mut(|spectator| !spectator.negotiated.has(version::CAP_CHAT)
+                                        || spectator.transport.send(&encode_s2c(msg.clone())).is_ok());
+
+        if !from_host
+        {
+            let _ = self.events.send(HostEvent::Chat(chat));
+        }
+    }
+
+    fn _send_spectators(&mut self, msg: s2c_message::Msg)
+    {
+        self.spectators.retain_mut(|spectator| spectator.transport.send(&encode_s2c(msg.clone())).is_ok());
+    }
+
+    fn _drop_opponent(&mut self)
+    {
+        if self.opponent.take().is_some()
+        {
+            let _ = self.events.send(HostEvent::OpponentLeft);
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use chess::piece_types::PieceTypes;
+use prost::{Message, DecodeError};
+
+pub mod client;
+pub mod codec;
+pub mod version;
+pub mod transport;
+pub mod discovery;
+pub mod host;
+
+// Types generated from `chess.proto` by build.rs
+pub mod items {
+    include!(concat!(env!("OUT_DIR"), "/networking_prep.networking.rs"));
+}
+
+use items::{C2sMessage, S2cMessage, Piece, c2s_message, s2c_message};
+
+// Wrap and serialize a client message. The transport adds the framing
+pub fn encode_c2s(msg: c2s_message::Msg) -> Vec<u8>
+{
+    C2sMessage{msg: Some(msg)}.encode_to_vec()
+}
+
+// Wrap and serialize a server message. The transport adds the framing
+pub fn encode_s2c(msg: s2c_message::Msg) -> Vec<u8>
+{
+    S2cMessage{msg: Some(msg)}.encode_to_vec()
+}
+
+// Decode a client message body (without the length prefix)
+pub fn decode_c2s(buf: &[u8]) -> Result<C2sMessage, DecodeError>
+{
+    C2sMessage::decode(buf)
+}
+
+// Decode a server message body (without the length prefix)
+pub fn decode_s2c(buf: &[u8]) -> Result<S2cMessage, DecodeError>
+{
+    S2cMessage::decode(buf)
+}
+
+// The piece of `Move.promotion`, `None` without one or for a piece a pawn
+// can't become
+pub fn promotion_from_proto(promotion: Option<i32>) -> Option<PieceTypes>
+{
+    match Piece::from_i32(promotion?)?
+    {
+        Piece::Knight => Some(PieceTypes::Knight),
+        Piece::Bishop => Some(PieceTypes::Bishop),
+        Piece::Rook => Some(PieceTypes::Rook),
+        Piece::Queen => Some(PieceTypes::Queen),
+        Piece::Pawn | Piece::King => None,
+    }
+}
+
+pub fn promotion_to_proto(promotion: Option<PieceTypes>) -> Option<i32>
+{
+    let piece: Piece = match promotion?
+    {
+        PieceTypes::Knight => Piece::Knight,
+        PieceTypes::Bishop => Piece::Bishop,
+        PieceTypes::Rook => Piece::Rook,
+        PieceTypes::Queen => Piece::Queen,
+        PieceTypes::Pawn | PieceTypes::King => return None,
+    };
+    Some(piece as i32)
+}
@@ -0,0 +1,330 @@
+use std::io;
+use std::net::{TcpListener, TcpStream, SocketAddr};
+use std::thread;
+use std::time::Duration;
+
+use tungstenite::{Message as WsMessage, WebSocket};
+use tungstenite::protocol::WebSocketConfig;
+
+use crate::inet::codec::{FramedReader, FramedWriter, FrameError, DEFAULT_MAX_FRAME};
+
+// How long a `recv` waits for data before giving the caller a chance to
+// send queued messages
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+// Carries encoded `chess.proto` messages (without any length prefix) to and
+// from the peer, so the network worker doesn't care how they are framed
+pub trait Transport: Send
+{
+    fn send(&mut self, body: &[u8]) -> Result<(), FrameError>;
+
+    // Wait up to `POLL_INTERVAL` for one message body, `None` if none arrived
+    fn recv(&mut self) -> Result<Option<Vec<u8>>, FrameError>;
+}
+
+// Which transport to use, the `transport` key in config/server.json
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TransportKind
+{
+    Tcp,
+    WebSocket,
+}
+
+impl TransportKind
+{
+    pub fn from_config(name: &str) -> Result<TransportKind, String>
+    {
+        match name
+        {
+            "tcp" => Ok(TransportKind::Tcp),
+            "ws" | "websocket" => Ok(TransportKind::WebSocket),
+            _ => Err(format!("Unknown transport \"{}\", use \"tcp\" or \"ws\"", name))
+        }
+    }
+
+    // The name `from_config` reads back
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            TransportKind::Tcp => "tcp",
+            TransportKind::WebSocket => "ws",
+        }
+    }
+}
+
+// Connect to `ip_port` with the chosen transport
+pub fn connect(kind: TransportKind, ip_port: &str) -> Result<Box<dyn Transport>, FrameError>
+{
+    match kind
+    {
+        TransportKind::Tcp => Ok(Box::new(TcpTransport::connect(ip_port)?)),
+        TransportKind::WebSocket => Ok(Box::new(WsTransport::connect(ip_port)?)),
+    }
+}
+
+// Raw TCP, every message is prefixed with its varint length
+pub struct TcpTransport
+{
+    reader: FramedReader<TcpStream>,
+    writer: FramedWriter<TcpStream>,
+}
+
+impl TcpTransport
+{
+    pub fn connect(ip_port: &str) -> Result<TcpTransport, FrameError>
+    {
+        TcpTransport::from_stream(TcpStream::connect(ip_port)?)
+    }
+
+    pub fn from_stream(stream: TcpStream) -> Result<TcpTransport, FrameError>
+    {
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        Ok(TcpTransport{
+            writer: FramedWriter::new(stream.try_clone()?),
+            reader: FramedReader::new(stream)
+        })
+    }
+
+    // When non-blocking, `recv` returns at once instead of waiting up to
+    // `POLL_INTERVAL`, and so can `send`, before all was written
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>
+    {
+        // The reader's stream is a clone of the same socket
+        self.writer.get_ref().set_nonblocking(nonblocking)
+    }
+}
+
+impl Transport for TcpTransport
+{
+    fn send(&mut self, body: &[u8]) -> Result<(), FrameError>
+    {
+        self.writer.write_frame(body)
+    }
+
+    fn recv(&mut self) -> Result<Option<Vec<u8>>, FrameError>
+    {
+        match self.reader.read_frame()
+        {
+            Ok(frame) => Ok(Some(frame)),
+            Err(FrameError::Io(err)) if _is_timeout(&err) => Ok(None),
+            Err(err) => Err(err)
+        }
+    }
+}
+
+// WebSocket, every message is sent as one binary frame. Goes through
+// proxies that only let HTTP traffic through
+pub struct WsTransport
+{
+    socket: WebSocket<TcpStream>,
+}
+
+impl WsTransport
+{
+    pub fn connect(ip_port: &str) -> Result<WsTransport, FrameError>
+    {
+        let stream: TcpStream = TcpStream::connect(ip_port)?;
+        let url: String = format!("ws://{}/", ip_port);
+
+        let (socket, _) = tungstenite::client::client_with_config(url.as_str(), stream, Some(_ws_config()))
+            .map_err(|err| FrameError::Io(io::Error::other(err.to_string())))?;
+
+        WsTransport::from_socket(socket)
+    }
+
+    // Server side, do the handshake on an accepted connection
+    pub fn accept(stream: TcpStream) -> Result<WsTransport, FrameError>
+    {
+        let socket: WebSocket<TcpStream> = tungstenite::accept_with_config(stream, Some(_ws_config()))
+            .map_err(|err| FrameError::Io(io::Error::other(err.to_string())))?;
+
+        WsTransport::from_socket(socket)
+    }
+
+    fn from_socket(socket: WebSocket<TcpStream>) -> Result<WsTransport, FrameError>
+    {
+        // Only set after the handshake, which is done blocking
+        socket.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+        Ok(WsTransport{socket})
+    }
+}
+
+impl Transport for WsTransport
+{
+    fn send(&mut self, body: &[u8]) -> Result<(), FrameError>
+    {
+        if body.len() > DEFAULT_MAX_FRAME
+        {
+            return Err(FrameError::TooLarge(body.len()));
+        }
+
+        Ok(self.socket.send(WsMessage::Binary(body.to_vec()))?)
+    }
+
+    fn recv(&mut self) -> Result<Option<Vec<u8>>, FrameError>
+    {
+        match self.socket.read()
+        {
+            Ok(WsMessage::Binary(data)) => Ok(Some(data)),
+            Ok(WsMessage::Close(_)) => Err(FrameError::Closed),
+            // Pings are answered by tungstenite itself, text is not part
+            // of the protocol
+            Ok(_) => Ok(None),
+            Err(tungstenite::Error::Io(err)) if _is_timeout(&err) => {
+                // Let queued pongs/closes go out while idle
+                match self.socket.flush()
+                {
+                    Err(tungstenite::Error::Io(err)) if _is_timeout(&err) => Ok(None),
+                    Err(err) => Err(err.into()),
+                    Ok(()) => Ok(None)
+                }
+            },
+            Err(err) => Err(err.into())
+        }
+    }
+}
+
+impl From<tungstenite::Error> for FrameError
+{
+    fn from(err: tungstenite::Error) -> FrameError
+    {
+        match err
+        {
+            tungstenite::Error::Io(err) => FrameError::Io(err),
+            tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => FrameError::Closed,
+            tungstenite::Error::Capacity(_) => FrameError::TooLarge(DEFAULT_MAX_FRAME),
+            err => FrameError::Io(io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+        }
+    }
+}
+
+// Listen for WebSocket clients on `listen` and relay each of them to the TCP
+// server at `upstream`. Lets WebSocket clients play on a plain TCP server and
+// doubles as a local endpoint for testing the WebSocket transport.
+// Returns the bound address (useful with port 0), the relay runs in the background
+pub fn spawn_ws_relay(listen: &str, upstream: &str) -> io::Result<SocketAddr>
+{
+    let listener: TcpListener = TcpListener::bind(listen)?;
+    let addr: SocketAddr = listener.local_addr()?;
+    let upstream: String = upstream.to_string();
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten()
+        {
+            let upstream: String = upstream.clone();
+            thread::spawn(move || {
+                let client = WsTransport::accept(stream);
+                let server = TcpTransport::connect(&upstream);
+                if let (Ok(mut client), Ok(mut server)) = (client, server)
+                {
+                    let _ = _pump(&mut client, &mut server);
+                }
+            });
+        }
+    });
+
+    Ok(addr)
+}
+
+// Forward messages both ways until either side closes
+fn _pump(a: &mut dyn Transport, b: &mut dyn Transport) -> Result<(), FrameError>
+{
+    loop
+    {
+        if let Some(body) = a.recv()? { b.send(&body)?; }
+        if let Some(body) = b.recv()? { a.send(&body)?; }
+    }
+}
+
+fn _ws_config() -> WebSocketConfig
+{
+    WebSocketConfig{
+        max_message_size: Some(DEFAULT_MAX_FRAME),
+        max_frame_size: Some(DEFAULT_MAX_FRAME),
+        ..WebSocketConfig::default()
+    }
+}
+
+fn _is_timeout(err: &io::Error) -> bool
+{
+    err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Instant;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    // A TCP server that echoes `echoes` messages and then closes, or keeps
+    // echoing until the peer closes. Reports on `done` when it stopped
+    fn _spawn_echo_server(echoes: usize, done: mpsc::Sender<()>) -> SocketAddr
+    {
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr: SocketAddr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut server: TcpTransport = TcpTransport::from_stream(stream).unwrap();
+            let mut echoed: usize = 0;
+            while echoed < echoes
+            {
+                match server.recv()
+                {
+                    Ok(Some(body)) => {
+                        if server.send(&body).is_err() { break; }
+                        echoed += 1;
+                    },
+                    Ok(None) => (),
+                    Err(_) => break,
+                }
+            }
+            let _ = done.send(());
+        });
+        addr
+    }
+
+    fn _recv(transport: &mut dyn Transport) -> Result<Vec<u8>, FrameError>
+    {
+        let start: Instant = Instant::now();
+        while start.elapsed() < TIMEOUT
+        {
+            if let Some(body) = transport.recv()? { return Ok(body); }
+        }
+        panic!("nothing received in {:?}", TIMEOUT);
+    }
+
+    #[test]
+    fn relay_echoes_and_closes_upstream()
+    {
+        let (done, server_done) = mpsc::channel::<()>();
+        let upstream: SocketAddr = _spawn_echo_server(usize::MAX, done);
+        let relay: SocketAddr = spawn_ws_relay("127.0.0.1:0", &upstream.to_string()).unwrap();
+
+        let mut client: WsTransport = WsTransport::connect(&relay.to_string()).unwrap();
+        client.send(b"e2e4").unwrap();
+        assert_eq!(_recv(&mut client).unwrap(), b"e2e4");
+
+        // The client going away ends the relayed TCP connection too
+        drop(client);
+        assert!(server_done.recv_timeout(TIMEOUT).is_ok());
+    }
+
+    #[test]
+    fn relay_closes_client_when_upstream_closes()
+    {
+        let (done, server_done) = mpsc::channel::<()>();
+        let upstream: SocketAddr = _spawn_echo_server(1, done);
+        let relay: SocketAddr = spawn_ws_relay("127.0.0.1:0", &upstream.to_string()).unwrap();
+
+        let mut client: WsTransport = WsTransport::connect(&relay.to_string()).unwrap();
+        client.send(b"e7e5").unwrap();
+        assert_eq!(_recv(&mut client).unwrap(), b"e7e5");
+        assert!(server_done.recv_timeout(TIMEOUT).is_ok());
+
+        assert!(_recv(&mut client).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+use crate::inet::items::{C2sConnectRequest, S2cConnectAck};
+
+// Version of `chess.proto` spoken by this build. Bump it whenever a change
+// would make an older peer misinterpret messages
+pub const PROTOCOL_VERSION: u32 = 1;
+
+// Oldest peer version this build can still talk to. Peers that predate
+// negotiation send (or default to) 0 and are refused with a clear message
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+// Optional features, only used on a connection if both sides list them.
+// Servers may also offer e.g. "clocks", which this build doesn't implement
+pub const CAP_CHAT: &str = "chat";
+
+// Capabilities implemented by this build
+pub const CAPABILITIES: &[&str] = &[CAP_CHAT];
+
+// The connect request this client sends, advertising its version and features
+pub fn connect_request(game_id: u64, spectate: bool) -> C2sConnectRequest
+{
+    C2sConnectRequest{
+        game_id,
+        spectate,
+        protocol_version: PROTOCOL_VERSION,
+        capabilities: CAPABILITIES.iter().map(|cap| cap.to_string()).collect()
+    }
+}
+
+// Client side check of the server's answer. Returns the capabilities enabled
+// for this connection, or the message to show when the server can't be used
+pub fn check_ack(ack: &S2cConnectAck) -> Result<Vec<String>, String>
+{
+    if !ack.success
+    {
+        return Err(format!("Server refused the connection\n{}",
+            ack.error.as_deref().unwrap_or("No reason given")));
+    }
+
+    // The server answers with the version it picked for this connection,
+    // which has to be one this build understands
+    if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&ack.protocol_version)
+    {
+        return Err(format!("Incompatible server\nServer speaks protocol v{}, this client supports v{} to v{}",
+            ack.protocol_version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION));
+    }
+
+    // Never use a feature the server enabled but this build doesn't know
+    Ok(ack.capabilities.iter()
+        .filter(|cap| CAPABILITIES.contains(&cap.as_str()))
+        .cloned()
+        .collect())
+}
+
+// Server side result of negotiating with a connecting client
+pub struct Negotiated
+{
+    pub protocol_version: u32,
+    pub capabilities: Vec<String>,
+}
+
+impl Negotiated
+{
+    pub fn has(&self, capability: &str) -> bool
+    {
+        self.capabilities.iter().any(|cap| cap == capability)
+    }
+}
+
+// Server side negotiation: rejects clients older than `MIN_PROTOCOL_VERSION`
+// and otherwise enables only the optional features both sides support, so
+// e.g. chat is never sent to a client that can't show it
+pub fn negotiate(request: &C2sConnectRequest, server_caps: &[&str]) -> Result<Negotiated, String>
+{
+    if !(MIN_PROTOCOL_VERSION..).contains(&request.protocol_version)
+    {
+        return Err(format!("Client speaks protocol v{}, server needs v{} or newer",
+            request.protocol_version, MIN_PROTOCOL_VERSION));
+    }
+
+    Ok(Negotiated{
+        protocol_version: request.protocol_version.min(PROTOCOL_VERSION),
+        capabilities: request.capabilities.iter()
+            .filter(|cap| server_caps.contains(&cap.as_str()))
+            .cloned()
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn _ack(protocol_version: u32, capabilities: &[&str]) -> S2cConnectAck
+    {
+        S2cConnectAck{
+            success: true,
+            protocol_version,
+            capabilities: capabilities.iter().map(|cap| cap.to_string()).collect(),
+            ..S2cConnectAck::default()
+        }
+    }
+
+    #[test]
+    fn ack_versions()
+    {
+        assert!(check_ack(&_ack(MIN_PROTOCOL_VERSION - 1, &[CAP_CHAT])).is_err());
+        assert_eq!(check_ack(&_ack(PROTOCOL_VERSION, &[CAP_CHAT])), Ok(vec![CAP_CHAT.to_string()]));
+        assert!(check_ack(&_ack(PROTOCOL_VERSION + 1, &[CAP_CHAT])).is_err());
+    }
+
+    #[test]
+    fn ack_capabilities()
+    {
+        // No chat when the server doesn't enable it
+        assert_eq!(check_ack(&_ack(PROTOCOL_VERSION, &[])), Ok(Vec::new()));
+        // Unknown features are never used
+        assert_eq!(check_ack(&_ack(PROTOCOL_VERSION, &["clocks", CAP_CHAT])), Ok(vec![CAP_CHAT.to_string()]));
+    }
+
+    #[test]
+    fn refused_ack()
+    {
+        let ack: S2cConnectAck = S2cConnectAck{success: false, error: Some(String::from("Game is full")), ..S2cConnectAck::default()};
+        assert!(check_ack(&ack).unwrap_err().contains("Game is full"));
+    }
+
+    #[test]
+    fn negotiate_versions_and_capabilities()
+    {
+        let mut request: C2sConnectRequest = connect_request(1, false);
+        let negotiated: Negotiated = negotiate(&request, &[CAP_CHAT, "clocks"]).unwrap();
+        assert_eq!(negotiated.protocol_version, PROTOCOL_VERSION);
+        assert!(negotiated.has(CAP_CHAT));
+        assert!(!negotiated.has("clocks"));
+
+        assert!(!negotiate(&request, &[]).unwrap().has(CAP_CHAT));
+
+        // A newer client is answered in this build's version
+        request.protocol_version = PROTOCOL_VERSION + 1;
+        assert_eq!(negotiate(&request, &[]).unwrap().protocol_version, PROTOCOL_VERSION);
+
+        request.protocol_version = MIN_PROTOCOL_VERSION - 1;
+        assert!(negotiate(&request, &[]).is_err());
+    }
+}
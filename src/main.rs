@@ -1,10 +1,9 @@
 extern crate glutin_window;
 
-use std::path::Path;
 use std::collections::HashMap;
 
 
-use piston::{WindowSettings, RenderEvent, EventLoop};
+use piston::{WindowSettings, RenderEvent, ResizeEvent, EventLoop, PressEvent, Button, Key, Window};
 use glutin_window::GlutinWindow;
 use opengl_graphics::{OpenGL, GlGraphics, Texture, TextureSettings, GlyphCache};
 use piston::event_loop::{EventSettings, Events};
@@ -12,19 +11,63 @@ use piston::event_loop::{EventSettings, Events};
 use chess::colors::Colors;
 use chess::piece_types::PieceTypes;
 
-pub use crate::chessview::{ChessView, ChessViewSettings};
+pub use crate::chessview::{ChessView, ChessViewSettings, ViewLayout};
 pub use crate::chessview_controller::ChessViewController;
 
 mod chessview;
 mod chessview_controller;
+mod chat;
+mod animation;
+mod autosave;
+mod cli;
+mod computer;
+mod database;
+mod game;
+mod inet;
+mod position;
+mod puzzle;
+mod render;
+mod screen;
+mod widgets;
+mod settings_file;
+
+use crate::cli::{Cli, StartOptions};
 
 const W_WIDTH: u32  = 640;
-const W_HEIGHT: u32 = 640;
+const W_HEIGHT: u32 = 700;
+
+// Colors, sizes, button positions and the font, see settings_file.rs.
+// Reloaded with F5. Another file can be given with --config
+const VIEW_CONFIG: &str = "config/view.json";
 
 fn main() {
+    // Commands that run without opening a window, see cli.rs
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options: StartOptions = match cli::parse(&args)
+    {
+        Ok(Cli::Gui(options)) => options,
+        Ok(Cli::Help(usage)) => {
+            println!("{}", usage);
+            return;
+        },
+        Ok(Cli::Command(command, args)) => {
+            if let Err(err) = command(&args)
+            {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+            return;
+        },
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
     let opengl = OpenGL::V3_2;
     let settings: WindowSettings = WindowSettings::new("Chess", (W_WIDTH, W_HEIGHT))
-                                    .exit_on_esc(true)
+                                    .exit_on_esc(false)
+                                    .resizable(true)
                                     .graphics_api(opengl)
                                     .vsync(true);
     let mut window: GlutinWindow = settings.build().expect("COULD NOT CREATE WINDOW");
@@ -36,55 +79,30 @@ fn main() {
 
 
     // Main chess gui components for event handling
-    let _chessview_settings: ChessViewSettings = ChessViewSettings::new();
+    let _chessview_settings: ChessViewSettings = cli::load_settings(&options.config, options.theme.as_deref())
+        .unwrap_or_else(|err| {
+            eprintln!("{}\nUsing the default settings", err);
+            ChessViewSettings::new()
+        });
     let mut _chessview: ChessView = ChessView::new(_chessview_settings);
+    let window_size = window.size();
+    let draw_size = window.draw_size();
+    _chessview.fit([window_size.width, window_size.height], [draw_size.width, draw_size.height]);
     let mut _chessview_controller: ChessViewController = ChessViewController::new();
+    if let Err(err) = cli::start(&options, &mut _chessview_controller, &mut _chessview)
+    {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
     
-    // Initializing the textures from the png files and storing them as a reference
-    // inside a hash map based on the chess struct attributes (color and type)
-    let mut _texture_storage: HashMap<(PieceTypes, Colors), Texture> = HashMap::new();
-    let w_king: Texture = Texture::from_path(Path::new("assets/img/w_king.png"),
-    &TextureSettings::new()).unwrap();
-    let w_queen: Texture = Texture::from_path(Path::new("assets/img/w_queen.png"),
-    &TextureSettings::new()).unwrap(); 
-    let w_rook: Texture = Texture::from_path(Path::new("assets/img/w_rook.png"),
-    &TextureSettings::new()).unwrap(); 
-    let w_knight: Texture = Texture::from_path(Path::new("assets/img/w_knight.png"),
-    &TextureSettings::new()).unwrap(); 
-    let w_bishop: Texture = Texture::from_path(Path::new("assets/img/w_bishop.png"),
-    &TextureSettings::new()).unwrap(); 
-    let w_pawn: Texture = Texture::from_path(Path::new("assets/img/w_pawn.png"),
-    &TextureSettings::new()).unwrap();
-
-    let b_king: Texture = Texture::from_path(Path::new("assets/img/b_king.png"),
-    &TextureSettings::new()).unwrap();
-    let b_queen: Texture = Texture::from_path(Path::new("assets/img/b_queen.png"),
-    &TextureSettings::new()).unwrap(); 
-    let b_rook: Texture = Texture::from_path(Path::new("assets/img/b_rook.png"),
-    &TextureSettings::new()).unwrap(); 
-    let b_knight: Texture = Texture::from_path(Path::new("assets/img/b_knight.png"),
-    &TextureSettings::new()).unwrap(); 
-    let b_bishop: Texture = Texture::from_path(Path::new("assets/img/b_bishop.png"),
-    &TextureSettings::new()).unwrap(); 
-    let b_pawn: Texture = Texture::from_path(Path::new("assets/img/b_pawn.png"),
-    &TextureSettings::new()).unwrap();       
-
-    _texture_storage.insert((PieceTypes::King, Colors::White), w_king);
-    _texture_storage.insert((PieceTypes::Queen, Colors::White), w_queen);
-    _texture_storage.insert((PieceTypes::Knight, Colors::White), w_knight);
-    _texture_storage.insert((PieceTypes::Bishop, Colors::White), w_bishop);
-    _texture_storage.insert((PieceTypes::Rook, Colors::White), w_rook);
-    _texture_storage.insert((PieceTypes::Pawn, Colors::White), w_pawn);
-
-    _texture_storage.insert((PieceTypes::King, Colors::Black), b_king);
-    _texture_storage.insert((PieceTypes::Queen, Colors::Black), b_queen);
-    _texture_storage.insert((PieceTypes::Knight, Colors::Black), b_knight);
-    _texture_storage.insert((PieceTypes::Bishop, Colors::Black), b_bishop);
-    _texture_storage.insert((PieceTypes::Rook, Colors::Black), b_rook);
-    _texture_storage.insert((PieceTypes::Pawn, Colors::Black), b_pawn);
+    // Textures of the chosen piece set, reloaded when it's changed on the
+    // settings screen
+    let mut _texture_storage: HashMap<(PieceTypes, Colors), Texture> =
+        _load_textures(&_chessview.settings._piece_set);
+    let mut _loaded_piece_set: String = _chessview.settings._piece_set.clone();
 
     // Load main font. Some random font copied from /usr/share/fonts
-    let mut main_glyph: GlyphCache = GlyphCache::new("assets/font/Lato-Bold.ttf", 
+    let mut main_glyph: GlyphCache = GlyphCache::new(_chessview.settings._font.as_str(),
                                                 (), 
                                                 TextureSettings::new()
                                             ).unwrap();
@@ -95,9 +113,63 @@ fn main() {
     {
         // The `ChessViewController` handles all the mouse clicks
         // and all other GUI events that are needed for the game to work
+        if let Some(args) = e.resize_args()
+        {
+            _chessview.fit(args.window_size, [args.draw_size[0] as f64, args.draw_size[1] as f64]);
+        }
+
         _chessview_controller.event(&mut _chessview, &e);
+
+        // Escape on the intro pops the last screen
+        if _chessview_controller.screens.is_empty()
+        {
+            window.set_should_close(true);
+        }
+
+        // Reload the settings file, a broken file keeps the current settings
+        if let Some(Button::Keyboard(Key::F5)) = e.press_args()
+        {
+            match cli::load_settings(&options.config, options.theme.as_deref())
+            {
+                Ok(settings) => {
+                    if settings._font != _chessview.settings._font
+                    {
+                        match GlyphCache::new(settings._font.as_str(), (), TextureSettings::new())
+                        {
+                            Ok(glyph) => main_glyph = glyph,
+                            Err(err) => eprintln!("Could not load {}: {}", settings._font, err),
+                        }
+                    }
+                    _chessview.settings = settings;
+                    // The view size may have changed
+                    let layout: ViewLayout = _chessview.layout;
+                    _chessview.fit(layout.window_size,
+                        [layout.window_size[0]*layout.dpi_scale, layout.window_size[1]*layout.dpi_scale]);
+                },
+                Err(err) => eprintln!("{}\nKeeping the current settings", err),
+            }
+        }
+
+        if _chessview.settings._piece_set != _loaded_piece_set
+        {
+            _texture_storage = _load_textures(&_chessview.settings._piece_set);
+            _loaded_piece_set = _chessview.settings._piece_set.clone();
+        }
+
+        // Keep the loop ticking while connected (or looking for LAN games)
+        // so network messages are handled without waiting for user input,
+        // and while a move is animated. Back to lazy afterwards to save CPU
+        events.set_lazy(!_chessview_controller.needs_polling(&_chessview) && !_chessview.is_animating());
         if let Some(args) = e.render_args()
         {
+            // Checked before drawing, so the frame drawn shows the end of
+            // the animation before it's dropped
+            let animation_done: bool = _chessview.animation.as_ref().is_some_and(|anim| anim.finished());
+            if _chessview_controller.chat.open
+            {
+                _chessview.wrap_chat(&mut main_glyph, &mut _chessview_controller.chat);
+            }
+
             gl.draw(args.viewport(), |c, g| {
                 use graphics::{clear};
 
@@ -109,6 +181,17 @@ fn main() {
                 _chessview.draw(&mut main_glyph, &_texture_storage,
                     &_chessview_controller, &c, g);
             });
+
+            _chessview.end_finished_animation(animation_done);
         }
     }
-}
\ No newline at end of file
+
+    // Closing the window in the middle of a game keeps it for "Continue"
+    _chessview_controller.autosave(&_chessview);
+}
+
+// Piece textures of a set for drawing with OpenGL
+fn _load_textures(piece_set: &str) -> HashMap<(PieceTypes, Colors), Texture>
+{
+    settings_file::load_piece_set(piece_set, |path| Texture::from_path(path, &TextureSettings::new()))
+}
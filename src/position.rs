@@ -0,0 +1,741 @@
+use chess::chess_engine::ChessEngine;
+use chess::colors::Colors;
+use chess::piece_types::PieceTypes;
+
+// Plain copy of the pieces on the board, indexed by square with a1 = 0,
+// b1 = 1, .., h8 = 63 (the same numbering as `Move` in chess.proto)
+pub type Squares = [Option<(PieceTypes, Colors)>; 64];
+
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+const FILES: [char; 8] = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'];
+
+// "e4" for square 28
+pub fn square_to_notation(square: u32) -> String
+{
+    format!("{}{}", FILES[(square % 8) as usize], square / 8 + 1)
+}
+
+// 28 for "e4", `None` for anything that isn't a square
+pub fn notation_to_square(notation: &str) -> Option<u32>
+{
+    let mut chars = notation.chars();
+    let file_char: char = chars.next()?;
+    let file: u32 = FILES.iter().position(|f| *f == file_char)? as u32;
+    let rank: u32 = chars.next()?.to_digit(10)?;
+
+    if chars.next().is_some() || !(1..=8).contains(&rank) { return None; }
+    Some(file + 8*(rank-1))
+}
+
+// Copy the engine's board. `get_board` has rank 8 in its first row
+pub fn snapshot(board: &ChessEngine) -> Squares
+{
+    let mut squares: Squares = [None; 64];
+    for (row_i, row) in board.get_board().iter().enumerate()
+    {
+        for (file, piece) in row.iter().enumerate()
+        {
+            if let Some(piece) = piece
+            {
+                squares[file + 8*(7-row_i)] = Some((piece.piece_type, piece.color));
+            }
+        }
+    }
+    squares
+}
+
+// Play `from` -> `to` (e.g. "e2", "e4") on the engine, the same way a click
+// on the board does. Returns whether the engine accepted the move. The
+// engine picks what a pawn promotes to, so with a `promotion` the move is
+// only played if it promotes to that piece
+pub fn play_move(board: &mut ChessEngine, from: &str, to: &str, promotion: Option<PieceTypes>) -> bool
+{
+    if promotion.is_some()
+    {
+        let mut played: ChessEngine = board.clone();
+        if !play_move(&mut played, from, to, None) { return false; }
+
+        let arrived: Option<PieceTypes> = notation_to_square(to)
+            .and_then(|square| snapshot(&played)[square as usize])
+            .map(|(piece_type, _)| piece_type);
+        if arrived != promotion { return false; }
+
+        *board = played;
+        return true;
+    }
+
+    let before: Squares = snapshot(board);
+
+    board.select_piece_notation(from);
+    board.play_selected_piece_with_notation(to);
+
+    snapshot(board) != before
+}
+
+pub fn piece_to_char(piece_type: PieceTypes, color: Colors) -> char
+{
+    let ch: char = match piece_type
+    {
+        PieceTypes::Pawn => 'p',
+        PieceTypes::Knight => 'n',
+        PieceTypes::Bishop => 'b',
+        PieceTypes::Rook => 'r',
+        PieceTypes::Queen => 'q',
+        PieceTypes::King => 'k',
+    };
+
+    if color == Colors::White { ch.to_ascii_uppercase() } else { ch }
+}
+
+pub fn char_to_piece(ch: char) -> Option<(PieceTypes, Colors)>
+{
+    let piece_type: PieceTypes = match ch.to_ascii_lowercase()
+    {
+        'p' => PieceTypes::Pawn,
+        'n' => PieceTypes::Knight,
+        'b' => PieceTypes::Bishop,
+        'r' => PieceTypes::Rook,
+        'q' => PieceTypes::Queen,
+        'k' => PieceTypes::King,
+        _ => return None
+    };
+
+    Some((piece_type, if ch.is_ascii_uppercase() { Colors::White } else { Colors::Black }))
+}
+
+// The pieces of a FEN and whether white is to move. Only for showing a
+// position, the engine itself can't be set up from one
+pub fn from_fen(fen: &str) -> Result<(Squares, bool), String>
+{
+    let mut fields = fen.split_whitespace();
+    let placement: &str = fields.next().ok_or(String::from("Empty FEN"))?;
+    let white_to_move: bool = match fields.next()
+    {
+        None | Some("w") => true,
+        Some("b") => false,
+        Some(side) => return Err(format!("Bad side to move \"{}\" in the FEN, use w or b", side)),
+    };
+
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != 8 { return Err(format!("The FEN has {} ranks instead of 8", ranks.len())); }
+
+    let mut squares: Squares = [None; 64];
+    for (i, rank_text) in ranks.iter().enumerate()
+    {
+        // Rank 8 comes first
+        let rank: usize = 7 - i;
+        let mut file: usize = 0;
+        for ch in rank_text.chars()
+        {
+            match ch.to_digit(10)
+            {
+                Some(empty @ 1..=8) => file += empty as usize,
+                _ => {
+                    let piece = char_to_piece(ch)
+                        .ok_or(format!("Unknown piece '{}' in the FEN", ch))?;
+                    if file < 8 { squares[file + 8*rank] = Some(piece); }
+                    file += 1;
+                }
+            }
+        }
+        if file != 8 { return Err(format!("Rank {} of the FEN has {} squares instead of 8", rank + 1, file)); }
+    }
+
+    Ok((squares, white_to_move))
+}
+
+// FEN of the position. The engine doesn't expose castling rights or the
+// en passant square, so castling is assumed possible while the king and
+// rook are still on their starting squares
+pub fn to_fen(squares: &Squares, white_to_move: bool, fullmove: u32) -> String
+{
+    let mut placement: String = String::new();
+    for rank in (0..8).rev()
+    {
+        let mut empty: u32 = 0;
+        for file in 0..8
+        {
+            match squares[file + 8*rank]
+            {
+                Some((piece_type, color)) => {
+                    if empty > 0 { placement.push_str(&empty.to_string()); }
+                    empty = 0;
+                    placement.push(piece_to_char(piece_type, color));
+                },
+                None => empty += 1
+            }
+        }
+        if empty > 0 { placement.push_str(&empty.to_string()); }
+        if rank > 0 { placement.push('/'); }
+    }
+
+    let on = |square: usize, piece_type: PieceTypes, color: Colors| squares[square] == Some((piece_type, color));
+    let mut castling: String = String::new();
+    if on(4, PieceTypes::King, Colors::White)
+    {
+        if on(7, PieceTypes::Rook, Colors::White) { castling.push('K'); }
+        if on(0, PieceTypes::Rook, Colors::White) { castling.push('Q'); }
+    }
+    if on(60, PieceTypes::King, Colors::Black)
+    {
+        if on(63, PieceTypes::Rook, Colors::Black) { castling.push('k'); }
+        if on(56, PieceTypes::Rook, Colors::Black) { castling.push('q'); }
+    }
+    if castling.is_empty() { castling.push('-'); }
+
+    format!("{} {} {} - 0 {}", placement, if white_to_move { "w" } else { "b" }, castling, fullmove)
+}
+
+// Usual material values, the king doesn't count
+pub fn piece_value(piece_type: PieceTypes) -> i32
+{
+    match piece_type
+    {
+        PieceTypes::Pawn => 1,
+        PieceTypes::Knight | PieceTypes::Bishop => 3,
+        PieceTypes::Rook => 5,
+        PieceTypes::Queen => 9,
+        PieceTypes::King => 0,
+    }
+}
+
+// Pieces of `color` that are no longer on the board compared to the
+// starting set, most valuable first. A promoted pawn counts as the pawn
+// being gone and nothing of the new piece's kind missing
+pub fn missing_pieces(squares: &Squares, color: Colors) -> Vec<PieceTypes>
+{
+    let start: [(PieceTypes, usize); 5] = [
+        (PieceTypes::Queen, 1),
+        (PieceTypes::Rook, 2),
+        (PieceTypes::Bishop, 2),
+        (PieceTypes::Knight, 2),
+        (PieceTypes::Pawn, 8),
+    ];
+
+    let mut missing: Vec<PieceTypes> = Vec::new();
+    for (piece_type, count) in start
+    {
+        let on_board: usize = squares.iter().filter(|s| **s == Some((piece_type, color))).count();
+        for _ in on_board..count { missing.push(piece_type); }
+    }
+    missing
+}
+
+// White's material minus black's, positive when white is ahead
+pub fn material_balance(squares: &Squares) -> i32
+{
+    squares.iter().flatten()
+        .map(|(piece_type, color)| if *color == Colors::White { piece_value(*piece_type) }
+                                   else { -piece_value(*piece_type) })
+        .sum()
+}
+
+// Square (a1 = 0) to block (a8 = 0, the order of `get_board` and
+// `ChessView::block_pos`) and back, the same mapping both ways
+pub fn square_to_block(square: u32) -> u8
+{
+    (square % 8 + 8 * (7 - square / 8)) as u8
+}
+
+pub fn block_to_square(block: u8) -> u32
+{
+    square_to_block(block as u32) as u32
+}
+
+// Whether the piece on `from` attacks `to`, only by how it moves and what
+// stands in between. Pins and whose turn it is don't matter here
+pub fn attacks(squares: &Squares, from: u32, to: u32) -> bool
+{
+    let (piece_type, color) = match squares[from as usize] { Some(piece) => piece, None => return false };
+    if from == to { return false; }
+
+    let (fx, fy) = ((from % 8) as i32, (from / 8) as i32);
+    let (tx, ty) = ((to % 8) as i32, (to / 8) as i32);
+    let (dx, dy) = (tx - fx, ty - fy);
+
+    // Every square strictly between from and to is empty
+    let clear_path = || {
+        let (sx, sy) = (dx.signum(), dy.signum());
+        let (mut x, mut y) = (fx + sx, fy + sy);
+        while (x, y) != (tx, ty)
+        {
+            if squares[(x + 8*y) as usize].is_some() { return false; }
+            x += sx;
+            y += sy;
+        }
+        true
+    };
+
+    match piece_type
+    {
+        PieceTypes::Pawn => {
+            let forward: i32 = if color == Colors::White { 1 } else { -1 };
+            dy == forward && dx.abs() == 1
+        },
+        PieceTypes::Knight => (dx.abs() == 1 && dy.abs() == 2) || (dx.abs() == 2 && dy.abs() == 1),
+        PieceTypes::King => dx.abs() <= 1 && dy.abs() <= 1,
+        PieceTypes::Bishop => dx.abs() == dy.abs() && clear_path(),
+        PieceTypes::Rook => (dx == 0 || dy == 0) && clear_path(),
+        PieceTypes::Queen => (dx == 0 || dy == 0 || dx.abs() == dy.abs()) && clear_path(),
+    }
+}
+
+// Whether the king of `color` is attacked
+pub fn in_check(squares: &Squares, color: Colors) -> bool
+{
+    let king: u32 = match squares.iter().position(|s| *s == Some((PieceTypes::King, color)))
+    {
+        Some(king) => king as u32,
+        None => return false,
+    };
+
+    (0..64).any(|s| matches!(squares[s as usize], Some((_, c)) if c != color) && attacks(squares, s, king))
+}
+
+// The move that turned `before` into `after` as (from, to, promotion),
+// castling is the king's move. `None` if the change isn't a single move
+pub fn diff_move(before: &Squares, after: &Squares) -> Option<(u32, u32, Option<PieceTypes>)>
+{
+    let changed: Vec<usize> = (0..64).filter(|s| before[*s] != after[*s]).collect();
+    if changed.is_empty() || changed.len() > 4 { return None; }
+
+    let arrivals: Vec<usize> = changed.iter().cloned().filter(|s| after[*s].is_some()).collect();
+    let to: usize = *arrivals.iter()
+        .find(|s| matches!(after[**s], Some((PieceTypes::King, _))))
+        .or(arrivals.first())?;
+    let (piece_type, color) = after[to]?;
+
+    let departures: Vec<usize> = changed.iter().cloned()
+        .filter(|s| after[*s].is_none() && matches!(before[*s], Some((_, c)) if c == color))
+        .collect();
+    let from: usize = *departures.iter().find(|s| before[**s] == Some((piece_type, color)))
+        .or_else(|| departures.iter().find(|s| before[**s] == Some((PieceTypes::Pawn, color))))?;
+
+    let promotion: Option<PieceTypes> = if before[from] != after[to] { Some(piece_type) } else { None };
+    Some((from as u32, to as u32, promotion))
+}
+
+// Standard algebraic notation of a move, e.g. "Nbd7", "exd5", "e8=Q+", "O-O".
+// Disambiguation only looks at how pieces move, so a pinned piece can
+// make it more specific than needed, which is still valid SAN
+pub fn san(before: &Squares, after: &Squares, from: u32, to: u32, promotion: Option<PieceTypes>) -> String
+{
+    let (piece_type, color) = match before[from as usize] { Some(piece) => piece, None => return String::from("--") };
+    let capture: bool = before[to as usize].is_some();
+    let opponent: Colors = if color == Colors::White { Colors::Black } else { Colors::White };
+    let check: &str = if in_check(after, opponent) { "+" } else { "" };
+
+    if piece_type == PieceTypes::King && (from % 8).abs_diff(to % 8) == 2
+    {
+        return format!("{}{}", if to % 8 == 6 { "O-O" } else { "O-O-O" }, check);
+    }
+
+    let target: String = square_to_notation(to);
+    let mut notation: String = String::new();
+
+    if piece_type == PieceTypes::Pawn
+    {
+        // A pawn changing file always captures, en passant included
+        if capture || from % 8 != to % 8
+        {
+            notation.push(FILES[(from % 8) as usize]);
+            notation.push('x');
+        }
+        notation.push_str(&target);
+        if let Some(promoted) = promotion
+        {
+            notation.push('=');
+            notation.push(piece_to_char(promoted, Colors::White));
+        }
+    }
+    else
+    {
+        notation.push(piece_to_char(piece_type, Colors::White));
+
+        let rivals: Vec<u32> = (0..64)
+            .filter(|s| *s != from && before[*s as usize] == Some((piece_type, color)) && attacks(before, *s, to))
+            .collect();
+        if !rivals.is_empty()
+        {
+            let from_notation: String = square_to_notation(from);
+            if rivals.iter().all(|s| s % 8 != from % 8) { notation.push_str(&from_notation[..1]); }
+            else if rivals.iter().all(|s| s / 8 != from / 8) { notation.push_str(&from_notation[1..]); }
+            else { notation.push_str(&from_notation); }
+        }
+
+        if capture { notation.push('x'); }
+        notation.push_str(&target);
+    }
+
+    notation.push_str(check);
+    notation
+}
+
+// The squares after moving the piece on `from` to `to`, without asking the
+// engine, e.g. for a diagram set up from a FEN. A king moving two files
+// takes its rook along, a pawn moving diagonally onto an empty square takes
+// en passant and `promotion` replaces a pawn reaching the last rank
+pub fn apply_move(squares: &Squares, from: u32, to: u32, promotion: Option<PieceTypes>) -> Squares
+{
+    let mut after: Squares = *squares;
+    let (piece_type, color) = match squares[from as usize] { Some(piece) => piece, None => return after };
+    let (fx, tx) = ((from % 8) as i32, (to % 8) as i32);
+
+    match piece_type
+    {
+        PieceTypes::King if (tx - fx).abs() == 2 => {
+            let (rook_from, rook_to) = if tx > fx { (from + 3, from + 1) } else { (from - 4, from - 1) };
+            after[rook_to as usize] = after[rook_from as usize].take();
+        },
+        PieceTypes::Pawn if fx != tx && squares[to as usize].is_none() => {
+            // The pawn passed by is beside `from`, on the file of `to`
+            after[(from / 8 * 8 + to % 8) as usize] = None;
+        },
+        _ => ()
+    }
+
+    after[from as usize] = None;
+    let arriving: PieceTypes = if piece_type == PieceTypes::Pawn { promotion.unwrap_or(piece_type) } else { piece_type };
+    after[to as usize] = Some((arriving, color));
+    after
+}
+
+// Whether the engine accepts `from` -> `to` in its current position, tried
+// on a copy so the real board is untouched
+pub fn is_legal(board: &ChessEngine, from: u32, to: u32) -> bool
+{
+    let mut copy: ChessEngine = board.clone();
+    play_move(&mut copy, &square_to_notation(from), &square_to_notation(to), None)
+}
+
+// The result when `color` is to move and has no move that the engine
+// accepts and that keeps its king out of check: checkmate if it's in check,
+// stalemate otherwise. `None` while the game goes on
+pub fn game_result(board: &ChessEngine, color: Colors) -> Option<&'static str>
+{
+    let squares: Squares = snapshot(board);
+    let can_move: bool = (0..64)
+        .filter(|from| matches!(squares[*from as usize], Some((_, c)) if c == color))
+        .any(|from| (0..64).any(|to| {
+            let mut copy: ChessEngine = board.clone();
+            play_move(&mut copy, &square_to_notation(from), &square_to_notation(to), None)
+                && !in_check(&snapshot(&copy), color)
+        }));
+
+    _result(&squares, color, can_move)
+}
+
+// `game_result` for squares the engine can't be set up in, see `legal_moves`
+pub fn position_result(squares: &Squares, color: Colors, last_move: Option<(u32, u32)>) -> Option<&'static str>
+{
+    _result(squares, color, !legal_moves(squares, color, last_move).is_empty())
+}
+
+fn _result(squares: &Squares, color: Colors, can_move: bool) -> Option<&'static str>
+{
+    if can_move { None }
+    else if !in_check(squares, color) { Some("1/2-1/2") }
+    else if color == Colors::White { Some("0-1") }
+    else { Some("1-0") }
+}
+
+// The moves of `color` that keep its king out of check, as (from, to),
+// worked out on the squares alone. A king and rook still on their starting
+// squares may castle, and `last_move` allows taking en passant
+pub fn legal_moves(squares: &Squares, color: Colors, last_move: Option<(u32, u32)>) -> Vec<(u32, u32)>
+{
+    let forward: i32 = if color == Colors::White { 1 } else { -1 };
+    let start_rank: u32 = if color == Colors::White { 1 } else { 6 };
+    let home: u32 = if color == Colors::White { 0 } else { 56 };
+
+    let mut moves: Vec<(u32, u32)> = Vec::new();
+    for from in 0..64u32
+    {
+        let piece_type: PieceTypes = match squares[from as usize] { Some((p, c)) if c == color => p, _ => continue };
+        for to in 0..64u32
+        {
+            let target: Option<(PieceTypes, Colors)> = squares[to as usize];
+            if target.is_some_and(|(_, c)| c == color) { continue; }
+
+            let dy: i32 = (to / 8) as i32 - (from / 8) as i32;
+            let possible: bool = match piece_type
+            {
+                PieceTypes::Pawn if from % 8 == to % 8 => target.is_none() && (dy == forward
+                    || (dy == 2*forward && from / 8 == start_rank && squares[(from as i32 + 8*forward) as usize].is_none())),
+                PieceTypes::Pawn => attacks(squares, from, to) && (target.is_some() || _passed(squares, to, last_move)),
+                PieceTypes::King if from == home + 4 && (to == home + 2 || to == home + 6) => _can_castle(squares, color, to),
+                _ => attacks(squares, from, to),
+            };
+            if possible && !in_check(&apply_move(squares, from, to, None), color) { moves.push((from, to)); }
+        }
+    }
+    moves
+}
+
+// Whether `last_move` was a pawn moving two squares past `to`, which can
+// be taken en passant there
+fn _passed(squares: &Squares, to: u32, last_move: Option<(u32, u32)>) -> bool
+{
+    match last_move
+    {
+        Some((from, pawn)) => matches!(squares[pawn as usize], Some((PieceTypes::Pawn, _)))
+            && from.abs_diff(pawn) == 16 && from + pawn == 2*to,
+        None => false,
+    }
+}
+
+// Castling of the king on its starting square to `to`, as far as the squares
+// tell: the rook is in its corner, nothing stands between them and the king
+// isn't in check and doesn't pass through it. Landing in check is left to
+// the caller
+fn _can_castle(squares: &Squares, color: Colors, to: u32) -> bool
+{
+    let home: u32 = if color == Colors::White { 0 } else { 56 };
+    let (rook, between, passed): (u32, std::ops::Range<u32>, u32) =
+        if to == home + 6 { (home + 7, home + 5..home + 7, home + 5) }
+        else { (home, home + 1..home + 4, home + 3) };
+
+    squares[rook as usize] == Some((PieceTypes::Rook, color))
+        && between.into_iter().all(|square| squares[square as usize].is_none())
+        && !in_check(squares, color)
+        && !in_check(&apply_move(squares, home + 4, passed, None), color)
+}
+
+// Whether `from` -> `to` takes a pawn to the last rank
+pub fn is_promotion(squares: &Squares, from: u32, to: u32) -> bool
+{
+    match squares[from as usize]
+    {
+        Some((PieceTypes::Pawn, Colors::White)) => to / 8 == 7,
+        Some((PieceTypes::Pawn, Colors::Black)) => to / 8 == 0,
+        _ => false,
+    }
+}
+
+// The moves typed text can mean, as (from, to, promotion). Understands SAN
+// ("Nf3", "exd5", "Rad1", "e8=Q", "O-O") and coordinates ("e2e4", "e2-e4",
+// "e7e8q"), the promotion is `None` unless the text names the piece. Only
+// checks how pieces move, `is_legal` has the last word. Errs if the text
+// isn't a move at all
+pub fn parse_move(squares: &Squares, white_to_move: bool, text: &str) -> Result<Vec<(u32, u32, Option<PieceTypes>)>, String>
+{
+    let color: Colors = if white_to_move { Colors::White } else { Colors::Black };
+    let cleaned: String = text.trim().trim_end_matches(['+', '#', '!', '?']).to_string();
+    let unreadable = || format!("Can't read \"{}\", try e.g. \"Nf3\" or \"g1f3\"", text.trim());
+
+    // Castling, the king's move
+    let king_rank: u32 = if white_to_move { 0 } else { 56 };
+    match cleaned.replace('0', "O").as_str()
+    {
+        "O-O" => return Ok(vec![(king_rank + 4, king_rank + 6, None)]),
+        "O-O-O" => return Ok(vec![(king_rank + 4, king_rank + 2, None)]),
+        _ => ()
+    }
+
+    // Only a pawn reaching the last rank can promote
+    let last_rank: u32 = if white_to_move { 7 } else { 0 };
+    let check_promotion = |piece_type: PieceTypes, to: u32, promotion: Option<PieceTypes>| {
+        if promotion.is_some() && (piece_type != PieceTypes::Pawn || to / 8 != last_rank)
+        {
+            return Err(format!("{} is not a promotion", text.trim()));
+        }
+        Ok(promotion)
+    };
+
+    // Coordinates, the piece on `from` has to be ours
+    let coords: String = cleaned.replace('-', "");
+    if coords.len() >= 4 && coords.is_ascii()
+    {
+        if let (Some(from), Some(to)) = (notation_to_square(&coords[0..2]), notation_to_square(&coords[2..4]))
+        {
+            let promotion: Option<PieceTypes> = match &coords[4..]
+            {
+                "" => None,
+                piece => Some(_promotion_piece(piece).ok_or_else(unreadable)?),
+            };
+            return match squares[from as usize]
+            {
+                Some((piece_type, c)) if c == color => Ok(vec![(from, to, check_promotion(piece_type, to, promotion)?)]),
+                _ => Err(format!("No {} piece on {}", if white_to_move { "white" } else { "black" }, &coords[0..2])),
+            };
+        }
+    }
+
+    // SAN: [piece][file][rank][x]square[=promotion]
+    let (san, promotion): (&str, Option<PieceTypes>) = match cleaned.split_once('=')
+    {
+        Some((san, piece)) => (san, Some(_promotion_piece(piece).ok_or_else(unreadable)?)),
+        None => (cleaned.as_str(), None),
+    };
+    if san.len() < 2 || !san.is_ascii() { return Err(unreadable()); }
+
+    let to: u32 = notation_to_square(&san[san.len()-2..]).ok_or_else(unreadable)?;
+    let mut rest: &str = &san[..san.len()-2];
+    let capture: bool = rest.ends_with('x');
+    rest = rest.trim_end_matches('x');
+
+    let piece_type: PieceTypes = match rest.chars().next()
+    {
+        Some('N') => PieceTypes::Knight,
+        Some('B') => PieceTypes::Bishop,
+        Some('R') => PieceTypes::Rook,
+        Some('Q') => PieceTypes::Queen,
+        Some('K') => PieceTypes::King,
+        _ => PieceTypes::Pawn,
+    };
+    if piece_type != PieceTypes::Pawn { rest = &rest[1..]; }
+    let promotion: Option<PieceTypes> = check_promotion(piece_type, to, promotion)?;
+
+    // What's left narrows down the piece: a file, a rank or both
+    let mut file: Option<u32> = None;
+    let mut rank: Option<u32> = None;
+    for ch in rest.chars()
+    {
+        match ch
+        {
+            'a'..='h' if file.is_none() => file = Some(ch as u32 - 'a' as u32),
+            '1'..='8' if rank.is_none() => rank = Some(ch as u32 - '1' as u32),
+            _ => return Err(unreadable()),
+        }
+    }
+
+    let forward: i32 = if white_to_move { 1 } else { -1 };
+    let candidates: Vec<(u32, u32, Option<PieceTypes>)> = (0..64u32)
+        .filter(|from| squares[*from as usize] == Some((piece_type, color)))
+        .filter(|from| file.is_none_or(|f| from % 8 == f) && rank.is_none_or(|r| from / 8 == r))
+        .filter(|from| {
+            if piece_type != PieceTypes::Pawn { return attacks(squares, *from, to); }
+
+            let dy: i32 = (to / 8) as i32 - (*from / 8) as i32;
+            if capture
+            {
+                // Captures, including en passant onto an empty square
+                return from % 8 != to % 8 && attacks(squares, *from, to);
+            }
+            if from % 8 != to % 8 { return false; }
+            // Pushes, two squares only from the starting rank
+            let start_rank: u32 = if white_to_move { 1 } else { 6 };
+            let between: u32 = (*from as i32 + 8*forward) as u32;
+            squares[to as usize].is_none()
+                && (dy == forward || (dy == 2*forward && from / 8 == start_rank && squares[between as usize].is_none()))
+        })
+        .map(|from| (from, to, promotion))
+        .collect();
+
+    if candidates.is_empty()
+    {
+        return Err(format!("No piece can play {}", text.trim()));
+    }
+    Ok(candidates)
+}
+
+// The piece named after "=" in SAN or at the end of coordinates, "Q" or "q"
+fn _promotion_piece(text: &str) -> Option<PieceTypes>
+{
+    let mut chars = text.chars();
+    let piece_type: PieceTypes = char_to_piece(chars.next()?)?.0;
+    if chars.next().is_some() || matches!(piece_type, PieceTypes::Pawn | PieceTypes::King) { return None; }
+    Some(piece_type)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn _squares(fen: &str) -> Squares
+    {
+        from_fen(fen).unwrap().0
+    }
+
+    fn _square(notation: &str) -> u32
+    {
+        notation_to_square(notation).unwrap()
+    }
+
+    #[test]
+    fn parse_san_and_coordinates()
+    {
+        let start: Squares = _squares(START_FEN);
+        assert_eq!(parse_move(&start, true, "Nf3"), Ok(vec![(_square("g1"), _square("f3"), None)]));
+        assert_eq!(parse_move(&start, true, "e4"), Ok(vec![(_square("e2"), _square("e4"), None)]));
+        assert_eq!(parse_move(&start, true, "e2-e4"), parse_move(&start, true, "e2e4"));
+        assert_eq!(parse_move(&start, false, "e5+"), Ok(vec![(_square("e7"), _square("e5"), None)]));
+        assert_eq!(parse_move(&start, true, "O-O"), Ok(vec![(_square("e1"), _square("g1"), None)]));
+        assert_eq!(parse_move(&start, false, "0-0-0"), Ok(vec![(_square("e8"), _square("c8"), None)]));
+
+        assert!(parse_move(&start, true, "e5").is_err());
+        assert!(parse_move(&start, true, "e7e5").is_err());
+        assert!(parse_move(&start, true, "hello").is_err());
+    }
+
+    #[test]
+    fn parse_ambiguous_moves()
+    {
+        let squares: Squares = _squares("4k3/8/8/8/8/8/4K3/R6R w - - 0 1");
+        assert_eq!(parse_move(&squares, true, "Rd1").map(|moves| moves.len()), Ok(2));
+        assert_eq!(parse_move(&squares, true, "Rad1"), Ok(vec![(_square("a1"), _square("d1"), None)]));
+        assert_eq!(parse_move(&squares, true, "R1d1").map(|moves| moves.len()), Ok(2));
+    }
+
+    #[test]
+    fn parse_promotions()
+    {
+        let squares: Squares = _squares("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1");
+        let (a7, a8, b8) = (_square("a7"), _square("a8"), _square("b8"));
+        assert_eq!(parse_move(&squares, true, "a8=N"), Ok(vec![(a7, a8, Some(PieceTypes::Knight))]));
+        assert_eq!(parse_move(&squares, true, "axb8=R+"), Ok(vec![(a7, b8, Some(PieceTypes::Rook))]));
+        assert_eq!(parse_move(&squares, true, "a7a8b"), Ok(vec![(a7, a8, Some(PieceTypes::Bishop))]));
+        assert_eq!(parse_move(&squares, true, "a7a8Q"), Ok(vec![(a7, a8, Some(PieceTypes::Queen))]));
+        // Left to whatever a click promotes to
+        assert_eq!(parse_move(&squares, true, "a8"), Ok(vec![(a7, a8, None)]));
+
+        assert!(parse_move(&squares, true, "a8=K").is_err());
+        assert!(parse_move(&squares, true, "a7a8p").is_err());
+        assert!(parse_move(&squares, true, "Ke2=Q").is_err());
+        assert!(parse_move(&squares, true, "e1e2q").is_err());
+    }
+
+    #[test]
+    fn legal_moves_without_the_engine()
+    {
+        let start: Squares = _squares(START_FEN);
+        assert_eq!(legal_moves(&start, Colors::White, None).len(), 20);
+        assert_eq!(position_result(&start, Colors::White, None), None);
+
+        // Castling both ways, but not through the rook's attack on d1
+        let squares: Squares = _squares("3rk3/8/8/8/8/8/8/R3K2R w KQ - 0 1");
+        let king: Vec<u32> = legal_moves(&squares, Colors::White, None).into_iter()
+            .filter(|(from, _)| *from == _square("e1")).map(|(_, to)| to).collect();
+        assert!(king.contains(&_square("g1")));
+        assert!(!king.contains(&_square("c1")));
+        assert!(!king.contains(&_square("d2")));
+
+        // En passant only right after the pawn passed
+        let squares: Squares = _squares("4k3/8/8/3Pp3/8/8/8/4K3 w - e6 0 1");
+        let (d5, e6) = (_square("d5"), _square("e6"));
+        assert!(legal_moves(&squares, Colors::White, Some((_square("e7"), _square("e5")))).contains(&(d5, e6)));
+        assert!(!legal_moves(&squares, Colors::White, Some((_square("e6"), _square("e5")))).contains(&(d5, e6)));
+        assert!(!legal_moves(&squares, Colors::White, None).contains(&(d5, e6)));
+
+        // A pinned piece stays put
+        let squares: Squares = _squares("4r1k1/8/8/8/8/8/4N3/4K3 w - - 0 1");
+        assert!(legal_moves(&squares, Colors::White, None).iter().all(|(from, _)| *from != _square("e2")));
+    }
+
+    #[test]
+    fn mate_and_stalemate_without_the_engine()
+    {
+        // Back rank mate
+        let squares: Squares = _squares("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1");
+        assert_eq!(position_result(&squares, Colors::Black, None), Some("1-0"));
+
+        let squares: Squares = _squares("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        assert_eq!(position_result(&squares, Colors::Black, None), Some("1/2-1/2"));
+
+        assert!(is_promotion(&_squares("8/P7/8/8/8/8/p7/8 w - - 0 1"), _square("a7"), _square("a8")));
+        assert!(is_promotion(&_squares("8/P7/8/8/8/8/p7/8 b - - 0 1"), _square("a2"), _square("a1")));
+        assert!(!is_promotion(&_squares(START_FEN), _square("e2"), _square("e4")));
+    }
+}
@@ -0,0 +1,341 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use chess::colors::Colors;
+use chess::piece_types::PieceTypes;
+use json::JsonValue;
+
+use crate::autosave;
+use crate::position::{self, Squares};
+
+// Puzzles in the CSV format of the Lichess puzzle database, header optional:
+// PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,GameUrl,OpeningTags
+// The FEN is the position before the opponent's move, the first of the UCI
+// moves, the solver and the opponent take turns with the rest
+pub const PUZZLE_FILE: &str = "assets/puzzles/puzzles.csv";
+
+// The rating, the streak and the puzzles played, next to the autosave, e.g.
+// {"rating": 1523, "streak": 2, "best-streak": 7, "solved": 30, "failed": 12,
+//  "played": ["00sHx", "0009B"]}
+const STATS_FILE: &str = "puzzles.json";
+
+const START_RATING: f64 = 1500.0;
+// The most one puzzle moves the rating
+const K_FACTOR: f64 = 32.0;
+
+// A move as (from, to, promotion)
+pub type PuzzleMove = (u32, u32, Option<PieceTypes>);
+
+pub struct Puzzle
+{
+    pub id: String,
+    // Set up when the puzzle is shown, one that can't be is skipped then
+    pub fen: String,
+    pub moves: Vec<PuzzleMove>,
+    pub rating: u32,
+}
+
+impl Puzzle
+{
+    fn _from_csv(line: &str) -> Result<Puzzle, String>
+    {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if fields.len() < 4 { return Err(String::from("needs at least PuzzleId, FEN, Moves and Rating")); }
+
+        let moves: Vec<PuzzleMove> = fields[2].split_whitespace()
+            .map(|uci| _parse_uci(uci).ok_or(format!("\"{}\" is not a UCI move", uci)))
+            .collect::<Result<Vec<PuzzleMove>, String>>()?;
+        // The opponent's move and at least one to find
+        if moves.len() < 2 { return Err(String::from("needs at least two moves")); }
+
+        Ok(Puzzle{
+            id: fields[0].to_string(),
+            fen: fields[1].to_string(),
+            moves,
+            rating: fields[3].parse::<u32>().map_err(|_| format!("\"{}\" is not a rating", fields[3]))?,
+        })
+    }
+}
+
+// "e2e4" or "e7e8q"
+fn _parse_uci(uci: &str) -> Option<PuzzleMove>
+{
+    if !uci.is_ascii() || !(4..=5).contains(&uci.len()) { return None; }
+
+    let promotion: Option<PieceTypes> = match uci.get(4..)
+    {
+        Some("") => None,
+        Some("q") => Some(PieceTypes::Queen),
+        Some("r") => Some(PieceTypes::Rook),
+        Some("b") => Some(PieceTypes::Bishop),
+        Some("n") => Some(PieceTypes::Knight),
+        _ => return None,
+    };
+    Some((position::notation_to_square(&uci[0..2])?, position::notation_to_square(&uci[2..4])?, promotion))
+}
+
+// Whether `mv` of the side to move is legal and checkmates. A puzzle that
+// ends in mate is solved by any mate, not only the one of the solution
+pub fn mates(squares: &Squares, white_to_move: bool, last_move: Option<(u32, u32)>, (from, to, promotion): PuzzleMove)
+    -> bool
+{
+    let (color, opponent) = if white_to_move { (Colors::White, Colors::Black) } else { (Colors::Black, Colors::White) };
+    if !position::legal_moves(squares, color, last_move).contains(&(from, to)) { return false; }
+
+    let after: Squares = position::apply_move(squares, from, to, promotion);
+    matches!(position::position_result(&after, opponent, Some((from, to))), Some("1-0") | Some("0-1"))
+}
+
+// Every puzzle of `path`. Lines that can't be read are reported and
+// skipped, like in the games database
+pub fn load(path: &str) -> Result<Vec<Puzzle>, String>
+{
+    let text: String = fs::read_to_string(path).map_err(|err| format!("Could not read {}: {}", path, err))?;
+
+    let mut puzzles: Vec<Puzzle> = Vec::new();
+    for (i, line) in text.lines().enumerate()
+    {
+        if line.trim().is_empty() || line.starts_with("PuzzleId") { continue; }
+        match Puzzle::_from_csv(line)
+        {
+            Ok(puzzle) => puzzles.push(puzzle),
+            Err(err) => eprintln!("{}:{}: {}", path, i + 1, err),
+        }
+    }
+    Ok(puzzles)
+}
+
+pub struct PuzzleStats
+{
+    pub rating: f64,
+    pub streak: u32,
+    pub best_streak: u32,
+    pub solved: u32,
+    pub failed: u32,
+    // Ids of the puzzles done, they're only shown again once all were
+    pub played: Vec<String>,
+}
+
+impl Default for PuzzleStats
+{
+    fn default() -> PuzzleStats
+    {
+        PuzzleStats{rating: START_RATING, streak: 0, best_streak: 0, solved: 0, failed: 0, played: Vec::new()}
+    }
+}
+
+impl PuzzleStats
+{
+    fn _to_json(&self) -> JsonValue
+    {
+        json::object!{
+            "rating": self.rating,
+            "streak": self.streak,
+            "best-streak": self.best_streak,
+            "solved": self.solved,
+            "failed": self.failed,
+            "played": self.played.clone(),
+        }
+    }
+
+    // Missing keys keep their defaults
+    fn _from_json(stats: &JsonValue) -> PuzzleStats
+    {
+        let start: PuzzleStats = PuzzleStats::default();
+        PuzzleStats{
+            rating: stats["rating"].as_f64().unwrap_or(start.rating),
+            streak: stats["streak"].as_u32().unwrap_or(start.streak),
+            best_streak: stats["best-streak"].as_u32().unwrap_or(start.best_streak),
+            solved: stats["solved"].as_u32().unwrap_or(start.solved),
+            failed: stats["failed"].as_u32().unwrap_or(start.failed),
+            played: stats["played"].members().filter_map(|id| id.as_str().map(str::to_string)).collect(),
+        }
+    }
+
+    // The stats saved before, the starting ones if there are none or they
+    // can't be read
+    pub fn load() -> PuzzleStats
+    {
+        let path: PathBuf = match autosave::data_file(STATS_FILE) { Some(path) => path, None => return PuzzleStats::default() };
+        let text: String = match fs::read_to_string(&path)
+        {
+            Ok(text) => text,
+            Err(err) => {
+                if err.kind() != io::ErrorKind::NotFound { eprintln!("Could not read {}: {}", path.display(), err); }
+                return PuzzleStats::default();
+            }
+        };
+
+        match json::parse(&text)
+        {
+            Ok(stats) => PuzzleStats::_from_json(&stats),
+            Err(err) => {
+                eprintln!("{} is not valid json: {}", path.display(), err);
+                PuzzleStats::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String>
+    {
+        let path: PathBuf = autosave::writable_data_file(STATS_FILE)?;
+        fs::write(&path, self._to_json().pretty(4))
+            .map_err(|err| format!("cannot write {}: {}", path.display(), err))
+    }
+
+    // Count a finished puzzle. The rating moves like an Elo rating against
+    // the puzzle's, returns by how much
+    pub fn record(&mut self, puzzle: &Puzzle, solved: bool) -> f64
+    {
+        let expected: f64 = 1.0 / (1.0 + 10f64.powf((puzzle.rating as f64 - self.rating) / 400.0));
+        let change: f64 = K_FACTOR * (if solved { 1.0 } else { 0.0 } - expected);
+        self.rating += change;
+
+        if solved
+        {
+            self.solved += 1;
+            self.streak += 1;
+            self.best_streak = self.best_streak.max(self.streak);
+        }
+        else
+        {
+            self.failed += 1;
+            self.streak = 0;
+        }
+        if !self.played.contains(&puzzle.id) { self.played.push(puzzle.id.clone()); }
+        change
+    }
+
+    // The puzzle not played yet that is rated closest to the solver, starts
+    // over once all of them were played
+    pub fn next(&mut self, puzzles: &[Puzzle]) -> Option<usize>
+    {
+        let all_played: bool = {
+            let played: HashSet<&str> = self._played();
+            puzzles.iter().all(|puzzle| played.contains(puzzle.id.as_str()))
+        };
+        if all_played { self.played.clear(); }
+
+        let played: HashSet<&str> = self._played();
+
+        puzzles.iter().enumerate()
+            .filter(|(_, puzzle)| !played.contains(puzzle.id.as_str()))
+            .min_by_key(|(_, puzzle)| (puzzle.rating as f64 - self.rating).abs() as u32)
+            .map(|(i, _)| i)
+    }
+
+    fn _played(&self) -> HashSet<&str>
+    {
+        self.played.iter().map(String::as_str).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn _square(notation: &str) -> u32
+    {
+        position::notation_to_square(notation).unwrap()
+    }
+
+    fn _puzzle(id: &str, rating: u32) -> Puzzle
+    {
+        Puzzle{id: id.to_string(), fen: String::new(), moves: Vec::new(), rating}
+    }
+
+    #[test]
+    fn parse_uci_moves()
+    {
+        assert_eq!(_parse_uci("e2e4"), Some((_square("e2"), _square("e4"), None)));
+        assert_eq!(_parse_uci("e7e8q"), Some((_square("e7"), _square("e8"), Some(PieceTypes::Queen))));
+        assert_eq!(_parse_uci("b2a1n"), Some((_square("b2"), _square("a1"), Some(PieceTypes::Knight))));
+
+        assert_eq!(_parse_uci("e7e8k"), None);
+        assert_eq!(_parse_uci("e7e8Q"), None);
+        assert_eq!(_parse_uci("e2e9"), None);
+        assert_eq!(_parse_uci("e2"), None);
+        assert_eq!(_parse_uci("e2e4e5"), None);
+        assert_eq!(_parse_uci("é2e4"), None);
+    }
+
+    #[test]
+    fn read_csv_lines()
+    {
+        let puzzle: Puzzle = Puzzle::_from_csv(
+            "00sHx,q3k1nr/1pp1nQpp/3p4/1P2p3/4P3/B1PP1b2/B5PP/5K2 b k - 0 17,e8d7 a2e6 d7d8 f7f8,1760,80,83,72,mate")
+            .unwrap();
+        assert_eq!(puzzle.id, "00sHx");
+        assert_eq!(puzzle.fen, "q3k1nr/1pp1nQpp/3p4/1P2p3/4P3/B1PP1b2/B5PP/5K2 b k - 0 17");
+        assert_eq!(puzzle.moves.len(), 4);
+        assert_eq!(puzzle.moves[3], (_square("f7"), _square("f8"), None));
+        assert_eq!(puzzle.rating, 1760);
+
+        // Only the first four fields are needed
+        assert!(Puzzle::_from_csv("id,8/8/8/8/8/8/8/8 w - - 0 1,e2e4 e7e5,1500").is_ok());
+
+        assert!(Puzzle::_from_csv("id,8/8/8/8/8/8/8/8 w - - 0 1,e2e4 e7e5").is_err());
+        assert!(Puzzle::_from_csv("id,8/8/8/8/8/8/8/8 w - - 0 1,e2e4 e7e5,hard").is_err());
+        assert!(Puzzle::_from_csv("id,8/8/8/8/8/8/8/8 w - - 0 1,e2e4 e7e5,-5").is_err());
+        assert!(Puzzle::_from_csv("id,8/8/8/8/8/8/8/8 w - - 0 1,e2e4,1500").is_err());
+        assert!(Puzzle::_from_csv("id,8/8/8/8/8/8/8/8 w - - 0 1,e2e4 Nf3,1500").is_err());
+    }
+
+    #[test]
+    fn any_mate_is_found()
+    {
+        let (squares, white_to_move) = position::from_fen("6k1/5ppp/8/8/8/8/8/R3R1K1 w - - 0 1").unwrap();
+        assert!(mates(&squares, white_to_move, None, (_square("a1"), _square("a8"), None)));
+        assert!(mates(&squares, white_to_move, None, (_square("e1"), _square("e8"), None)));
+        assert!(!mates(&squares, white_to_move, None, (_square("a1"), _square("a7"), None)));
+        // Not a rook move
+        assert!(!mates(&squares, white_to_move, None, (_square("a1"), _square("b8"), None)));
+
+        let (squares, white_to_move) = position::from_fen("k7/2P5/1K6/8/8/8/8/8 w - - 0 1").unwrap();
+        let (c7, c8) = (_square("c7"), _square("c8"));
+        assert!(mates(&squares, white_to_move, None, (c7, c8, Some(PieceTypes::Queen))));
+        assert!(mates(&squares, white_to_move, None, (c7, c8, Some(PieceTypes::Rook))));
+        assert!(!mates(&squares, white_to_move, None, (c7, c8, Some(PieceTypes::Knight))));
+    }
+
+    #[test]
+    fn record_moves_the_rating()
+    {
+        let mut stats: PuzzleStats = PuzzleStats::default();
+        let puzzle: Puzzle = _puzzle("a", 1500);
+
+        // Against an equal rating a result is worth half the K factor
+        assert_eq!(stats.record(&puzzle, true), K_FACTOR / 2.0);
+        // Less once the rating is above the puzzle's
+        assert!(stats.record(&puzzle, true) < K_FACTOR / 2.0);
+        assert_eq!((stats.solved, stats.streak, stats.best_streak), (2, 2, 2));
+
+        // Failing a much harder puzzle costs little
+        let change: f64 = stats.record(&_puzzle("b", 2500), false);
+        assert!(change < 0.0 && change > -1.0);
+        assert_eq!((stats.failed, stats.streak, stats.best_streak), (1, 0, 2));
+        // Each id is kept once
+        assert_eq!(stats.played, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn next_is_the_closest_unplayed_puzzle()
+    {
+        let puzzles: Vec<Puzzle> = vec![_puzzle("easy", 1000), _puzzle("fair", 1550), _puzzle("hard", 2000)];
+        let mut stats: PuzzleStats = PuzzleStats::default();
+        assert_eq!(stats.next(&puzzles), Some(1));
+
+        stats.played.push(String::from("fair"));
+        assert_eq!(stats.next(&puzzles), Some(0));
+
+        // All played, they start over
+        stats.played = vec![String::from("easy"), String::from("fair"), String::from("hard")];
+        assert_eq!(stats.next(&puzzles), Some(1));
+        assert!(stats.played.is_empty());
+
+        assert_eq!(stats.next(&[]), None);
+    }
+}
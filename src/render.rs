@@ -0,0 +1,580 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+
+use chess::colors::Colors;
+use chess::piece_types::PieceTypes;
+use graphics::{Context, DrawState, Graphics, ImageSize};
+use graphics::glyph_cache::rusttype::GlyphCache;
+use graphics::types::Color;
+use texture::{CreateTexture, Format, TextureOp, TextureSettings, UpdateTexture};
+
+use crate::{ChessView, ChessViewSettings, ChessViewController, ViewLayout, VIEW_CONFIG};
+use crate::game::Game;
+use crate::position;
+use crate::cli;
+use crate::settings_file;
+
+// Drawing without a window or a GPU. `Canvas` implements piston's `Graphics`
+// on a plain pixel buffer, so `ChessView` draws the board into it with the
+// same code as on screen. Used for the `render-png` command (board diagrams
+// for docs and bug reports) and `render-gif` (a whole game, animated)
+
+// Drawn this many times bigger and scaled down, smooths the edges
+const SUPERSAMPLING: u32 = 2;
+
+// Largest canvas in pixels (supersampled), 16 bytes each
+const MAX_CANVAS_PIXELS: u32 = 64 * 1024 * 1024;
+
+pub type CanvasGlyphCache = GlyphCache<'static, (), Bitmap>;
+pub type PieceBitmaps = HashMap<(PieceTypes, Colors), Bitmap>;
+
+// RGBA image, 8 bits per channel. The texture type of `Canvas`
+#[derive(Clone)]
+pub struct Bitmap
+{
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Bitmap
+{
+    pub fn from_png(path: &Path) -> Result<Bitmap, String>
+    {
+        let file: File = File::open(path).map_err(|err| err.to_string())?;
+        let mut decoder = png::Decoder::new(file);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(|err| err.to_string())?;
+
+        let mut data: Vec<u8> = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).map_err(|err| err.to_string())?;
+        data.truncate(info.buffer_size());
+
+        let pixels: Vec<u8> = match info.color_type
+        {
+            png::ColorType::Rgba => data,
+            png::ColorType::Rgb => data.chunks(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+            png::ColorType::GrayscaleAlpha => data.chunks(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+            png::ColorType::Grayscale => data.iter().flat_map(|v| [*v, *v, *v, 255]).collect(),
+            png::ColorType::Indexed => return Err(String::from("Indexed colors weren't expanded")),
+        };
+
+        Ok(Bitmap{width: info.width, height: info.height, pixels})
+    }
+
+    // Texel at x, y as 0.0 - 1.0 values, clamped to the edges
+    fn _texel(&self, x: i64, y: i64) -> Color
+    {
+        let x: usize = x.clamp(0, self.width as i64 - 1) as usize;
+        let y: usize = y.clamp(0, self.height as i64 - 1) as usize;
+        let i: usize = 4 * (x + y * self.width as usize);
+        [
+            self.pixels[i] as f32 / 255.0,
+            self.pixels[i + 1] as f32 / 255.0,
+            self.pixels[i + 2] as f32 / 255.0,
+            self.pixels[i + 3] as f32 / 255.0,
+        ]
+    }
+
+    // Bilinear sample at texture coordinates u, v (0.0 - 1.0). Weighted by
+    // alpha so transparent texels don't darken the edges of the pieces
+    fn _sample(&self, u: f32, v: f32) -> Color
+    {
+        if self.width == 0 || self.height == 0 { return [0.0; 4]; }
+
+        let x: f32 = u * self.width as f32 - 0.5;
+        let y: f32 = v * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+
+        let mut sum: Color = [0.0; 4];
+        for (dx, dy, weight) in [(0, 0, (1.0 - fx) * (1.0 - fy)), (1, 0, fx * (1.0 - fy)),
+                                 (0, 1, (1.0 - fx) * fy), (1, 1, fx * fy)]
+        {
+            let texel: Color = self._texel(x0 as i64 + dx, y0 as i64 + dy);
+            for channel in 0..3 { sum[channel] += texel[channel] * texel[3] * weight; }
+            sum[3] += texel[3] * weight;
+        }
+
+        if sum[3] > 0.0
+        {
+            let alpha: f32 = sum[3];
+            for channel in sum.iter_mut().take(3) { *channel /= alpha; }
+        }
+        sum
+    }
+}
+
+impl ImageSize for Bitmap
+{
+    fn get_size(&self) -> (u32, u32)
+    {
+        (self.width, self.height)
+    }
+}
+
+// The glyph cache makes its textures through these, there's no factory
+impl TextureOp<()> for Bitmap
+{
+    type Error = String;
+}
+
+impl CreateTexture<()> for Bitmap
+{
+    fn create<S: Into<[u32; 2]>>(_factory: &mut (), _format: Format, memory: &[u8], size: S,
+        _settings: &TextureSettings) -> Result<Bitmap, String>
+    {
+        let [width, height] = size.into();
+        if memory.len() != (4 * width * height) as usize
+        {
+            return Err(format!("Expected {} bytes for a {}x{} texture, got {}",
+                4 * width * height, width, height, memory.len()));
+        }
+
+        Ok(Bitmap{width, height, pixels: memory.to_vec()})
+    }
+}
+
+impl UpdateTexture<()> for Bitmap
+{
+    fn update<O, S>(&mut self, _factory: &mut (), _format: Format, memory: &[u8], offset: O, size: S)
+        -> Result<(), String>
+        where O: Into<[u32; 2]>,
+              S: Into<[u32; 2]>
+    {
+        let [x, y] = offset.into();
+        let [width, height] = size.into();
+        if x + width > self.width || y + height > self.height || memory.len() != (4 * width * height) as usize
+        {
+            return Err(String::from("Texture update out of bounds"));
+        }
+
+        for row in 0..height as usize
+        {
+            let src: usize = row * 4 * width as usize;
+            let dst: usize = 4 * (x as usize + (y as usize + row) * self.width as usize);
+            self.pixels[dst..dst + 4 * width as usize].copy_from_slice(&memory[src..src + 4 * width as usize]);
+        }
+        Ok(())
+    }
+}
+
+// Pixel buffer piston's shapes, images and text can be drawn into.
+// Triangles are filled without anti-aliasing (see `SUPERSAMPLING`), the
+// draw state (clipping, stencils) is ignored as `ChessView` doesn't use it
+pub struct Canvas
+{
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+}
+
+impl Canvas
+{
+    pub fn new(width: u32, height: u32) -> Result<Canvas, String>
+    {
+        let pixels: u32 = width.checked_mul(height).filter(|pixels| *pixels <= MAX_CANVAS_PIXELS)
+            .ok_or(format!("A {}x{} image is too big", width, height))?;
+        Ok(Canvas{width, height, pixels: vec![[0.0; 4]; pixels as usize]})
+    }
+
+    // The image scaled down by `factor`, averaging each `factor` x `factor`
+    // block of pixels. RGBA, 8 bits per channel
+    pub fn to_rgba8(&self, factor: u32) -> Vec<u8>
+    {
+        let (width, height) = (self.width / factor, self.height / factor);
+        let mut data: Vec<u8> = Vec::with_capacity((4 * width * height) as usize);
+
+        for y in 0..height
+        {
+            for x in 0..width
+            {
+                let mut sum: Color = [0.0; 4];
+                for sy in 0..factor
+                {
+                    for sx in 0..factor
+                    {
+                        let pixel: Color = self.pixels[(x*factor + sx + (y*factor + sy) * self.width) as usize];
+                        for channel in 0..4 { sum[channel] += pixel[channel]; }
+                    }
+                }
+                let count: f32 = (factor * factor) as f32;
+                data.extend(sum.iter().map(|v| ((v / count).clamp(0.0, 1.0) * 255.0).round() as u8));
+            }
+        }
+        data
+    }
+
+    pub fn write_png(&self, path: &Path, factor: u32) -> Result<(), String>
+    {
+        let file: File = File::create(path).map_err(|err| format!("Could not create {}: {}", path.display(), err))?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width / factor, self.height / factor);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().map_err(|err| err.to_string())?;
+        writer.write_image_data(&self.to_rgba8(factor)).map_err(|err| err.to_string())
+    }
+
+    // Fills one triangle given in normalized device coordinates (-1.0 - 1.0,
+    // y up). `shade` gets the barycentric weights of each covered pixel
+    // center and returns its color
+    fn _fill_triangle(&mut self, vertices: [[f32; 2]; 3], shade: impl Fn([f32; 3]) -> Color)
+    {
+        let p: [[f32; 2]; 3] = vertices.map(|[x, y]| [
+            (x + 1.0) / 2.0 * self.width as f32,
+            (1.0 - y) / 2.0 * self.height as f32
+        ]);
+
+        let area: f32 = _edge(p[0], p[1], p[2]);
+        if area.abs() < f32::EPSILON { return; }
+
+        let min_x: u32 = p.iter().map(|v| v[0]).fold(f32::MAX, f32::min).floor().max(0.0) as u32;
+        let min_y: u32 = p.iter().map(|v| v[1]).fold(f32::MAX, f32::min).floor().max(0.0) as u32;
+        let max_x: u32 = p.iter().map(|v| v[0]).fold(f32::MIN, f32::max).ceil().clamp(0.0, self.width as f32) as u32;
+        let max_y: u32 = p.iter().map(|v| v[1]).fold(f32::MIN, f32::max).ceil().clamp(0.0, self.height as f32) as u32;
+
+        for y in min_y..max_y
+        {
+            for x in min_x..max_x
+            {
+                let center: [f32; 2] = [x as f32 + 0.5, y as f32 + 0.5];
+                let weights: [f32; 3] = [
+                    _edge(p[1], p[2], center) / area,
+                    _edge(p[2], p[0], center) / area,
+                    _edge(p[0], p[1], center) / area,
+                ];
+                if weights.iter().any(|w| *w < 0.0) { continue; }
+
+                self._blend(x, y, shade(weights));
+            }
+        }
+    }
+
+    // Alpha blends `color` over the pixel, like the OpenGL back-end does
+    fn _blend(&mut self, x: u32, y: u32, color: Color)
+    {
+        let pixel: &mut Color = &mut self.pixels[(x + y * self.width) as usize];
+        let alpha: f32 = color[3];
+        for channel in 0..3
+        {
+            pixel[channel] = color[channel] * alpha + pixel[channel] * (1.0 - alpha);
+        }
+        pixel[3] = alpha + pixel[3] * (1.0 - alpha);
+    }
+}
+
+impl Graphics for Canvas
+{
+    type Texture = Bitmap;
+
+    fn clear_color(&mut self, color: Color)
+    {
+        self.pixels.fill(color);
+    }
+
+    fn clear_stencil(&mut self, _value: u8) {}
+
+    fn tri_list<F>(&mut self, _draw_state: &DrawState, color: &[f32; 4], mut f: F)
+        where F: FnMut(&mut dyn FnMut(&[[f32; 2]]))
+    {
+        f(&mut |vertices: &[[f32; 2]]| {
+            for tri in vertices.chunks_exact(3)
+            {
+                self._fill_triangle([tri[0], tri[1], tri[2]], |_| *color);
+            }
+        });
+    }
+
+    fn tri_list_c<F>(&mut self, _draw_state: &DrawState, mut f: F)
+        where F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 4]]))
+    {
+        f(&mut |vertices: &[[f32; 2]], colors: &[[f32; 4]]| {
+            for (tri, cols) in vertices.chunks_exact(3).zip(colors.chunks_exact(3))
+            {
+                self._fill_triangle([tri[0], tri[1], tri[2]], |w| _mix(cols, w));
+            }
+        });
+    }
+
+    fn tri_list_uv<F>(&mut self, _draw_state: &DrawState, color: &[f32; 4], texture: &Bitmap, mut f: F)
+        where F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]]))
+    {
+        f(&mut |vertices: &[[f32; 2]], uvs: &[[f32; 2]]| {
+            for (tri, uv) in vertices.chunks_exact(3).zip(uvs.chunks_exact(3))
+            {
+                self._fill_triangle([tri[0], tri[1], tri[2]], |w| {
+                    let texel: Color = _sample_at(texture, uv, w);
+                    [texel[0] * color[0], texel[1] * color[1], texel[2] * color[2], texel[3] * color[3]]
+                });
+            }
+        });
+    }
+
+    fn tri_list_uv_c<F>(&mut self, _draw_state: &DrawState, texture: &Bitmap, mut f: F)
+        where F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]], &[[f32; 4]]))
+    {
+        f(&mut |vertices: &[[f32; 2]], uvs: &[[f32; 2]], colors: &[[f32; 4]]| {
+            for ((tri, uv), cols) in vertices.chunks_exact(3).zip(uvs.chunks_exact(3)).zip(colors.chunks_exact(3))
+            {
+                self._fill_triangle([tri[0], tri[1], tri[2]], |w| {
+                    let texel: Color = _sample_at(texture, uv, w);
+                    let color: Color = _mix(cols, w);
+                    [texel[0] * color[0], texel[1] * color[1], texel[2] * color[2], texel[3] * color[3]]
+                });
+            }
+        });
+    }
+}
+
+// Options of `render-png` and `render-gif`
+struct RenderOptions
+{
+    out: String,
+    width: Option<u32>,
+    flipped: bool,
+    config: String,
+    theme: Option<String>,
+    piece_set: Option<String>,
+    // render-png
+    fen: String,
+    selected: Option<u32>,
+    arrows: Vec<String>,
+    circles: Vec<String>,
+    // render-gif
+    pgn: String,
+    delay_ms: u32,
+    last_move: bool,
+}
+
+pub const RENDER_PNG_USAGE: &str = "\
+Usage: chess-gui render-png --out FILE [options]
+  --fen FEN         Position to draw (default: the starting position)
+  --out FILE        Where to write the PNG
+  --width PIXELS    Width of the image (default: the window width)
+  --flip            Draw the board from black's side
+  --config FILE     Settings file (default: config/view.json)
+  --theme NAME      Board theme from assets/themes
+  --pieces NAME     Piece set from assets/pieces
+  --select SQUARE   Highlight a square like a selected piece, e.g. e2
+  --arrows LIST     Arrows like the PGN [%cal] comment, e.g. Ge2e4,Rd8h4
+  --circles LIST    Circled squares like [%csl], e.g. Gd4,Ye5";
+
+pub const RENDER_GIF_USAGE: &str = "\
+Usage: chess-gui render-gif --pgn FILE --out FILE [options]
+  --pgn FILE        Game to animate, the first one in the file
+  --out FILE        Where to write the GIF
+  --delay MS        Time each move is shown (default: 1000)
+  --width PIXELS    Width of the image (default: the window width)
+  --flip            Draw the board from black's side
+  --config FILE     Settings file (default: config/view.json)
+  --theme NAME      Board theme from assets/themes
+  --pieces NAME     Piece set from assets/pieces
+  --no-last-move    Don't highlight the last move";
+
+// Color quantization speed of the GIF frames, 1 (best) - 30 (fastest)
+const GIF_QUANTIZE_SPEED: i32 = 10;
+
+// `chess-gui render-png ...`, draws a position to a PNG without opening a window
+pub fn render_png_command(args: &[String]) -> Result<(), String>
+{
+    let options: RenderOptions = _parse_render_options(args, false)?;
+
+    let mut chessview: ChessView = ChessView::new(_render_settings(&options)?);
+    let (squares, _) = position::from_fen(&options.fen)?;
+    chessview.position = Some(squares);
+    chessview.flipped = options.flipped;
+    let annotations = chessview.game.annotations_mut();
+    for list in options.arrows.iter() { annotations.add_arrows(list)?; }
+    for list in options.circles.iter() { annotations.add_circles(list)?; }
+
+    let mut controller: ChessViewController = ChessViewController::new();
+    if let Some(square) = options.selected { controller.selected = position::square_to_block(square); }
+
+    let canvas: Canvas = render(&mut chessview, &controller, options.width)?;
+    canvas.write_png(Path::new(&options.out), SUPERSAMPLING)?;
+    println!("Wrote {}", options.out);
+    Ok(())
+}
+
+// `chess-gui render-gif ...`, animates a PGN game, one frame per move
+pub fn render_gif_command(args: &[String]) -> Result<(), String>
+{
+    let options: RenderOptions = _parse_render_options(args, true)?;
+
+    let pgn: String = fs::read_to_string(&options.pgn)
+        .map_err(|err| format!("Could not read {}: {}", options.pgn, err))?;
+    let game: Game = Game::from_pgn(&pgn)?;
+
+    let mut chessview: ChessView = ChessView::new(_render_settings(&options)?);
+    chessview.flipped = options.flipped;
+    if !options.last_move { chessview.settings._last_move_col = [0.0; 4]; }
+    let controller: ChessViewController = ChessViewController::new();
+
+    let (mut glyph, textures) = load_resources(&chessview.settings)?;
+    let size: [f64; 2] = _image_size(&chessview.settings, options.width)?;
+    if size[0] > u16::MAX as f64 || size[1] > u16::MAX as f64 { return Err(String::from("The GIF would be too big")); }
+    let (width, height) = (size[0] as u16, size[1] as u16);
+
+    let file: File = File::create(&options.out).map_err(|err| format!("Could not create {}: {}", options.out, err))?;
+    let mut encoder = gif::Encoder::new(BufWriter::new(file), width, height, &[]).map_err(|err| err.to_string())?;
+    encoder.set_repeat(gif::Repeat::Infinite).map_err(|err| err.to_string())?;
+
+    let positions: Vec<position::Squares> = game.positions();
+    for (ply, squares) in positions.iter().enumerate()
+    {
+        // The game up to this position, for the last move and the annotations
+        chessview.position = Some(*squares);
+        chessview.game = game.clone();
+        chessview.game.go_to(ply);
+
+        let canvas: Canvas = render_with(&mut chessview, &controller, size, &mut glyph, &textures)?;
+        let mut rgba: Vec<u8> = canvas.to_rgba8(SUPERSAMPLING);
+        let mut frame = gif::Frame::from_rgba_speed(width, height, &mut rgba, GIF_QUANTIZE_SPEED);
+
+        // The final position stays a while before it starts over
+        let delay_ms: u32 = if ply + 1 == positions.len() { options.delay_ms * 3 } else { options.delay_ms };
+        frame.delay = (delay_ms / 10).min(u16::MAX as u32) as u16;
+        encoder.write_frame(&frame).map_err(|err| err.to_string())?;
+    }
+
+    println!("Wrote {} ({} moves)", options.out, game.line().len());
+    Ok(())
+}
+
+// Draw the board of `chessview` to a canvas `width` pixels wide (the view
+// width by default) times `SUPERSAMPLING`. Changes the layout of `chessview`
+pub fn render(chessview: &mut ChessView, controller: &ChessViewController, width: Option<u32>)
+    -> Result<Canvas, String>
+{
+    let (mut glyph, textures) = load_resources(&chessview.settings)?;
+    let size: [f64; 2] = _image_size(&chessview.settings, width)?;
+    render_with(chessview, controller, size, &mut glyph, &textures)
+}
+
+// The font and piece images for drawing on a `Canvas`
+pub fn load_resources(settings: &ChessViewSettings) -> Result<(CanvasGlyphCache, PieceBitmaps), String>
+{
+    let glyph: CanvasGlyphCache = GlyphCache::new(&settings._font, (), TextureSettings::new())
+        .map_err(|err| format!("Could not load {}: {}", settings._font, err))?;
+    let textures: PieceBitmaps = settings_file::load_piece_set(&settings._piece_set, Bitmap::from_png);
+    Ok((glyph, textures))
+}
+
+// Draw the board into a new canvas of `size` (in output pixels), with
+// resources loaded once by the caller
+pub fn render_with(chessview: &mut ChessView, controller: &ChessViewController, size: [f64; 2],
+    glyph: &mut CanvasGlyphCache, textures: &PieceBitmaps) -> Result<Canvas, String>
+{
+    let samples: f64 = SUPERSAMPLING as f64;
+    let mut canvas: Canvas = Canvas::new((size[0] * samples) as u32, (size[1] * samples) as u32)?;
+    chessview.fit(size, [size[0] * samples, size[1] * samples]);
+
+    canvas.clear_color([1.0; 4]);
+    chessview.draw_diagram(glyph, textures, controller, &Context::new_abs(size[0], size[1]), &mut canvas);
+    Ok(canvas)
+}
+
+// Output size for a width, the height keeps the proportions of the view.
+// Too big for a canvas once supersampled is an error
+fn _image_size(settings: &ChessViewSettings, width: Option<u32>) -> Result<[f64; 2], String>
+{
+    let view_size: [f64; 2] = ViewLayout::fit(settings, [1.0; 2], [1.0; 2]).view_size;
+    let width: f64 = width.map(|width| width as f64).unwrap_or(view_size[0]).round().max(1.0);
+    let size: [f64; 2] = [width, (width * view_size[1] / view_size[0]).round().max(1.0)];
+
+    let samples: f64 = SUPERSAMPLING as f64;
+    if size[0] * samples * size[1] * samples > MAX_CANVAS_PIXELS as f64
+    {
+        return Err(format!("A {}x{} image is too big", size[0], size[1]));
+    }
+    Ok(size)
+}
+
+// The settings file of the options like the GUI loads it, with the piece set
+fn _render_settings(options: &RenderOptions) -> Result<ChessViewSettings, String>
+{
+    let mut settings: ChessViewSettings = cli::load_settings(&options.config, options.theme.as_deref())?;
+    if let Some(piece_set) = &options.piece_set { settings._piece_set = piece_set.clone(); }
+    Ok(settings)
+}
+
+fn _parse_render_options(args: &[String], gif: bool) -> Result<RenderOptions, String>
+{
+    let usage: &str = if gif { RENDER_GIF_USAGE } else { RENDER_PNG_USAGE };
+    let mut options: RenderOptions = RenderOptions{
+        out: String::new(),
+        width: None,
+        flipped: false,
+        config: String::from(VIEW_CONFIG),
+        theme: None,
+        piece_set: None,
+        fen: String::from(position::START_FEN),
+        selected: None,
+        arrows: Vec::new(),
+        circles: Vec::new(),
+        pgn: String::new(),
+        delay_ms: 1000,
+        last_move: true,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next()
+    {
+        let mut value = || args.next().cloned().ok_or(format!("{} needs a value\n{}", arg, usage));
+        match (arg.as_str(), gif)
+        {
+            ("--out", _) => options.out = value()?,
+            ("--width", _) => options.width = Some(value()?.parse::<u32>()
+                .ok().filter(|width| *width > 0)
+                .ok_or(String::from("--width must be a positive whole number"))?),
+            ("--flip", _) => options.flipped = true,
+            ("--config", _) => options.config = value()?,
+            ("--theme", _) => options.theme = Some(value()?),
+            ("--pieces", _) => options.piece_set = Some(value()?),
+            ("--fen", false) => options.fen = value()?,
+            ("--select", false) => {
+                let square: String = value()?;
+                options.selected = Some(position::notation_to_square(&square)
+                    .ok_or(format!("Bad square \"{}\"", square))?);
+            },
+            ("--arrows", false) => options.arrows.push(value()?),
+            ("--circles", false) => options.circles.push(value()?),
+            ("--pgn", true) => options.pgn = value()?,
+            ("--delay", true) => options.delay_ms = value()?.parse::<u32>()
+                .map_err(|_| String::from("--delay must be a whole number of milliseconds"))?,
+            ("--no-last-move", true) => options.last_move = false,
+            _ => return Err(format!("Unknown option \"{}\"\n{}", arg, usage)),
+        }
+    }
+
+    if options.out.is_empty() { return Err(format!("--out is missing\n{}", usage)); }
+    if gif && options.pgn.is_empty() { return Err(format!("--pgn is missing\n{}", usage)); }
+    Ok(options)
+}
+
+// Twice the signed area of the triangle a, b, c
+fn _edge(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> f32
+{
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+// Vertex colors interpolated with barycentric weights
+fn _mix(colors: &[[f32; 4]], weights: [f32; 3]) -> Color
+{
+    let mut color: Color = [0.0; 4];
+    for (vertex, weight) in colors.iter().zip(weights)
+    {
+        for channel in 0..4 { color[channel] += vertex[channel] * weight; }
+    }
+    color
+}
+
+fn _sample_at(texture: &Bitmap, uvs: &[[f32; 2]], weights: [f32; 3]) -> Color
+{
+    let u: f32 = uvs[0][0] * weights[0] + uvs[1][0] * weights[1] + uvs[2][0] * weights[2];
+    let v: f32 = uvs[0][1] * weights[0] + uvs[1][1] * weights[1] + uvs[2][1] * weights[2];
+    texture._sample(u, v)
+}
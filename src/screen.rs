@@ -0,0 +1,1192 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use chess::colors::Colors;
+use chess::piece_types::PieceTypes;
+use graphics::{Context, Rectangle, Text};
+use opengl_graphics::{GlGraphics, GlyphCache, Texture};
+use piston::{Event, Key};
+
+use crate::{ChessView, ChessViewSettings, ChessViewController};
+use crate::autosave::{self, SavedGame};
+use crate::database::{self, ArchivedGame, Filter};
+use crate::chessview_controller::GameMode;
+use crate::animation::Animation;
+use crate::game::{Game, PlayedMove};
+use crate::inet::discovery::DiscoveredGame;
+use crate::position::{self, Squares};
+use crate::puzzle::{self, Puzzle, PuzzleMove, PuzzleStats};
+use crate::settings_file::PREFERENCES;
+use crate::widgets::{UiInput, Button, Checkbox, Choice, Slider, TextField, ScrollList};
+
+// Longest "host:port" typed in the lobby
+const MAX_ADDRESS_LEN: usize = 64;
+
+// The far end of the animation slider on the settings screen
+const MAX_ANIMATION_MS: f64 = 1000.0;
+
+// Filter fields of the games screen
+const MAX_OPPONENT_LEN: usize = 32;
+const DATE_LEN: usize = 10;
+
+// Replay autoplay speeds, the time between two moves
+const MIN_STEP_MS: f64 = 200.0;
+const MAX_STEP_MS: f64 = 3000.0;
+const DEFAULT_STEP_MS: f64 = 1000.0;
+// Around and between the rows of the replay bar
+const REPLAY_PADDING: f64 = 10.0;
+
+// Time to see the position before the opponent's puzzle move
+const PUZZLE_REPLY_MS: u64 = 600;
+const PUZZLE_PADDING: f64 = 8.0;
+const PUZZLE_BUTTON_WIDTH: f64 = 100.0;
+
+// What the stack does with a screen after it handled an event
+pub enum Transition
+{
+    None,
+    // Show a screen on top, e.g. the lobby over the intro or the pause menu
+    // over a game
+    Push(Box<dyn Screen>),
+    // Back to the screen below, popping the last one closes the window
+    Pop,
+    Replace(Box<dyn Screen>),
+    // Drop every screen and start over from these, the last one on top
+    Reset(Vec<Box<dyn Screen>>),
+}
+
+// One screen of the GUI. The controller keeps them on a stack, only the top
+// one gets the input. An overlay (pause, promotion..) draws over the
+// screens below it instead of hiding them
+pub trait Screen
+{
+    // Called for every screen on the stack on every event, so a game
+    // under the pause menu keeps receiving its network messages. `covered`
+    // when another screen is on top, nothing should be played then
+    fn update(&mut self, _controller: &mut ChessViewController, _chessview: &mut ChessView, _covered: bool) {}
+
+    fn handle_event(&mut self, controller: &mut ChessViewController, chessview: &mut ChessView,
+                    e: &Event) -> Transition;
+
+    fn draw(&self, chessview: &ChessView, glyph: &mut GlyphCache,
+            textures: &HashMap<(PieceTypes, Colors), Texture>,
+            controller: &ChessViewController, c: &Context, g: &mut GlGraphics);
+
+    fn is_overlay(&self) -> bool { false }
+
+    // Whether it changes by itself, e.g. a replay playing, so the event
+    // loop has to keep running without input
+    fn needs_polling(&self) -> bool { false }
+}
+
+#[derive(Default)]
+pub struct ScreenStack
+{
+    screens: Vec<Box<dyn Screen>>
+}
+
+impl ScreenStack
+{
+    pub fn new(first: Box<dyn Screen>) -> ScreenStack
+    {
+        ScreenStack{screens: vec![first]}
+    }
+
+    pub fn push(&mut self, screen: Box<dyn Screen>)
+    {
+        self.screens.push(screen);
+    }
+
+    // Nothing left to show, the window should close
+    pub fn is_empty(&self) -> bool
+    {
+        self.screens.is_empty()
+    }
+
+    pub fn needs_polling(&self) -> bool
+    {
+        self.screens.iter().any(|screen| screen.needs_polling())
+    }
+
+    // Returns whether the screens changed, e.g. a new game was set up
+    pub fn event(&mut self, controller: &mut ChessViewController, chessview: &mut ChessView, e: &Event) -> bool
+    {
+        let top: usize = self.screens.len().saturating_sub(1);
+        for (i, screen) in self.screens.iter_mut().enumerate()
+        {
+            screen.update(controller, chessview, i < top);
+        }
+
+        let transition: Transition = match self.screens.last_mut()
+        {
+            Some(screen) => screen.handle_event(controller, chessview, e),
+            None => return false
+        };
+
+        match transition
+        {
+            Transition::None => return false,
+            Transition::Push(screen) => self.screens.push(screen),
+            Transition::Pop => { self.screens.pop(); },
+            Transition::Replace(screen) => {
+                self.screens.pop();
+                self.screens.push(screen);
+            },
+            Transition::Reset(screens) => self.screens = screens,
+        }
+        true
+    }
+
+    // Draws the top screen, and the ones below it as long as the ones above
+    // are overlays
+    pub fn draw(&self, chessview: &ChessView, glyph: &mut GlyphCache,
+                textures: &HashMap<(PieceTypes, Colors), Texture>,
+                controller: &ChessViewController, c: &Context, g: &mut GlGraphics)
+    {
+        let first: usize = self.screens.iter().rposition(|screen| !screen.is_overlay()).unwrap_or(0);
+        for screen in self.screens.iter().skip(first)
+        {
+            screen.draw(chessview, glyph, textures, controller, c, g);
+        }
+    }
+}
+
+// Title and the SP, MP, Host, Settings, Games and Puzzles buttons, and
+// Continue when a game was saved
+pub struct IntroScreen
+{
+    saved: Option<SavedGame>,
+}
+
+impl IntroScreen
+{
+    pub fn new() -> IntroScreen
+    {
+        let saved: Option<SavedGame> = autosave::load().unwrap_or_else(|err| {
+            eprintln!("Could not load the saved game: {}", err);
+            None
+        });
+        IntroScreen{saved}
+    }
+}
+
+fn _intro_buttons(settings: &ChessViewSettings) -> [Button<'static>; 7]
+{
+    let [w, h] = settings._intro_button_size;
+    let button = |pos: [f64; 2], label: &'static str| Button::new([pos[0], pos[1], w, h], label);
+    [
+        button(settings._intro_sp_button_pos, "SP"),
+        button(settings._intro_mp_button_pos, "MP"),
+        button(settings._intro_host_button_pos, "Host"),
+        button(settings._intro_settings_button_pos, "Settings"),
+        button(settings._intro_games_button_pos, "Games"),
+        button(settings._intro_puzzles_button_pos, "Puzzles"),
+        button(settings._intro_continue_button_pos, "Continue"),
+    ]
+}
+
+impl Screen for IntroScreen
+{
+    fn handle_event(&mut self, controller: &mut ChessViewController, chessview: &mut ChessView,
+                    _e: &Event) -> Transition
+    {
+        if controller.ui.key == Some(Key::Escape) { return Transition::Pop; }
+
+        let [sp, mp, host, settings, games, puzzles, resume] = _intro_buttons(&chessview.settings);
+        if let (Some(saved), true) = (&self.saved, resume.clicked(&controller.ui))
+        {
+            match &saved.server
+            {
+                Some((game, spectate)) => controller.connect(game, *spectate, chessview),
+                None => {
+                    controller.start_game(chessview, saved.mode, saved.game.clone());
+                    chessview.flipped = saved.flipped;
+                },
+            }
+            Transition::Push(Box::new(GameScreen))
+        }
+        else if sp.clicked(&controller.ui)
+        {
+            controller.start_game(chessview, GameMode::Local, Game::new());
+            Transition::Push(Box::new(GameScreen))
+        }
+        else if mp.clicked(&controller.ui)
+        {
+            controller.open_lobby();
+            Transition::Push(Box::new(LobbyScreen::new()))
+        }
+        else if host.clicked(&controller.ui)
+        {
+            controller.start_hosting(chessview);
+            Transition::Push(Box::new(GameScreen))
+        }
+        else if settings.clicked(&controller.ui)
+        {
+            controller.open_settings();
+            Transition::Push(Box::new(SettingsScreen::new()))
+        }
+        else if games.clicked(&controller.ui) { Transition::Push(Box::new(GamesScreen::new())) }
+        else if puzzles.clicked(&controller.ui)
+        {
+            Transition::Push(Box::new(PuzzleScreen::new(puzzle::PUZZLE_FILE, controller, chessview)))
+        }
+        else { Transition::None }
+    }
+
+    fn draw(&self, chessview: &ChessView, glyph: &mut GlyphCache,
+            _textures: &HashMap<(PieceTypes, Colors), Texture>,
+            controller: &ChessViewController, c: &Context, g: &mut GlGraphics)
+    {
+        chessview.draw_background(c, g);
+        chessview.draw_text(Text::new_color([1.0; 4], 50), "Chess 2",
+            chessview.settings._intro_text_coords, glyph, c, g);
+
+        let [sp, mp, host, settings, games, puzzles, resume] = _intro_buttons(&chessview.settings);
+        for button in [sp, mp, host, settings, games, puzzles]
+        {
+            button.draw(chessview, &controller.ui, glyph, c, g);
+        }
+        if self.saved.is_some() { resume.draw(chessview, &controller.ui, glyph, c, g); }
+    }
+}
+
+// Title and status line of the lobby and settings screens
+fn _draw_menu_title(chessview: &ChessView, title: &str, status: &str, glyph: &mut GlyphCache,
+                    c: &Context, g: &mut GlGraphics)
+{
+    let settings: &ChessViewSettings = &chessview.settings;
+    chessview.draw_background(c, g);
+    chessview.draw_text(Text::new_color([1.0; 4], 40), title, settings._lobby_title_coords, glyph, c, g);
+    chessview.draw_text(Text::new_color([1.0; 4], 16), status,
+        [settings._lobby_row_pos[0], settings._lobby_row_pos[1] - 12.0], glyph, c, g);
+}
+
+// The configured server and the games announced on the LAN, and a field
+// for joining any server by its address
+pub struct LobbyScreen
+{
+    address: TextField,
+    games: ScrollList,
+}
+
+impl LobbyScreen
+{
+    pub fn new() -> LobbyScreen
+    {
+        LobbyScreen{address: TextField::new(MAX_ADDRESS_LEN), games: ScrollList::new()}
+    }
+
+    // The address field and its join button, on the first row
+    fn _address_row(settings: &ChessViewSettings) -> ([f64; 4], Button<'static>)
+    {
+        let [x, y, w, h] = settings.lobby_row_rect(0);
+        let button_w: f64 = 100.0;
+        ([x, y, w - button_w - settings._lobby_row_gap, h], Button::new([x + w - button_w, y, button_w, h], "Join"))
+    }
+}
+
+impl Screen for LobbyScreen
+{
+    fn update(&mut self, controller: &mut ChessViewController, _chessview: &mut ChessView, _covered: bool)
+    {
+        controller.poll_lobby();
+    }
+
+    fn handle_event(&mut self, controller: &mut ChessViewController, chessview: &mut ChessView,
+                    _e: &Event) -> Transition
+    {
+        let settings: &ChessViewSettings = &chessview.settings;
+        let back: Button = Button::new(settings.lobby_back_button_rect(), "Back");
+        if back.clicked(&controller.ui) || controller.ui.key == Some(Key::Escape)
+        {
+            controller.discovery = None;
+            return Transition::Pop;
+        }
+
+        let (address_rect, join) = LobbyScreen::_address_row(settings);
+        let submitted: bool = self.address.handle(address_rect, &controller.ui);
+        if submitted || join.clicked(&controller.ui)
+        {
+            let address: String = self.address.value.trim().to_string();
+            return match controller.join_address(&address, chessview)
+            {
+                Ok(()) => Transition::Replace(Box::new(GameScreen)),
+                Err(err) => { controller.lobby_status = err; Transition::None },
+            };
+        }
+
+        let list_rect: [f64; 4] = settings.lobby_list_rect(1);
+        match self.games.handle(list_rect, controller.lobby_games.len(), settings, &controller.ui)
+        {
+            Some(i) => {
+                let game: DiscoveredGame = controller.lobby_games[i].clone();
+                controller.join_game(&game, chessview);
+                Transition::Replace(Box::new(GameScreen))
+            },
+            None => Transition::None
+        }
+    }
+
+    fn draw(&self, chessview: &ChessView, glyph: &mut GlyphCache,
+            _textures: &HashMap<(PieceTypes, Colors), Texture>,
+            controller: &ChessViewController, c: &Context, g: &mut GlGraphics)
+    {
+        let settings: &ChessViewSettings = &chessview.settings;
+
+        let mut status: &str = controller.lobby_status.as_str();
+        if status.is_empty() && controller.lobby_games.len() <= 1
+        {
+            status = "Looking for games on the LAN..";
+        }
+        _draw_menu_title(chessview, "Join a game", status, glyph, c, g);
+
+        let (address_rect, join) = LobbyScreen::_address_row(settings);
+        self.address.draw(address_rect, "host:port", chessview, glyph, c, g);
+        join.draw(chessview, &controller.ui, glyph, c, g);
+
+        // The configured server has no player count until connected
+        let rows: Vec<(String, bool)> = controller.lobby_games.iter().map(|game| {
+            let label: String = if game.players == 0 && game.spectators == 0
+            {
+                format!("{} - {} - game {}", game.name, game.ip_port, game.game_id)
+            }
+            else
+            {
+                format!("{} - game {} - {}/2 players, {} watching",
+                    game.name, game.game_id, game.players, game.spectators)
+            };
+            (label, false)
+        }).collect();
+        self.games.draw(settings.lobby_list_rect(1), &rows, chessview, glyph, c, g);
+
+        Button::new(settings.lobby_back_button_rect(), "Back").draw(chessview, &controller.ui, glyph, c, g);
+    }
+}
+
+// Coordinates and animation options, then one row per theme and piece set.
+// Every change is used right away and remembered in config/preferences.json
+pub struct SettingsScreen
+{
+    rows: ScrollList,
+}
+
+impl SettingsScreen
+{
+    pub fn new() -> SettingsScreen
+    {
+        SettingsScreen{rows: ScrollList::new()}
+    }
+
+    fn _coords(settings: &ChessViewSettings) -> Checkbox<'static>
+    {
+        Checkbox::new(settings.lobby_row_rect(0), "Show coordinates", settings._coords)
+    }
+
+    fn _animation<'a>(settings: &ChessViewSettings, label: &'a str) -> Slider<'a>
+    {
+        Slider::new(settings.lobby_row_rect(1), label, 0.0, MAX_ANIMATION_MS, settings._animation_ms as f64)
+    }
+
+    fn _save(controller: &mut ChessViewController, settings: &ChessViewSettings)
+    {
+        controller.settings_status = match settings.save_preferences(PREFERENCES)
+        {
+            Ok(()) => String::new(),
+            Err(err) => err,
+        };
+    }
+}
+
+impl Screen for SettingsScreen
+{
+    fn handle_event(&mut self, controller: &mut ChessViewController, chessview: &mut ChessView,
+                    _e: &Event) -> Transition
+    {
+        let back: Button = Button::new(chessview.settings.lobby_back_button_rect(), "Back");
+        if back.clicked(&controller.ui) || controller.ui.key == Some(Key::Escape) { return Transition::Pop; }
+
+        if SettingsScreen::_coords(&chessview.settings).toggled(&controller.ui)
+        {
+            chessview.settings._coords = !chessview.settings._coords;
+            SettingsScreen::_save(controller, &chessview.settings);
+        }
+
+        let animation: Slider = SettingsScreen::_animation(&chessview.settings, "");
+        if let Some(ms) = animation.dragged(&controller.ui)
+        {
+            // In steps of 10 ms
+            chessview.settings._animation_ms = (ms / 10.0).round() as u32 * 10;
+        }
+        if animation.released(&controller.ui)
+        {
+            SettingsScreen::_save(controller, &chessview.settings);
+        }
+
+        let themes: usize = controller.settings_themes.len();
+        let rows: usize = themes + controller.settings_piece_sets.len();
+        let list_rect: [f64; 4] = chessview.settings.lobby_list_rect(2);
+        let i: usize = match self.rows.handle(list_rect, rows, &chessview.settings, &controller.ui)
+        {
+            Some(i) => i,
+            None => return Transition::None
+        };
+
+        if i < themes
+        {
+            if let Err(err) = chessview.settings.apply_theme(&controller.settings_themes[i])
+            {
+                controller.settings_status = err;
+                return Transition::None;
+            }
+        }
+        else
+        {
+            // main.rs notices the change and loads the new textures
+            chessview.settings._piece_set = controller.settings_piece_sets[i - themes].clone();
+        }
+
+        SettingsScreen::_save(controller, &chessview.settings);
+        Transition::None
+    }
+
+    fn draw(&self, chessview: &ChessView, glyph: &mut GlyphCache,
+            _textures: &HashMap<(PieceTypes, Colors), Texture>,
+            controller: &ChessViewController, c: &Context, g: &mut GlGraphics)
+    {
+        let settings: &ChessViewSettings = &chessview.settings;
+        _draw_menu_title(chessview, "Settings", controller.settings_status.as_str(), glyph, c, g);
+
+        SettingsScreen::_coords(settings).draw(chessview, &controller.ui, glyph, c, g);
+        let animation_label: String = match settings._animation_ms
+        {
+            0 => String::from("Animation: off"),
+            ms => format!("Animation: {} ms", ms),
+        };
+        SettingsScreen::_animation(settings, &animation_label).draw(chessview, &controller.ui, glyph, c, g);
+
+        let themes = controller.settings_themes.iter()
+            .map(|name| (format!("Board: {}", name), *name == settings._theme));
+        let piece_sets = controller.settings_piece_sets.iter()
+            .map(|name| (format!("Pieces: {}", name), *name == settings._piece_set));
+        let rows: Vec<(String, bool)> = themes.chain(piece_sets).collect();
+        self.rows.draw(settings.lobby_list_rect(2), &rows, chessview, glyph, c, g);
+
+        Button::new(settings.lobby_back_button_rect(), "Back").draw(chessview, &controller.ui, glyph, c, g);
+    }
+}
+
+// The finished games of the database, newest first, narrowed down by
+// result, color, opponent and date. Clicking one replays it
+pub struct GamesScreen
+{
+    games: Vec<ArchivedGame>,
+    status: String,
+    // Indexes into `RESULT_OPTIONS` and `COLOR_OPTIONS`, 0 is any
+    result: usize,
+    color: usize,
+    opponent: TextField,
+    from: TextField,
+    to: TextField,
+    rows: ScrollList,
+}
+
+const RESULT_OPTIONS: [&str; 4] = ["any", database::RESULTS[0], database::RESULTS[1], database::RESULTS[2]];
+const COLOR_OPTIONS: [&str; 3] = ["any", "white", "black"];
+
+impl GamesScreen
+{
+    pub fn new() -> GamesScreen
+    {
+        let (games, status): (Vec<ArchivedGame>, String) = match database::load()
+        {
+            Ok(games) if games.is_empty() => (games, String::from("No finished games yet")),
+            Ok(games) => (games, String::new()),
+            Err(err) => (Vec::new(), err),
+        };
+        GamesScreen{
+            games, status, result: 0, color: 0,
+            opponent: TextField::new(MAX_OPPONENT_LEN),
+            from: TextField::new(DATE_LEN),
+            to: TextField::new(DATE_LEN),
+            rows: ScrollList::new(),
+        }
+    }
+
+    // Result and color on the first row with the opponent field, the date
+    // range on the second
+    fn _filter_rects(settings: &ChessViewSettings) -> [[f64; 4]; 5]
+    {
+        let gap: f64 = settings._lobby_row_gap;
+        let [x, y, w, h] = settings.lobby_row_rect(0);
+        let choice_w: f64 = 170.0;
+        let [x2, y2, w2, h2] = settings.lobby_row_rect(1);
+        let half: f64 = (w2 - gap) / 2.0;
+        [
+            [x, y, choice_w, h],
+            [x + choice_w + gap, y, choice_w, h],
+            [x + 2.0*(choice_w + gap), y, w - 2.0*(choice_w + gap), h],
+            [x2, y2, half, h2],
+            [x2 + half + gap, y2, half, h2],
+        ]
+    }
+
+    fn _filter(&self) -> Filter
+    {
+        Filter{
+            result: if self.result == 0 { None } else { Some(RESULT_OPTIONS[self.result]) },
+            color: match self.color { 1 => Some(Colors::White), 2 => Some(Colors::Black), _ => None },
+            opponent: self.opponent.value.clone(),
+            from: self.from.value.clone(),
+            to: self.to.value.clone(),
+        }
+    }
+
+    // Indexes of the games that pass the filters
+    fn _shown(&self) -> Vec<usize>
+    {
+        let filter: Filter = self._filter();
+        (0..self.games.len()).filter(|i| filter.matches(&self.games[*i])).collect()
+    }
+}
+
+impl Screen for GamesScreen
+{
+    fn handle_event(&mut self, controller: &mut ChessViewController, chessview: &mut ChessView,
+                    _e: &Event) -> Transition
+    {
+        let settings: &ChessViewSettings = &chessview.settings;
+        let back: Button = Button::new(settings.lobby_back_button_rect(), "Back");
+        if back.clicked(&controller.ui) || controller.ui.key == Some(Key::Escape) { return Transition::Pop; }
+
+        let [result_rect, color_rect, opponent_rect, from_rect, to_rect] = GamesScreen::_filter_rects(settings);
+        if let Some(result) = Choice::new(result_rect, "Result", &RESULT_OPTIONS, self.result).picked(&controller.ui)
+        {
+            self.result = result;
+        }
+        if let Some(color) = Choice::new(color_rect, "Played", &COLOR_OPTIONS, self.color).picked(&controller.ui)
+        {
+            self.color = color;
+        }
+        self.opponent.handle(opponent_rect, &controller.ui);
+        self.from.handle(from_rect, &controller.ui);
+        self.to.handle(to_rect, &controller.ui);
+
+        let shown: Vec<usize> = self._shown();
+        let list_rect: [f64; 4] = settings.lobby_list_rect(2);
+        let i: usize = match self.rows.handle(list_rect, shown.len(), settings, &controller.ui)
+        {
+            Some(i) => i,
+            None => return Transition::None
+        };
+
+        let archived: &ArchivedGame = &self.games[shown[i]];
+        match Game::from_pgn(&archived.pgn)
+        {
+            Ok(game) => {
+                controller.start_replay(chessview, archived.color == Some(Colors::Black));
+                Transition::Push(Box::new(ReplayScreen::new(game)))
+            },
+            Err(err) => { self.status = err; Transition::None },
+        }
+    }
+
+    fn draw(&self, chessview: &ChessView, glyph: &mut GlyphCache,
+            _textures: &HashMap<(PieceTypes, Colors), Texture>,
+            controller: &ChessViewController, c: &Context, g: &mut GlGraphics)
+    {
+        let settings: &ChessViewSettings = &chessview.settings;
+        _draw_menu_title(chessview, "Games", self.status.as_str(), glyph, c, g);
+
+        let [result_rect, color_rect, opponent_rect, from_rect, to_rect] = GamesScreen::_filter_rects(settings);
+        Choice::new(result_rect, "Result", &RESULT_OPTIONS, self.result).draw(chessview, &controller.ui, glyph, c, g);
+        Choice::new(color_rect, "Played", &COLOR_OPTIONS, self.color).draw(chessview, &controller.ui, glyph, c, g);
+        self.opponent.draw(opponent_rect, "opponent", chessview, glyph, c, g);
+        self.from.draw(from_rect, "from YYYY.MM.DD", chessview, glyph, c, g);
+        self.to.draw(to_rect, "to YYYY.MM.DD", chessview, glyph, c, g);
+
+        let rows: Vec<(String, bool)> = self._shown().into_iter().map(|i| {
+            let game: &ArchivedGame = &self.games[i];
+            (format!("{}  {} - {}  {}  {}", game.date, game.white, game.black, game.result, game.opening), false)
+        }).collect();
+        self.rows.draw(settings.lobby_list_rect(2), &rows, chessview, glyph, c, g);
+
+        Button::new(settings.lobby_back_button_rect(), "Back").draw(chessview, &controller.ui, glyph, c, g);
+    }
+}
+
+// The board, played in any of the `GameMode`s
+pub struct GameScreen;
+
+impl Screen for GameScreen
+{
+    fn update(&mut self, controller: &mut ChessViewController, chessview: &mut ChessView, covered: bool)
+    {
+        match controller.mode
+        {
+            GameMode::Online => controller.poll_mp(chessview),
+            // Not while paused
+            GameMode::Computer(_) if !covered => controller.computer_move(chessview),
+            _ => ()
+        }
+    }
+
+    fn handle_event(&mut self, controller: &mut ChessViewController, chessview: &mut ChessView,
+                    e: &Event) -> Transition
+    {
+        // Escape first clears the typed move and the cursor, then pauses
+        let typing: bool = !controller.move_input.is_empty() || controller.move_error.is_some()
+                           || controller.key_cursor.is_some();
+        if controller.ui.key == Some(Key::Escape) && !typing
+        {
+            return Transition::Push(Box::new(PauseScreen));
+        }
+
+        controller.game_event(chessview, e);
+        Transition::None
+    }
+
+    fn draw(&self, chessview: &ChessView, glyph: &mut GlyphCache,
+            textures: &HashMap<(PieceTypes, Colors), Texture>,
+            controller: &ChessViewController, c: &Context, g: &mut GlGraphics)
+    {
+        if controller.mode == GameMode::Online { chessview.draw_mp(glyph, textures, controller, c, g); }
+        else { chessview.draw_sp(glyph, textures, controller, c, g); }
+    }
+}
+
+
+// Steps through a loaded or finished game: the first, previous, next and
+// last move with the buttons or the arrow keys, autoplay and a scrubber.
+// Nothing can be played on the board
+pub struct ReplayScreen
+{
+    game: Game,
+    ply: usize,
+    playing: bool,
+    // Autoplay delay between two moves
+    step_ms: f64,
+    last_step: Instant,
+}
+
+impl ReplayScreen
+{
+    // Starts before the first move, `ChessViewController::start_replay`
+    // sets up the board
+    pub fn new(game: Game) -> ReplayScreen
+    {
+        ReplayScreen{game, ply: 0, playing: false, step_ms: DEFAULT_STEP_MS, last_step: Instant::now()}
+    }
+
+    fn _go_to(&mut self, ply: usize, controller: &mut ChessViewController, chessview: &mut ChessView)
+    {
+        self.ply = ply.min(self.game.line().len());
+        self.last_step = Instant::now();
+        controller.show_ply(chessview, &self.game, self.ply);
+    }
+
+    // "12... Nf6 (24/60)", the move that led to the position shown
+    fn _move_label(&self) -> String
+    {
+        let progress: String = format!("({}/{})", self.ply, self.game.line().len());
+        match self.ply.checked_sub(1)
+        {
+            Some(i) => format!("{}{} {} {}", i/2 + 1, if i % 2 == 0 { "." } else { "..." },
+                self.game.line()[i].san, progress),
+            None => format!("Start {}", progress),
+        }
+    }
+
+    // The scrubber on the top row of the bar, the buttons and the speed
+    // below it
+    fn _scrubber<'a>(&self, settings: &ChessViewSettings, label: &'a str) -> Slider<'a>
+    {
+        let [x, y, w, h] = settings.replay_bar_rect();
+        let row_h: f64 = (h - 3.0*REPLAY_PADDING) / 2.0;
+        Slider::new([x + REPLAY_PADDING, y + REPLAY_PADDING, w - 2.0*REPLAY_PADDING, row_h], label,
+            0.0, self.game.line().len() as f64, self.ply as f64)
+    }
+
+    fn _buttons(&self, settings: &ChessViewSettings) -> [Button<'static>; 5]
+    {
+        let [x, y, _, h] = settings.replay_bar_rect();
+        let row_h: f64 = (h - 3.0*REPLAY_PADDING) / 2.0;
+        let top: f64 = y + 2.0*REPLAY_PADDING + row_h;
+        let (step_w, play_w, gap) = (44.0, 90.0, 6.0);
+
+        let mut left: f64 = x + REPLAY_PADDING;
+        let mut button = |w: f64, label: &'static str| {
+            let rect: [f64; 4] = [left, top, w, row_h];
+            left += w + gap;
+            Button::new(rect, label)
+        };
+        [
+            button(step_w, "|<"),
+            button(step_w, "<"),
+            button(play_w, if self.playing { "Stop" } else { "Play" }),
+            button(step_w, ">"),
+            button(step_w, ">|"),
+        ]
+    }
+
+    fn _speed<'a>(&self, settings: &ChessViewSettings, label: &'a str) -> Slider<'a>
+    {
+        let [x, _, w, _] = settings.replay_bar_rect();
+        let last: [f64; 4] = self._buttons(settings)[4].rect;
+        let left: f64 = last[0] + last[2] + REPLAY_PADDING;
+        Slider::new([left, last[1], x + w - REPLAY_PADDING - left, last[3]], label,
+            MIN_STEP_MS, MAX_STEP_MS, self.step_ms)
+    }
+}
+
+impl Screen for ReplayScreen
+{
+    fn update(&mut self, controller: &mut ChessViewController, chessview: &mut ChessView, covered: bool)
+    {
+        if covered || !self.playing || self.last_step.elapsed() < Duration::from_millis(self.step_ms as u64) { return; }
+
+        if self.ply < self.game.line().len() { self._go_to(self.ply + 1, controller, chessview); }
+        else { self.playing = false; }
+    }
+
+    fn handle_event(&mut self, controller: &mut ChessViewController, chessview: &mut ChessView,
+                    _e: &Event) -> Transition
+    {
+        if controller.ui.key == Some(Key::Escape)
+        {
+            controller.leave_game(chessview);
+            return Transition::Pop;
+        }
+
+        let plies: usize = self.game.line().len();
+        let [first, prev, play, next, last] = self._buttons(&chessview.settings);
+        let scrubber: Slider = self._scrubber(&chessview.settings, "");
+        let speed: Slider = self._speed(&chessview.settings, "");
+        let ui: &UiInput = &controller.ui;
+
+        // Where to go, stepping by hand stops the autoplay
+        let mut target: Option<usize> = None;
+        if first.clicked(ui) || matches!(ui.key, Some(Key::Up) | Some(Key::Home)) { target = Some(0); }
+        if prev.clicked(ui) || ui.key == Some(Key::Left) { target = Some(self.ply.saturating_sub(1)); }
+        if next.clicked(ui) || ui.key == Some(Key::Right) { target = Some(self.ply + 1); }
+        if last.clicked(ui) || matches!(ui.key, Some(Key::Down) | Some(Key::End)) { target = Some(plies); }
+        if let Some(ply) = scrubber.dragged(ui) { target = Some(ply.round() as usize); }
+        if let Some(ms) = speed.dragged(ui)
+        {
+            // In steps of 100 ms
+            self.step_ms = (ms / 100.0).round() * 100.0;
+        }
+
+        if play.clicked(ui) || ui.key == Some(Key::Space)
+        {
+            self.playing = !self.playing;
+            // From the start again once it's over
+            let ply: usize = if self.playing && self.ply >= plies { 0 } else { self.ply };
+            self._go_to(ply, controller, chessview);
+        }
+        else if let Some(ply) = target
+        {
+            self.playing = false;
+            self._go_to(ply, controller, chessview);
+        }
+        Transition::None
+    }
+
+    fn draw(&self, chessview: &ChessView, glyph: &mut GlyphCache,
+            textures: &HashMap<(PieceTypes, Colors), Texture>,
+            controller: &ChessViewController, c: &Context, g: &mut GlGraphics)
+    {
+        chessview.draw_sp(glyph, textures, controller, c, g);
+
+        let settings: &ChessViewSettings = &chessview.settings;
+        Rectangle::new(settings._replay_bar_col).draw(settings.replay_bar_rect(), &c.draw_state, c.transform, g);
+
+        let move_label: String = self._move_label();
+        self._scrubber(settings, &move_label).draw(chessview, &controller.ui, glyph, c, g);
+        for button in self._buttons(settings)
+        {
+            button.draw(chessview, &controller.ui, glyph, c, g);
+        }
+        let speed_label: String = format!("{:.1} s a move", self.step_ms / 1000.0);
+        self._speed(settings, &speed_label).draw(chessview, &controller.ui, glyph, c, g);
+    }
+
+    fn needs_polling(&self) -> bool { self.playing }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PuzzleState
+{
+    Solving,
+    Solved,
+    // The rest of the solution is played out
+    Failed,
+}
+
+// Solves the puzzles of a CSV file: the opponent's moves are played, the
+// solver has to find the others, by clicking or typing them. The engine
+// can't be set up from a FEN, so the puzzle is played on the squares of a
+// diagram and only the moves of the solution count. The rating and the
+// streak are kept between runs
+pub struct PuzzleScreen
+{
+    puzzles: Vec<Puzzle>,
+    stats: PuzzleStats,
+    // Index into `puzzles`, `None` when there's none to show
+    current: Option<usize>,
+    squares: Squares,
+    white_to_move: bool,
+    // Moves of the solution on the board, the opponent's first one included
+    played: usize,
+    state: PuzzleState,
+    status: String,
+    // When the opponent's next move is due
+    reply_at: Option<Instant>,
+}
+
+impl PuzzleScreen
+{
+    // Leaves the game on the board and shows the first puzzle
+    pub fn new(file: &str, controller: &mut ChessViewController, chessview: &mut ChessView) -> PuzzleScreen
+    {
+        controller.leave_game(chessview);
+        let mut screen: PuzzleScreen = PuzzleScreen{
+            puzzles: Vec::new(), stats: PuzzleStats::load(), current: None, squares: [None; 64],
+            white_to_move: true, played: 0, state: PuzzleState::Solved, status: String::new(), reply_at: None,
+        };
+
+        match puzzle::load(file)
+        {
+            Ok(puzzles) if puzzles.is_empty() => screen.status = format!("No puzzles in {}", file),
+            Ok(puzzles) => {
+                screen.puzzles = puzzles;
+                screen._next(controller, chessview);
+            },
+            Err(err) => screen.status = err,
+        }
+        screen
+    }
+
+    // Set up the next puzzle, its first move follows after a moment
+    fn _next(&mut self, controller: &mut ChessViewController, chessview: &mut ChessView)
+    {
+        controller.selected = 255;
+        controller.move_input.clear();
+        controller.move_error = None;
+
+        // Puzzles with a broken FEN count as played
+        for _ in 0..self.puzzles.len()
+        {
+            let i: usize = match self.stats.next(&self.puzzles) { Some(i) => i, None => break };
+            let puzzle: &Puzzle = &self.puzzles[i];
+            match position::from_fen(&puzzle.fen)
+            {
+                Ok((squares, white_to_move)) => {
+                    self.current = Some(i);
+                    self.squares = squares;
+                    self.white_to_move = white_to_move;
+                    self.played = 0;
+                    self.state = PuzzleState::Solving;
+                    self.reply_at = Some(Instant::now() + Duration::from_millis(PUZZLE_REPLY_MS));
+                    self.status = format!("Puzzle {} ({}), find the best move for {}", puzzle.id, puzzle.rating,
+                        if white_to_move { "black" } else { "white" });
+
+                    // The solver plays from the bottom
+                    chessview.flipped = white_to_move;
+                    chessview.game = Game::new();
+                    chessview.position = Some(squares);
+                    return;
+                },
+                Err(err) => {
+                    eprintln!("Puzzle {}: {}", puzzle.id, err);
+                    self.stats.played.push(puzzle.id.clone());
+                }
+            }
+        }
+
+        self.current = None;
+        self.status = String::from("None of the puzzles can be set up");
+    }
+
+    // Play a move of the solution on the board, animated and recorded for
+    // the last move highlight
+    fn _play(&mut self, chessview: &mut ChessView, (from, to, promotion): PuzzleMove)
+    {
+        let after: Squares = position::apply_move(&self.squares, from, to, promotion);
+        let san: String = position::san(&self.squares, &after, from, to, promotion);
+        chessview.game.play(PlayedMove{from, to, promotion, san});
+        chessview.animation = Animation::between(&self.squares, &after,
+            Duration::from_millis(chessview.settings._animation_ms as u64));
+        chessview.position = Some(after);
+
+        self.squares = after;
+        self.white_to_move = !self.white_to_move;
+        self.played += 1;
+    }
+
+    // A move of the solver, `promotion` only when it was typed. Anything
+    // but the move of the solution fails the puzzle, except that any mate
+    // does on the last move
+    fn _attempt(&mut self, chessview: &mut ChessView, from: u32, to: u32, promotion: Option<PieceTypes>)
+    {
+        let i: usize = match self.current { Some(i) => i, None => return };
+        let expected: PuzzleMove = self.puzzles[i].moves[self.played];
+        let last: bool = self.played + 1 == self.puzzles[i].moves.len();
+
+        // Another mate than the solution's, a click promotes to a queen
+        let mv: PuzzleMove = (from, to, if !position::is_promotion(&self.squares, from, to) { None }
+                                        else { promotion.or(Some(PieceTypes::Queen)) });
+        let last_move: Option<(u32, u32)> = chessview.game.last_move().map(|prev| (prev.from, prev.to));
+
+        let played: Option<PuzzleMove> =
+            if (from, to) == (expected.0, expected.1) && promotion.is_none_or(|piece| Some(piece) == expected.2)
+            {
+                Some(expected)
+            }
+            else if last && puzzle::mates(&self.squares, self.white_to_move, last_move, mv)
+            {
+                Some(mv)
+            }
+            else { None };
+
+        if let Some(mv) = played
+        {
+            self._play(chessview, mv);
+            if self.played == self.puzzles[i].moves.len()
+            {
+                let change: f64 = self._finish(true);
+                self.status = format!("Solved! Rating {:+.0}", change);
+            }
+            else
+            {
+                self.status = String::from("Right, keep going");
+                self.reply_at = Some(Instant::now() + Duration::from_millis(PUZZLE_REPLY_MS));
+            }
+        }
+        else
+        {
+            let (from, to, promotion) = expected;
+            let san: String = position::san(&self.squares,
+                &position::apply_move(&self.squares, from, to, promotion), from, to, promotion);
+            let change: f64 = self._finish(false);
+            self.status = format!("Wrong, the move was {}. Rating {:+.0}", san, change);
+            // Then the solution is played out
+            self.reply_at = Some(Instant::now() + Duration::from_millis(PUZZLE_REPLY_MS));
+        }
+    }
+
+    // Count the puzzle and save the stats, returns the rating change
+    fn _finish(&mut self, solved: bool) -> f64
+    {
+        let i: usize = match self.current { Some(i) => i, None => return 0.0 };
+        let change: f64 = self.stats.record(&self.puzzles[i], solved);
+        if let Err(err) = self.stats.save()
+        {
+            eprintln!("Could not save the puzzle rating: {}", err);
+        }
+        self.state = if solved { PuzzleState::Solved } else { PuzzleState::Failed };
+        change
+    }
+
+    // Select one of the solver's pieces, or move the selected one
+    fn _click(&mut self, controller: &mut ChessViewController, chessview: &mut ChessView, block: u8)
+    {
+        let square: u32 = position::block_to_square(block);
+        let color: Colors = if self.white_to_move { Colors::White } else { Colors::Black };
+        if matches!(self.squares[square as usize], Some((_, c)) if c == color)
+        {
+            controller.selected = if controller.selected == block { 255 } else { block };
+            return;
+        }
+        if controller.selected == 255 { return; }
+
+        let from: u32 = position::block_to_square(controller.selected);
+        controller.selected = 255;
+        self._attempt(chessview, from, square, None);
+    }
+
+    fn _submit(&mut self, controller: &mut ChessViewController, chessview: &mut ChessView)
+    {
+        let text: String = std::mem::take(&mut controller.move_input);
+        let candidates: Vec<PuzzleMove> = match position::parse_move(&self.squares, self.white_to_move, &text)
+        {
+            Ok(candidates) => candidates,
+            Err(err) => { controller.move_error = Some(err); return; }
+        };
+        let (from, to, promotion) = match candidates.as_slice()
+        {
+            [mv] => *mv,
+            [] => { controller.move_error = Some(format!("{} is not a legal move", text)); return; },
+            _ => { controller.move_error = Some(format!("{} is ambiguous", text)); return; },
+        };
+        controller.selected = 255;
+        self._attempt(chessview, from, to, promotion);
+    }
+
+    fn _next_button(&self, settings: &ChessViewSettings) -> Button<'static>
+    {
+        let [x, y, w, h] = settings.puzzle_bar_rect();
+        let rect: [f64; 4] = [x + w - PUZZLE_BUTTON_WIDTH - PUZZLE_PADDING, y + PUZZLE_PADDING,
+                              PUZZLE_BUTTON_WIDTH, h - 2.0*PUZZLE_PADDING];
+        // Skipping counts as failing
+        Button::new(rect, if self.state == PuzzleState::Solving { "Skip" } else { "Next" })
+    }
+}
+
+impl Screen for PuzzleScreen
+{
+    fn update(&mut self, _controller: &mut ChessViewController, chessview: &mut ChessView, covered: bool)
+    {
+        let (i, due) = match (self.current, self.reply_at) { (Some(i), Some(due)) => (i, due), _ => return };
+        if covered || Instant::now() < due || chessview.is_animating() { return; }
+
+        self.reply_at = None;
+        let moves: usize = self.puzzles[i].moves.len();
+        if self.played >= moves { return; }
+        self._play(chessview, self.puzzles[i].moves[self.played]);
+
+        match self.state
+        {
+            // A line that ends on the opponent's move is solved by then
+            PuzzleState::Solving if self.played == moves => {
+                let change: f64 = self._finish(true);
+                self.status = format!("Solved! Rating {:+.0}", change);
+            },
+            PuzzleState::Failed if self.played < moves => {
+                self.reply_at = Some(Instant::now() + Duration::from_millis(PUZZLE_REPLY_MS));
+            },
+            _ => (),
+        }
+    }
+
+    fn handle_event(&mut self, controller: &mut ChessViewController, chessview: &mut ChessView,
+                    _e: &Event) -> Transition
+    {
+        let key: Option<Key> = controller.ui.key;
+        let typing: bool = !controller.move_input.is_empty() || controller.move_error.is_some();
+        if key == Some(Key::Escape)
+        {
+            if typing
+            {
+                controller.move_input.clear();
+                controller.move_error = None;
+                return Transition::None;
+            }
+            controller.leave_game(chessview);
+            return Transition::Pop;
+        }
+
+        let finished: bool = self.state != PuzzleState::Solving;
+        if self.current.is_some() && (self._next_button(&chessview.settings).clicked(&controller.ui)
+            || (finished && matches!(key, Some(Key::Return) | Some(Key::Space))))
+        {
+            if !finished { self._finish(false); }
+            self._next(controller, chessview);
+            return Transition::None;
+        }
+
+        // The solver's turn
+        if finished || self.reply_at.is_some() || self.current.is_none() { return Transition::None; }
+
+        if let Some(text) = controller.ui.text.clone() { controller.type_move(&text); }
+        match key
+        {
+            Some(Key::Backspace) => {
+                controller.move_input.pop();
+                controller.move_error = None;
+            },
+            Some(Key::Return) if !controller.move_input.is_empty() => self._submit(controller, chessview),
+            _ => ()
+        }
+
+        if controller.ui.clicked
+        {
+            if let Some(block) = controller.block_under_cursor(chessview)
+            {
+                self._click(controller, chessview, block);
+            }
+        }
+        Transition::None
+    }
+
+    fn draw(&self, chessview: &ChessView, glyph: &mut GlyphCache,
+            textures: &HashMap<(PieceTypes, Colors), Texture>,
+            controller: &ChessViewController, c: &Context, g: &mut GlGraphics)
+    {
+        chessview.draw_sp(glyph, textures, controller, c, g);
+
+        let settings: &ChessViewSettings = &chessview.settings;
+        let [x, y, _, _] = settings.puzzle_bar_rect();
+        Rectangle::new(settings._puzzle_bar_col).draw(settings.puzzle_bar_rect(), &c.draw_state, c.transform, g);
+
+        let font_size: u32 = settings._chat_font_size;
+        let line_height: f64 = font_size as f64 + 6.0;
+        let stats: String = format!("Rating {:.0}   Streak {} (best {})   Solved {} of {}",
+            self.stats.rating, self.stats.streak, self.stats.best_streak,
+            self.stats.solved, self.stats.solved + self.stats.failed);
+        for (i, line) in [self.status.as_str(), stats.as_str()].into_iter().enumerate()
+        {
+            chessview.draw_text(Text::new_color(settings._chat_text_col, font_size), line,
+                [x + PUZZLE_PADDING, y + PUZZLE_PADDING + (i + 1) as f64 * line_height], glyph, c, g);
+        }
+
+        if self.current.is_some() { self._next_button(settings).draw(chessview, &controller.ui, glyph, c, g); }
+    }
+
+    fn needs_polling(&self) -> bool { self.reply_at.is_some() }
+}
+
+// Drawn over the game: resume it, or leave it for the intro. An MP game
+// keeps running below. A game that is over can be replayed
+pub struct PauseScreen;
+
+fn _pause_buttons(settings: &ChessViewSettings) -> [Button<'static>; 3]
+{
+    let [resume, leave, replay] = settings.pause_button_rects();
+    [Button::new(resume, "Resume"), Button::new(leave, "Leave"), Button::new(replay, "Replay")]
+}
+
+impl Screen for PauseScreen
+{
+    fn handle_event(&mut self, controller: &mut ChessViewController, chessview: &mut ChessView,
+                    _e: &Event) -> Transition
+    {
+        let [resume, leave, replay] = _pause_buttons(&chessview.settings);
+        if resume.clicked(&controller.ui) || controller.ui.key == Some(Key::Escape) { return Transition::Pop; }
+        if leave.clicked(&controller.ui)
+        {
+            controller.leave_game(chessview);
+            return Transition::Reset(vec![Box::new(IntroScreen::new())]);
+        }
+        if chessview.game.result != "*" && replay.clicked(&controller.ui)
+        {
+            let game: Game = chessview.game.clone();
+            let flipped: bool = chessview.flipped;
+            controller.start_replay(chessview, flipped);
+            return Transition::Reset(vec![Box::new(IntroScreen::new()), Box::new(ReplayScreen::new(game))]);
+        }
+        Transition::None
+    }
+
+    fn draw(&self, chessview: &ChessView, glyph: &mut GlyphCache,
+            _textures: &HashMap<(PieceTypes, Colors), Texture>,
+            controller: &ChessViewController, c: &Context, g: &mut GlGraphics)
+    {
+        Rectangle::new(chessview.settings._overlay_col).draw(
+            chessview.layout.visible_rect(),
+            &c.draw_state,
+            c.transform,
+            g
+        );
+        chessview.draw_text(Text::new_color([1.0; 4], 50), "Paused",
+            chessview.settings._intro_text_coords, glyph, c, g);
+
+        let [resume, leave, replay] = _pause_buttons(&chessview.settings);
+        for button in [resume, leave]
+        {
+            button.draw(chessview, &controller.ui, glyph, c, g);
+        }
+        if chessview.game.result != "*" { replay.draw(chessview, &controller.ui, glyph, c, g); }
+    }
+
+    fn is_overlay(&self) -> bool { true }
+}
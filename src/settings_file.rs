@@ -0,0 +1,436 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chess::colors::Colors;
+use chess::piece_types::PieceTypes;
+use graphics::types::Color;
+use json::JsonValue;
+
+use crate::ChessViewSettings;
+
+// Board themes, one json file per theme with all of `THEME_KEYS`
+pub const THEMES_DIR: &str = "assets/themes";
+
+// Piece sets, one directory per set with w_king.png, b_pawn.png, ..
+pub const PIECES_DIR: &str = "assets/pieces";
+
+// Used for any piece image missing from the chosen set
+pub const DEFAULT_PIECE_SET: &str = "classic";
+
+const PIECE_NAMES: [(PieceTypes, &str); 6] = [
+    (PieceTypes::King, "king"),
+    (PieceTypes::Queen, "queen"),
+    (PieceTypes::Rook, "rook"),
+    (PieceTypes::Knight, "knight"),
+    (PieceTypes::Bishop, "bishop"),
+    (PieceTypes::Pawn, "pawn"),
+];
+
+// What was picked on the settings screen: {"theme": "green", "piece-set": "classic",
+// "coords": true, "animation-ms": 180}
+pub const PREFERENCES: &str = "config/preferences.json";
+
+// Settings screen options that are stored as they are in the settings file
+const PREFERENCE_KEYS: [&str; 2] = ["coords", "animation-ms"];
+
+const THEME_KEYS: [&str; 5] = ["light-block-col", "dark-block-col", "from-block-col", "border-col", "bg-col"];
+
+// Loading `ChessViewSettings` from a json file such as config/view.json.
+// Every key is optional and overrides the default from `ChessViewSettings::new`,
+// the key names are the field names without the leading underscore and with
+// dashes, e.g. `_from_block_col` -> "from-block-col". Colors are [r, g, b, a]
+// arrays (0.0 - 1.0, alpha optional) or "#rrggbb" / "#rrggbbaa" strings
+impl ChessViewSettings
+{
+    // The defaults merged with the file at `path`. A missing file just
+    // means the defaults are used
+    pub fn from_file(path: &str) -> Result<ChessViewSettings, String>
+    {
+        let mut settings: ChessViewSettings = ChessViewSettings::new();
+
+        let text: String = match fs::read_to_string(path)
+        {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(settings),
+            Err(err) => return Err(format!("cannot read {}: {}", path, err)),
+        };
+
+        let config: JsonValue = json::parse(&text)
+            .map_err(|err| format!("{} is not valid json: {}", path, err))?;
+
+        settings.merge_json(&config).map_err(|err| format!("{}: {}", path, err))?;
+        Ok(settings)
+    }
+
+    // Override the settings with the keys found in `config`
+    pub fn merge_json(&mut self, config: &JsonValue) -> Result<(), String>
+    {
+        if !config.is_object()
+        {
+            return Err(String::from("the settings must be a json object"));
+        }
+
+        for (key, value) in config.entries()
+        {
+            match key
+            {
+                "pos" => self._pos = _vec2(key, value)?,
+                "size" => self._size = _positive(key, value)?,
+                "font" => self._font = _file(key, value)?,
+                "bg-col" => self._bg_col = _color(key, value)?,
+                "light-block-col" => self._light_block_col = _color(key, value)?,
+                "dark-block-col" => self._dark_block_col = _color(key, value)?,
+                "border-col" => self._border_col = _color(key, value)?,
+                "border-radius" => self._border_radius = _non_negative(key, value)?,
+                "block-radius" => self._block_radius = _non_negative(key, value)?,
+                "from-block-col" => self._from_block_col = _color(key, value)?,
+                "coords" => self._coords = _bool(key, value)?,
+                "coords-font-size" => self._coords_font_size = _font_size(key, value)?,
+                "coords-col" => self._coords_col = _color(key, value)?,
+                "captured-height" => self._captured_height = _non_negative(key, value)?,
+                "captured-text-col" => self._captured_text_col = _color(key, value)?,
+                "animation-ms" => self._animation_ms = _whole_number(key, value)?,
+                "last-move-col" => self._last_move_col = _color(key, value)?,
+                "key-cursor-col" => self._key_cursor_col = _color(key, value)?,
+                "move-error-col" => self._move_error_col = _color(key, value)?,
+                "overlay-col" => self._overlay_col = _color(key, value)?,
+                "button-hover-col" => self._button_hover_col = _color(key, value)?,
+                "button-pressed-col" => self._button_pressed_col = _color(key, value)?,
+
+                "intro-text-coords" => self._intro_text_coords = _vec2(key, value)?,
+                "intro-button-col" => self._intro_button_col = _color(key, value)?,
+                "intro-button-size" => self._intro_button_size = _size2(key, value)?,
+                "intro-sp-button-pos" => self._intro_sp_button_pos = _vec2(key, value)?,
+                "intro-mp-button-pos" => self._intro_mp_button_pos = _vec2(key, value)?,
+                "intro-host-button-pos" => self._intro_host_button_pos = _vec2(key, value)?,
+                "intro-settings-button-pos" => self._intro_settings_button_pos = _vec2(key, value)?,
+                "intro-continue-button-pos" => self._intro_continue_button_pos = _vec2(key, value)?,
+                "intro-games-button-pos" => self._intro_games_button_pos = _vec2(key, value)?,
+                "intro-puzzles-button-pos" => self._intro_puzzles_button_pos = _vec2(key, value)?,
+
+                "lobby-title-coords" => self._lobby_title_coords = _vec2(key, value)?,
+                "lobby-row-pos" => self._lobby_row_pos = _vec2(key, value)?,
+                "lobby-row-size" => self._lobby_row_size = _size2(key, value)?,
+                "lobby-row-gap" => self._lobby_row_gap = _non_negative(key, value)?,
+                "lobby-row-col" => self._lobby_row_col = _color(key, value)?,
+                "lobby-back-button-pos" => self._lobby_back_button_pos = _vec2(key, value)?,
+
+                "chat-height" => self._chat_height = _positive(key, value)?,
+                "chat-font-size" => self._chat_font_size = _font_size(key, value)?,
+                "chat-bg-col" => self._chat_bg_col = _color(key, value)?,
+                "chat-input-col" => self._chat_input_col = _color(key, value)?,
+                "chat-text-col" => self._chat_text_col = _color(key, value)?,
+
+                "replay-bar-height" => self._replay_bar_height = _positive(key, value)?,
+                "replay-bar-col" => self._replay_bar_col = _color(key, value)?,
+
+                "puzzle-bar-height" => self._puzzle_bar_height = _positive(key, value)?,
+                "puzzle-bar-col" => self._puzzle_bar_col = _color(key, value)?,
+
+                _ => return Err(format!("unknown setting \"{}\"", key))
+            }
+        }
+
+        // Checks that involve more than one key
+        if self._chat_height > self._size
+        {
+            return Err(format!("\"chat-height\" ({}) can't be larger than \"size\" ({})",
+                self._chat_height, self._size));
+        }
+        if self._replay_bar_height > self._size
+        {
+            return Err(format!("\"replay-bar-height\" ({}) can't be larger than \"size\" ({})",
+                self._replay_bar_height, self._size));
+        }
+        if self._puzzle_bar_height > self._size
+        {
+            return Err(format!("\"puzzle-bar-height\" ({}) can't be larger than \"size\" ({})",
+                self._puzzle_bar_height, self._size));
+        }
+
+        Ok(())
+    }
+
+    // `from_file` followed by the theme and piece set from the preferences
+    // file, which win over the colors of the settings file
+    pub fn load(path: &str, preferences: &str) -> Result<ChessViewSettings, String>
+    {
+        let mut settings: ChessViewSettings = ChessViewSettings::from_file(path)?;
+
+        let prefs: JsonValue = match fs::read_to_string(preferences)
+        {
+            Ok(text) => json::parse(&text).map_err(|err| format!("{} is not valid json: {}", preferences, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(settings),
+            Err(err) => return Err(format!("cannot read {}: {}", preferences, err)),
+        };
+
+        if let Some(theme) = prefs["theme"].as_str()
+        {
+            settings.apply_theme(theme).map_err(|err| format!("{}: {}", preferences, err))?;
+        }
+        if let Some(piece_set) = prefs["piece-set"].as_str()
+        {
+            if !list_piece_sets().iter().any(|name| name == piece_set)
+            {
+                return Err(format!("{}: no piece set \"{}\" in {}", preferences, piece_set, PIECES_DIR));
+            }
+            settings._piece_set = piece_set.to_string();
+        }
+
+        let mut options: JsonValue = JsonValue::new_object();
+        for key in PREFERENCE_KEYS.iter().filter(|key| prefs.has_key(key))
+        {
+            options[*key] = prefs[*key].clone();
+        }
+        settings.merge_json(&options).map_err(|err| format!("{}: {}", preferences, err))?;
+
+        Ok(settings)
+    }
+
+    // Use the colors of the theme `name` from `THEMES_DIR`
+    pub fn apply_theme(&mut self, name: &str) -> Result<(), String>
+    {
+        let path: PathBuf = Path::new(THEMES_DIR).join(format!("{}.json", name));
+        let text: String = fs::read_to_string(&path)
+            .map_err(|err| format!("cannot read theme {}: {}", path.display(), err))?;
+        let theme: JsonValue = json::parse(&text)
+            .map_err(|err| format!("theme {} is not valid json: {}", path.display(), err))?;
+
+        for (key, _) in theme.entries()
+        {
+            if !THEME_KEYS.contains(&key)
+            {
+                return Err(format!("theme {}: \"{}\" is not a theme color", path.display(), key));
+            }
+        }
+        // Every theme sets all colors, so switching themes never leaves
+        // colors of the previous one behind
+        for key in THEME_KEYS
+        {
+            if !theme.has_key(key)
+            {
+                return Err(format!("theme {}: missing \"{}\"", path.display(), key));
+            }
+        }
+
+        // Applied to a copy so a bad color doesn't leave a half applied theme
+        let mut themed: ChessViewSettings = self.clone();
+        themed.merge_json(&theme).map_err(|err| format!("theme {}: {}", path.display(), err))?;
+        themed._theme = name.to_string();
+        *self = themed;
+        Ok(())
+    }
+
+    // Remember the chosen theme, piece set and options for the next start
+    pub fn save_preferences(&self, preferences: &str) -> Result<(), String>
+    {
+        let mut prefs: JsonValue = json::object!{
+            "piece-set": self._piece_set.as_str(),
+            "coords": self._coords,
+            "animation-ms": self._animation_ms
+        };
+        if !self._theme.is_empty()
+        {
+            prefs["theme"] = self._theme.as_str().into();
+        }
+
+        fs::write(preferences, prefs.pretty(4))
+            .map_err(|err| format!("cannot write {}: {}", preferences, err))
+    }
+}
+
+// Names of the themes in `THEMES_DIR`, sorted
+pub fn list_themes() -> Vec<String>
+{
+    _list_dir(THEMES_DIR, |path| path.is_file() && path.extension().is_some_and(|ext| ext == "json"))
+}
+
+// Names of the piece sets in `PIECES_DIR`, sorted
+pub fn list_piece_sets() -> Vec<String>
+{
+    _list_dir(PIECES_DIR, |path| path.is_dir())
+}
+
+// Load the piece images (w_king.png, b_pawn.png, ..) of a set with `load`,
+// which makes a texture of the drawing back-end out of an image file.
+// Images missing from the set are taken from the default set, pieces without
+// any image are drawn as letters by `ChessView`
+pub fn load_piece_set<T>(piece_set: &str, load: impl Fn(&Path) -> Result<T, String>)
+    -> HashMap<(PieceTypes, Colors), T>
+{
+    let mut textures: HashMap<(PieceTypes, Colors), T> = HashMap::new();
+
+    for (color, prefix) in [(Colors::White, "w"), (Colors::Black, "b")]
+    {
+        for (piece_type, name) in PIECE_NAMES
+        {
+            let file: String = format!("{}_{}.png", prefix, name);
+            // The default set only for images the chosen one doesn't have
+            let mut sets: Vec<&str> = vec![piece_set];
+            if piece_set != DEFAULT_PIECE_SET { sets.push(DEFAULT_PIECE_SET); }
+
+            for set in sets
+            {
+                let path: PathBuf = Path::new(PIECES_DIR).join(set).join(&file);
+                if !path.is_file() { continue; }
+                match load(&path)
+                {
+                    Ok(texture) => {
+                        textures.insert((piece_type, color), texture);
+                        break;
+                    },
+                    Err(err) => eprintln!("Could not load {}: {}", path.display(), err),
+                }
+            }
+        }
+    }
+
+    textures
+}
+
+fn _list_dir(dir: &str, keep: fn(&Path) -> bool) -> Vec<String>
+{
+    let mut names: Vec<String> = match fs::read_dir(dir)
+    {
+        Ok(entries) => entries.flatten()
+            .map(|entry| entry.path())
+            .filter(|path| keep(path))
+            .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    names.sort();
+    names
+}
+
+fn _number(key: &str, value: &JsonValue) -> Result<f64, String>
+{
+    value.as_f64().ok_or(format!("\"{}\" must be a number, got {}", key, value.dump()))
+}
+
+fn _bool(key: &str, value: &JsonValue) -> Result<bool, String>
+{
+    value.as_bool().ok_or(format!("\"{}\" must be true or false, got {}", key, value.dump()))
+}
+
+fn _non_negative(key: &str, value: &JsonValue) -> Result<f64, String>
+{
+    let number: f64 = _number(key, value)?;
+    if number < 0.0 { return Err(format!("\"{}\" can't be negative, got {}", key, number)); }
+    Ok(number)
+}
+
+fn _positive(key: &str, value: &JsonValue) -> Result<f64, String>
+{
+    let number: f64 = _number(key, value)?;
+    if number <= 0.0 { return Err(format!("\"{}\" must be larger than 0, got {}", key, number)); }
+    Ok(number)
+}
+
+fn _whole_number(key: &str, value: &JsonValue) -> Result<u32, String>
+{
+    value.as_u32().ok_or(format!("\"{}\" must be a whole number, got {}", key, value.dump()))
+}
+
+fn _font_size(key: &str, value: &JsonValue) -> Result<u32, String>
+{
+    match value.as_u32()
+    {
+        Some(size) if size > 0 => Ok(size),
+        _ => Err(format!("\"{}\" must be a positive whole number, got {}", key, value.dump()))
+    }
+}
+
+// [x, y]
+fn _vec2(key: &str, value: &JsonValue) -> Result<[f64; 2], String>
+{
+    if !value.is_array() || value.len() != 2
+    {
+        return Err(format!("\"{}\" must be an [x, y] array, got {}", key, value.dump()));
+    }
+    Ok([_number(key, &value[0])?, _number(key, &value[1])?])
+}
+
+// [width, height], both larger than 0
+fn _size2(key: &str, value: &JsonValue) -> Result<[f64; 2], String>
+{
+    let size: [f64; 2] = _vec2(key, value)?;
+    if size[0] <= 0.0 || size[1] <= 0.0
+    {
+        return Err(format!("\"{}\" must have a positive width and height, got {}", key, value.dump()));
+    }
+    Ok(size)
+}
+
+fn _file(key: &str, value: &JsonValue) -> Result<String, String>
+{
+    let path: &str = value.as_str().ok_or(format!("\"{}\" must be a path", key))?;
+    if !Path::new(path).is_file()
+    {
+        return Err(format!("\"{}\": no such file {}", key, path));
+    }
+    Ok(path.to_string())
+}
+
+fn _color(key: &str, value: &JsonValue) -> Result<Color, String>
+{
+    let err = || format!("\"{}\" must be an [r, g, b(, a)] array with values from 0 to 1 \
+                          or a \"#rrggbb(aa)\" string, got {}", key, value.dump());
+
+    if let Some(hex) = value.as_str()
+    {
+        let hex: &str = hex.strip_prefix('#').ok_or_else(err)?;
+        if (hex.len() != 6 && hex.len() != 8) || !hex.is_ascii() { return Err(err()); }
+
+        let mut color: Color = [1.0; 4];
+        for (i, channel) in color.iter_mut().enumerate().take(hex.len() / 2)
+        {
+            *channel = u8::from_str_radix(&hex[2*i..2*i+2], 16).map_err(|_| err())? as f32 / 255.0;
+        }
+        return Ok(color);
+    }
+
+    if !value.is_array() || (value.len() != 3 && value.len() != 4) { return Err(err()); }
+
+    let mut color: Color = [1.0; 4];
+    for (i, channel) in value.members().enumerate()
+    {
+        let channel_value: f64 = channel.as_f64().ok_or_else(err)?;
+        if !(0.0..=1.0).contains(&channel_value) { return Err(err()); }
+        color[i] = channel_value as f32;
+    }
+    Ok(color)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::cell::RefCell;
+
+    // The paths `load_piece_set` loads for `piece_set`
+    fn _loaded(piece_set: &str) -> Vec<PathBuf>
+    {
+        let loaded: RefCell<Vec<PathBuf>> = RefCell::new(Vec::new());
+        let textures: HashMap<(PieceTypes, Colors), ()> = load_piece_set(piece_set, |path| {
+            loaded.borrow_mut().push(path.to_path_buf());
+            Ok(())
+        });
+        assert_eq!(textures.len(), 12);
+        loaded.into_inner()
+    }
+
+    #[test]
+    fn each_piece_is_loaded_once()
+    {
+        let default_dir: PathBuf = Path::new(PIECES_DIR).join(DEFAULT_PIECE_SET);
+        for piece_set in [DEFAULT_PIECE_SET, "no-such-set"]
+        {
+            let loaded: Vec<PathBuf> = _loaded(piece_set);
+            assert_eq!(loaded.len(), 12);
+            assert!(loaded.iter().all(|path| path.starts_with(&default_dir)));
+        }
+    }
+}
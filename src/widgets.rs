@@ -0,0 +1,418 @@
+use graphics::types::Color;
+use graphics::{Context, Graphics, Rectangle, Text};
+use graphics::character::CharacterCache;
+use piston::{GenericEvent, Button as InputButton, Key, MouseButton};
+
+use crate::{ChessView, ChessViewSettings};
+
+// Menu widgets. Buttons, checkboxes, choices and sliders hold nothing between
+// events, a screen makes them again from the layout whenever it handles an
+// event or draws, so both always agree on where they are. Text fields and
+// scroll lists keep their contents and are stored in the screen
+
+const BUTTON_FONT_SIZE: u32 = 30;
+const ROW_FONT_SIZE: u32 = 18;
+const ROW_PADDING: f64 = 10.0;
+const SCROLLBAR_WIDTH: f64 = 6.0;
+
+// The mouse and the keyboard as the widgets see them, updated by the
+// controller on every event. Positions are in view coordinates
+#[derive(Clone, Default)]
+pub struct UiInput
+{
+    pub cursor: [f64; 2],
+    // The left button is held, and where it went down
+    pub mouse_down: bool,
+    pub drag_start: Option<[f64; 2]>,
+    // Only set during the event they happened in
+    pub clicked: bool,
+    pub released: bool,
+    pub scroll: f64,
+    pub text: Option<String>,
+    pub key: Option<Key>,
+}
+
+impl UiInput
+{
+    pub fn begin<E: GenericEvent>(&mut self, e: &E, to_view: impl Fn([f64; 2]) -> [f64; 2])
+    {
+        // Kept during the release event, so a slider sees where it ended
+        if !self.mouse_down { self.drag_start = None; }
+
+        if let Some(cursor) = e.mouse_cursor_args()
+        {
+            self.cursor = to_view(cursor);
+        }
+
+        self.clicked = matches!(e.press_args(), Some(InputButton::Mouse(MouseButton::Left)));
+        self.released = matches!(e.release_args(), Some(InputButton::Mouse(MouseButton::Left)));
+        if self.clicked
+        {
+            self.mouse_down = true;
+            self.drag_start = Some(self.cursor);
+        }
+        if self.released { self.mouse_down = false; }
+
+        self.scroll = e.mouse_scroll_args().map_or(0.0, |scroll| scroll[1]);
+        self.text = e.text_args();
+        self.key = match e.press_args() { Some(InputButton::Keyboard(key)) => Some(key), _ => None };
+    }
+
+    pub fn hovers(&self, rect: [f64; 4]) -> bool
+    {
+        _inside(self.cursor, rect)
+    }
+
+    // The left button went down inside `rect` and wasn't released before
+    // this event
+    pub fn drags(&self, rect: [f64; 4]) -> bool
+    {
+        self.drag_start.is_some_and(|start| _inside(start, rect))
+    }
+}
+
+pub struct Button<'a>
+{
+    pub rect: [f64; 4],
+    pub label: &'a str,
+}
+
+impl<'a> Button<'a>
+{
+    pub fn new(rect: [f64; 4], label: &'a str) -> Button<'a>
+    {
+        Button{rect, label}
+    }
+
+    pub fn clicked(&self, ui: &UiInput) -> bool
+    {
+        ui.clicked && ui.hovers(self.rect)
+    }
+
+    pub fn draw<G: Graphics, C: CharacterCache<Texture = G::Texture>>(&self, view: &ChessView,
+        ui: &UiInput, glyph: &mut C, c: &Context, g: &mut G)
+    {
+        let settings: &ChessViewSettings = &view.settings;
+        let col: Color = if ui.mouse_down && ui.drags(self.rect) && ui.hovers(self.rect) { settings._button_pressed_col }
+                         else if ui.hovers(self.rect) { settings._button_hover_col }
+                         else { settings._intro_button_col };
+
+        Rectangle::new(col).draw(self.rect, &c.draw_state, c.transform, g);
+        draw_label(view, self.label, BUTTON_FONT_SIZE, [1.0; 4], self.rect, true, glyph, c, g);
+    }
+}
+
+// A row with a box that is ticked or not and a label
+pub struct Checkbox<'a>
+{
+    pub rect: [f64; 4],
+    pub label: &'a str,
+    pub checked: bool,
+}
+
+impl<'a> Checkbox<'a>
+{
+    pub fn new(rect: [f64; 4], label: &'a str, checked: bool) -> Checkbox<'a>
+    {
+        Checkbox{rect, label, checked}
+    }
+
+    // Whether it was clicked, i.e. should flip
+    pub fn toggled(&self, ui: &UiInput) -> bool
+    {
+        ui.clicked && ui.hovers(self.rect)
+    }
+
+    pub fn draw<G: Graphics, C: CharacterCache<Texture = G::Texture>>(&self, view: &ChessView,
+        ui: &UiInput, glyph: &mut C, c: &Context, g: &mut G)
+    {
+        let settings: &ChessViewSettings = &view.settings;
+        let [x, y, _, h] = self.rect;
+        let row_col: Color = if ui.hovers(self.rect) { settings._button_hover_col } else { settings._lobby_row_col };
+        Rectangle::new(row_col).draw(self.rect, &c.draw_state, c.transform, g);
+
+        let side: f64 = h * 0.6;
+        let tick_box: [f64; 4] = [x + ROW_PADDING, y + (h - side) / 2.0, side, side];
+        Rectangle::new([1.0; 4]).draw(tick_box, &c.draw_state, c.transform, g);
+        if self.checked
+        {
+            let inset: f64 = side * 0.2;
+            Rectangle::new(settings._intro_button_col).draw(
+                [tick_box[0] + inset, tick_box[1] + inset, side - 2.0*inset, side - 2.0*inset],
+                &c.draw_state, c.transform, g);
+        }
+
+        let label_rect: [f64; 4] = [tick_box[0] + side, y, self.rect[2] - side - ROW_PADDING, h];
+        draw_label(view, self.label, ROW_FONT_SIZE, [1.0; 4], label_rect, false, glyph, c, g);
+    }
+}
+
+// A row showing which of `options` is chosen, each click picks the next one
+pub struct Choice<'a>
+{
+    pub rect: [f64; 4],
+    pub label: &'a str,
+    pub options: &'a [&'a str],
+    pub chosen: usize,
+}
+
+impl<'a> Choice<'a>
+{
+    pub fn new(rect: [f64; 4], label: &'a str, options: &'a [&'a str], chosen: usize) -> Choice<'a>
+    {
+        Choice{rect, label, options, chosen}
+    }
+
+    // The option picked by a click on it
+    pub fn picked(&self, ui: &UiInput) -> Option<usize>
+    {
+        if !ui.clicked || !ui.hovers(self.rect) || self.options.is_empty() { return None; }
+        Some((self.chosen + 1) % self.options.len())
+    }
+
+    pub fn draw<G: Graphics, C: CharacterCache<Texture = G::Texture>>(&self, view: &ChessView,
+        ui: &UiInput, glyph: &mut C, c: &Context, g: &mut G)
+    {
+        let settings: &ChessViewSettings = &view.settings;
+        let row_col: Color = if ui.hovers(self.rect) { settings._button_hover_col } else { settings._lobby_row_col };
+        Rectangle::new(row_col).draw(self.rect, &c.draw_state, c.transform, g);
+
+        let option: &str = self.options.get(self.chosen).copied().unwrap_or("");
+        let label_rect: [f64; 4] = [self.rect[0] + ROW_PADDING, self.rect[1], self.rect[2] - 2.0*ROW_PADDING, self.rect[3]];
+        draw_label(view, &format!("{}: {}", self.label, option), ROW_FONT_SIZE, [1.0; 4], label_rect, false,
+            glyph, c, g);
+    }
+}
+
+// A row with a label on the left half and a track on the right half, the
+// knob follows the mouse while the button is held
+pub struct Slider<'a>
+{
+    pub rect: [f64; 4],
+    pub label: &'a str,
+    pub min: f64,
+    pub max: f64,
+    pub value: f64,
+}
+
+impl<'a> Slider<'a>
+{
+    pub fn new(rect: [f64; 4], label: &'a str, min: f64, max: f64, value: f64) -> Slider<'a>
+    {
+        Slider{rect, label, min, max, value}
+    }
+
+    // The value under the mouse while the slider is dragged
+    pub fn dragged(&self, ui: &UiInput) -> Option<f64>
+    {
+        if !ui.mouse_down || !ui.drags(self.rect) { return None; }
+
+        let track: [f64; 4] = self._track();
+        let t: f64 = ((ui.cursor[0] - track[0]) / track[2]).clamp(0.0, 1.0);
+        Some(self.min + t * (self.max - self.min))
+    }
+
+    // Dragging it just ended, e.g. to save the value
+    pub fn released(&self, ui: &UiInput) -> bool
+    {
+        ui.released && ui.drags(self.rect)
+    }
+
+    pub fn draw<G: Graphics, C: CharacterCache<Texture = G::Texture>>(&self, view: &ChessView,
+        ui: &UiInput, glyph: &mut C, c: &Context, g: &mut G)
+    {
+        let settings: &ChessViewSettings = &view.settings;
+        Rectangle::new(settings._lobby_row_col).draw(self.rect, &c.draw_state, c.transform, g);
+
+        let label_rect: [f64; 4] = [self.rect[0], self.rect[1], self.rect[2] / 2.0, self.rect[3]];
+        draw_label(view, self.label, ROW_FONT_SIZE, [1.0; 4], label_rect, false, glyph, c, g);
+
+        let track: [f64; 4] = self._track();
+        Rectangle::new([1.0; 4]).draw(track, &c.draw_state, c.transform, g);
+
+        let t: f64 = if self.max > self.min { ((self.value - self.min) / (self.max - self.min)).clamp(0.0, 1.0) } else { 0.0 };
+        let knob_w: f64 = 12.0;
+        let knob_h: f64 = self.rect[3] * 0.6;
+        let knob_col: Color = if ui.mouse_down && ui.drags(self.rect) { settings._button_pressed_col }
+                              else if ui.hovers(self.rect) { settings._button_hover_col }
+                              else { settings._intro_button_col };
+        Rectangle::new(knob_col).draw(
+            [track[0] + t*track[2] - knob_w/2.0, self.rect[1] + (self.rect[3] - knob_h) / 2.0, knob_w, knob_h],
+            &c.draw_state, c.transform, g);
+    }
+
+    fn _track(&self) -> [f64; 4]
+    {
+        let [x, y, w, h] = self.rect;
+        [x + w/2.0 + ROW_PADDING, y + h/2.0 - 2.0, w/2.0 - 2.0*ROW_PADDING, 4.0]
+    }
+}
+
+// One line of typed text, focused by clicking on it
+pub struct TextField
+{
+    pub value: String,
+    pub focused: bool,
+    max_len: usize,
+}
+
+impl TextField
+{
+    pub fn new(max_len: usize) -> TextField
+    {
+        TextField{value: String::new(), focused: false, max_len}
+    }
+
+    // Returns true when Enter was pressed in it
+    pub fn handle(&mut self, rect: [f64; 4], ui: &UiInput) -> bool
+    {
+        if ui.clicked { self.focused = ui.hovers(rect); }
+        if !self.focused { return false; }
+
+        if let Some(text) = &ui.text
+        {
+            for ch in text.chars().filter(|ch| !ch.is_control())
+            {
+                if self.value.chars().count() < self.max_len { self.value.push(ch); }
+            }
+        }
+
+        match ui.key
+        {
+            Some(Key::Backspace) => { self.value.pop(); false },
+            Some(Key::Return) => true,
+            _ => false
+        }
+    }
+
+    // `placeholder` is shown greyed out while it's empty
+    pub fn draw<G: Graphics, C: CharacterCache<Texture = G::Texture>>(&self, rect: [f64; 4],
+        placeholder: &str, view: &ChessView, glyph: &mut C, c: &Context, g: &mut G)
+    {
+        let settings: &ChessViewSettings = &view.settings;
+        Rectangle::new(settings._chat_input_col).draw(rect, &c.draw_state, c.transform, g);
+        if self.focused
+        {
+            Rectangle::new_border(settings._intro_button_col, 1.5).draw(rect, &c.draw_state, c.transform, g);
+        }
+
+        let text_rect: [f64; 4] = [rect[0] + ROW_PADDING, rect[1], rect[2] - 2.0*ROW_PADDING, rect[3]];
+        if self.value.is_empty() && !self.focused
+        {
+            let [r, g_, b, a] = settings._chat_text_col;
+            draw_label(view, placeholder, ROW_FONT_SIZE, [r, g_, b, a * 0.5], text_rect, false, glyph, c, g);
+        }
+        else
+        {
+            let caret: &str = if self.focused { "_" } else { "" };
+            draw_label(view, &format!("{}{}", self.value, caret), ROW_FONT_SIZE, settings._chat_text_col,
+                text_rect, false, glyph, c, g);
+        }
+    }
+}
+
+// Rows laid out like the lobby ones, scrolled with the mouse wheel when
+// they don't all fit in the list
+#[derive(Default)]
+pub struct ScrollList
+{
+    // Index of the topmost row shown
+    first: usize,
+}
+
+impl ScrollList
+{
+    pub fn new() -> ScrollList
+    {
+        ScrollList{first: 0}
+    }
+
+    // Scrolls, and returns the row clicked if any
+    pub fn handle(&mut self, rect: [f64; 4], rows: usize, settings: &ChessViewSettings, ui: &UiInput) -> Option<usize>
+    {
+        if ui.scroll != 0.0 && ui.hovers(rect)
+        {
+            let first: i64 = self.first as i64 - ui.scroll.signum() as i64;
+            self.first = first.max(0) as usize;
+        }
+        self.first = self._first(rect, rows, settings);
+
+        if !ui.clicked { return None; }
+        (self.first..rows).zip(0..ScrollList::_visible(rect, settings))
+            .find(|(_, shown)| ui.hovers(ScrollList::_row_rect(rect, *shown, settings)))
+            .map(|(row, _)| row)
+    }
+
+    // `rows` are the labels, the highlighted ones are drawn in the button color
+    pub fn draw<G: Graphics, C: CharacterCache<Texture = G::Texture>>(&self, rect: [f64; 4],
+        rows: &[(String, bool)], view: &ChessView, glyph: &mut C, c: &Context, g: &mut G)
+    {
+        let settings: &ChessViewSettings = &view.settings;
+        let visible: usize = ScrollList::_visible(rect, settings);
+        let first: usize = self._first(rect, rows.len(), settings);
+
+        for (shown, (label, highlighted)) in rows.iter().skip(first).take(visible).enumerate()
+        {
+            let row_rect: [f64; 4] = ScrollList::_row_rect(rect, shown, settings);
+            let row_col: Color = if *highlighted { settings._intro_button_col } else { settings._lobby_row_col };
+            Rectangle::new(row_col).draw(row_rect, &c.draw_state, c.transform, g);
+
+            let label_rect: [f64; 4] = [row_rect[0] + ROW_PADDING, row_rect[1], row_rect[2] - 2.0*ROW_PADDING, row_rect[3]];
+            draw_label(view, label, ROW_FONT_SIZE, [1.0; 4], label_rect, false, glyph, c, g);
+        }
+
+        // How much of the list is shown and where
+        if rows.len() > visible && visible > 0
+        {
+            let height: f64 = rect[3] * visible as f64 / rows.len() as f64;
+            let top: f64 = rect[1] + rect[3] * first as f64 / rows.len() as f64;
+            Rectangle::new(settings._intro_button_col).draw(
+                [rect[0] + rect[2] + SCROLLBAR_WIDTH, top, SCROLLBAR_WIDTH, height],
+                &c.draw_state, c.transform, g);
+        }
+    }
+
+    // The first row, kept so that the list never scrolls past its end
+    fn _first(&self, rect: [f64; 4], rows: usize, settings: &ChessViewSettings) -> usize
+    {
+        self.first.min(rows.saturating_sub(ScrollList::_visible(rect, settings)))
+    }
+
+    fn _visible(rect: [f64; 4], settings: &ChessViewSettings) -> usize
+    {
+        let step: f64 = settings._lobby_row_size[1] + settings._lobby_row_gap;
+        ((rect[3] + settings._lobby_row_gap) / step).floor().max(0.0) as usize
+    }
+
+    fn _row_rect(rect: [f64; 4], shown: usize, settings: &ChessViewSettings) -> [f64; 4]
+    {
+        let step: f64 = settings._lobby_row_size[1] + settings._lobby_row_gap;
+        [rect[0], rect[1] + shown as f64 * step, rect[2], settings._lobby_row_size[1]]
+    }
+}
+
+// Draws `text` centered vertically in `rect`, and horizontally if
+// `centered`, otherwise from its left edge. Measured with the font, so it
+// fits any label and font size
+#[allow(clippy::too_many_arguments)]
+pub fn draw_label<G: Graphics, C: CharacterCache<Texture = G::Texture>>(view: &ChessView, text: &str,
+    font_size: u32, color: Color, rect: [f64; 4], centered: bool, glyph: &mut C, c: &Context, g: &mut G)
+{
+    let pixel_scale: f64 = view.layout.pixel_scale();
+    let pixel_size: u32 = ((font_size as f64 * pixel_scale).round() as u32).max(1);
+
+    // Height of the capital letters above the baseline
+    let cap_height: f64 = glyph.character(pixel_size, 'H')
+        .map(|character| character.top())
+        .unwrap_or(pixel_size as f64 * 0.7) / pixel_scale;
+
+    let x: f64 = if centered { rect[0] + (rect[2] - view.text_width(glyph, font_size, text)) / 2.0 }
+                 else { rect[0] };
+    view.draw_text(Text::new_color(color, font_size), text, [x, rect[1] + (rect[3] + cap_height) / 2.0],
+        glyph, c, g);
+}
+
+fn _inside(pos: [f64; 2], rect: [f64; 4]) -> bool
+{
+    pos[0] >= rect[0] && pos[0] <= rect[0]+rect[2] && pos[1] >= rect[1] && pos[1] <= rect[1]+rect[3]
+}